@@ -0,0 +1,104 @@
+-- 基线: 合并了之前 db.sql 中的建表语句和所有的 alter table.
+-- 每条语句都可以重复执行, 已经按 db.sql 建好的数据库执行后补齐缺少的字段和索引.
+-- 索引名与 postgres 自动生成的名字一致, 避免在旧的数据库上重复建索引.
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE TABLE IF NOT EXISTS "admin" (
+                         "id" SERIAL PRIMARY KEY,
+                         "username" varchar(50) UNIQUE NOT NULL,
+                         "password" varchar(255) NOT NULL,
+                         "is_sys" boolean NOT NULL DEFAULT FALSE,
+                         "is_del" boolean NOT NULL DEFAULT FALSE
+);
+
+-- 升级前已有的非系统账号按最小权限成为查看(viewer), 由管理员再分配角色
+ALTER TABLE "admin" ADD COLUMN IF NOT EXISTS "role" varchar(20) NOT NULL DEFAULT 'viewer';
+ALTER TABLE "admin" ALTER COLUMN "role" SET DEFAULT 'viewer';
+UPDATE "admin" SET "role" = 'administrator' WHERE "is_sys" = TRUE AND "role" <> 'administrator';
+ALTER TABLE "admin" ADD COLUMN IF NOT EXISTS "must_change_password" boolean NOT NULL DEFAULT FALSE;
+ALTER TABLE "admin" ADD COLUMN IF NOT EXISTS "is_disabled" boolean NOT NULL DEFAULT FALSE;
+ALTER TABLE "admin" ADD COLUMN IF NOT EXISTS "totp_secret" varchar(64);
+ALTER TABLE "admin" ADD COLUMN IF NOT EXISTS "totp_enabled" boolean NOT NULL DEFAULT FALSE;
+ALTER TABLE "admin" ADD COLUMN IF NOT EXISTS "totp_last_step" bigint NOT NULL DEFAULT 0;
+-- 管理员记住的每页记录数
+ALTER TABLE "admin" ADD COLUMN IF NOT EXISTS "page_size" integer;
+
+-- 管理员限定的类目(药箱), 没有记录表示不限制
+CREATE TABLE IF NOT EXISTS "admin_scope" (
+                               "admin_id" integer NOT NULL REFERENCES "admin" ("id"),
+                               "category" varchar NOT NULL,
+                               PRIMARY KEY ("admin_id", "category")
+);
+
+CREATE TABLE IF NOT EXISTS "medicinal" (
+                             "id" SERIAL PRIMARY KEY,
+                             "category" varchar NOT NULL,
+                             "name" varchar NOT NULL,
+                             "batch_number" varchar,
+                             "count" varchar,
+                             "validity" date NOT NULL,
+                             is_del BOOLEAN NOT NULL DEFAULT FALSE,
+                             "notify_at" TIMESTAMPTZ NOT NULL DEFAULT (now()),
+                             "created_at" date DEFAULT (now())
+);
+
+ALTER TABLE "medicinal" ADD COLUMN IF NOT EXISTS "spec" varchar NOT NULL DEFAULT 'Empty';
+-- 名称及类目的拼音全拼和首字母, 由程序写入, 用于按拼音搜索, 旧数据在程序启动时补全
+ALTER TABLE "medicinal" ADD COLUMN IF NOT EXISTS "name_pinyin" varchar;
+ALTER TABLE "medicinal" ADD COLUMN IF NOT EXISTS "name_initials" varchar;
+ALTER TABLE "medicinal" ADD COLUMN IF NOT EXISTS "category_pinyin" varchar;
+ALTER TABLE "medicinal" ADD COLUMN IF NOT EXISTS "category_initials" varchar;
+
+CREATE INDEX IF NOT EXISTS "medicinal_name_idx" ON "medicinal" ("name");
+CREATE INDEX IF NOT EXISTS "medicinal_validity_idx" ON "medicinal" ("validity");
+CREATE UNIQUE INDEX IF NOT EXISTS "medicinal_id_idx" ON "medicinal" ("id");
+-- 按 (有效期, ID) 游标翻页
+CREATE INDEX IF NOT EXISTS "medicinal_validity_id_idx" ON "medicinal" ("validity", "id");
+
+-- 模糊搜索使用 pg_trgm 的三元组索引
+CREATE INDEX IF NOT EXISTS "medicinal_name_idx1" ON "medicinal" USING gin ("name" gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS "medicinal_batch_number_idx" ON "medicinal" USING gin ("batch_number" gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS "medicinal_spec_idx" ON "medicinal" USING gin ("spec" gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS "medicinal_category_idx" ON "medicinal" USING gin ("category" gin_trgm_ops);
+
+CREATE TABLE IF NOT EXISTS "api_token" (
+                             "id" SERIAL PRIMARY KEY,
+                             "admin_id" integer NOT NULL REFERENCES "admin" ("id"),
+                             "name" varchar(100) NOT NULL,
+                             "token_hash" char(64) UNIQUE NOT NULL,
+                             "scope" varchar(10) NOT NULL DEFAULT 'read',
+                             "expires_at" TIMESTAMPTZ,
+                             "last_used_at" TIMESTAMPTZ,
+                             "is_revoked" boolean NOT NULL DEFAULT FALSE,
+                             "created_at" TIMESTAMPTZ NOT NULL DEFAULT (now())
+);
+
+CREATE INDEX IF NOT EXISTS "api_token_admin_id_idx" ON "api_token" ("admin_id");
+
+-- 二步验证的恢复码, 只保存 sha256 摘要, 每个只能使用一次
+CREATE TABLE IF NOT EXISTS "admin_recovery_code" (
+                                       "id" SERIAL PRIMARY KEY,
+                                       "admin_id" integer NOT NULL REFERENCES "admin" ("id"),
+                                       "code_hash" char(64) NOT NULL,
+                                       "used_at" TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS "admin_recovery_code_admin_id_idx" ON "admin_recovery_code" ("admin_id");
+
+-- 角色的设置, 没有记录的角色使用默认值
+CREATE TABLE IF NOT EXISTS "role_setting" (
+                                "role" varchar(20) PRIMARY KEY,
+                                "require_2fa" boolean NOT NULL DEFAULT FALSE
+);
+
+-- 登录失败的记录, 用于排查暴力破解
+CREATE TABLE IF NOT EXISTS "login_event" (
+                               "id" SERIAL PRIMARY KEY,
+                               "username" varchar(50) NOT NULL,
+                               "ip" varchar(64) NOT NULL,
+                               "reason" varchar(100) NOT NULL,
+                               "created_at" TIMESTAMPTZ NOT NULL DEFAULT (now())
+);
+
+CREATE INDEX IF NOT EXISTS "login_event_created_at_idx" ON "login_event" ("created_at");
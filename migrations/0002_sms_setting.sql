@@ -0,0 +1,10 @@
+-- 短信的密钥、签名、模板和接收号码, 只有一行, 为空的字段使用配置中的值
+CREATE TABLE IF NOT EXISTS "sms_setting" (
+                               "id" integer PRIMARY KEY DEFAULT 1 CHECK ("id" = 1),
+                               "access_key_id" varchar(100) NOT NULL DEFAULT '',
+                               "access_key_secret" varchar(100) NOT NULL DEFAULT '',
+                               "sign_name" varchar(50) NOT NULL DEFAULT '',
+                               "template_code" varchar(50) NOT NULL DEFAULT '',
+                               "phones" varchar(20)[] NOT NULL DEFAULT '{}',
+                               "updated_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
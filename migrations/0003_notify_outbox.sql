@@ -0,0 +1,19 @@
+-- 通知发件箱, 每个渠道一行, 发送前先写入, 失败时按退避时间重试
+-- status: pending 等待发送, sending 正在发送, sent 已发送, failed 重试之后仍然失败
+CREATE TABLE IF NOT EXISTS "notify_outbox" (
+                               "id" serial PRIMARY KEY,
+                               "channel" varchar(50) NOT NULL,
+                               "channel_name" varchar(50) NOT NULL,
+                               "recipients" text NOT NULL DEFAULT '',
+                               "kind" varchar(20) NOT NULL,
+                               "title" varchar(200) NOT NULL,
+                               "summary" text NOT NULL,
+                               "payload" text NOT NULL,
+                               "status" varchar(20) NOT NULL DEFAULT 'pending',
+                               "attempts" integer NOT NULL DEFAULT 0,
+                               "next_attempt_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                               "response" text NOT NULL DEFAULT '',
+                               "created_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                               "sent_at" TIMESTAMPTZ
+);
+CREATE INDEX IF NOT EXISTS "notify_outbox_status_next_attempt_at" ON "notify_outbox" ("status", "next_attempt_at");
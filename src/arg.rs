@@ -1,13 +1,32 @@
+use crate::error::AppError;
+use crate::Result;
+use chrono::NaiveDate;
 use serde::Deserialize;
+use utoipa::IntoParams;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct MedicinalBackendQueryArg {
+    /// 页码, 从 0 开始
     pub page: Option<u32>,
+    /// 药品名称关键字
     pub keyword: Option<String>,
+    /// 提示信息, 仅后台页面使用
     pub msg: Option<String>,
+    /// 是否查询已删除的药品
     pub is_del: Option<bool>,
+    /// 过期查询: 0 所有, 1 已过期, n(>1) 表示 (n-1)*30 天内过期
     pub expired: Option<u8>,
+    /// 药品类目(药箱)
     pub category: Option<String>, // 用于查询分类
+    /// 排序字段: `name`, `category`, `batch_number`, `validity`, `created_at`, `count`, 默认 `validity`
+    pub order: Option<String>,
+    /// 是否倒序
+    pub desc: Option<bool>,
+    /// 每页的记录数, 限制在配置的范围内, 指定后会记住作为该管理员的默认值
+    pub page_size: Option<u32>,
+    /// 按 (有效期, ID) 翻页的游标, 为空字符串表示第一页; 指定游标时忽略页码和排序参数
+    pub cursor: Option<String>,
 }
 
 impl MedicinalBackendQueryArg {
@@ -50,31 +69,107 @@ impl MedicinalBackendQueryArg {
         }
     }
 
-    // 排序方式
-    // 默认以有效期排序
-    // 可以以类目排序
-    // 刚 upload之后以 create_at 排序
-    // pub fn order(&self) -> String {
-    //     match &self.order {
-    //         Some(s) => s.to_string(),
-    //         None => "validity".to_string(),
-    //     }
-    // }
+    /// 排序字段, 不认识的字段按有效期排序
+    pub fn order(&self) -> OrderKey {
+        match &self.order {
+            Some(s) => OrderKey::from(s.as_str()),
+            None => OrderKey::Validity,
+        }
+    }
+
+    pub fn desc(&self) -> bool {
+        match &self.desc {
+            Some(b) => *b,
+            None => false,
+        }
+    }
+
+    /// 游标: `None` 表示按页码翻页, `Some(None)` 表示按游标翻页的第一页, 格式错误时返回错误
+    pub fn cursor(&self) -> Result<Option<Option<Cursor>>> {
+        match self.cursor.as_deref() {
+            None => Ok(None),
+            Some("") => Ok(Some(None)),
+            Some(s) => Cursor::parse(s).map(|c| Some(Some(c))),
+        }
+    }
+
+    /// 有关键字并且没有指定排序字段时按相关度排序
+    pub fn is_ranked(&self) -> bool {
+        !self.keyword().is_empty() && self.order.is_none()
+    }
+
+    /// 除页码外的查询参数, 用于生成分页链接
+    pub fn query_string(&self) -> String {
+        let order = if self.is_ranked() {
+            None
+        } else {
+            Some(self.order())
+        };
+        self.query_with_order(order, self.desc())
+    }
+
+    /// 点击表头的排序链接: 当前已按该字段排序时切换方向, 否则按升序
+    pub fn sort_query(&self, key: &str) -> String {
+        let key = OrderKey::from(key);
+        let desc = !self.is_ranked() && key == self.order() && !self.desc();
+        self.query_with_order(Some(key), desc)
+    }
+
+    /// 表头的排序图标
+    pub fn sort_icon(&self, key: &str) -> &'static str {
+        let sorted = !self.is_ranked() && OrderKey::from(key) == self.order();
+        match (sorted, self.desc()) {
+            (false, _) => "fa-sort",
+            (true, false) => "fa-sort-up",
+            (true, true) => "fa-sort-down",
+        }
+    }
+
+    fn query_with_order(&self, order: Option<OrderKey>, desc: bool) -> String {
+        let mut pairs = vec![
+            ("keyword", self.keyword().to_string()),
+            ("is_del", self.is_del().to_string()),
+            ("expired", self.expired().to_string()),
+            ("category", self.category().to_string()),
+        ];
+        if let Some(order) = order {
+            pairs.push(("order", order.as_str().to_string()));
+            pairs.push(("desc", desc.to_string()));
+        }
+        encode_query(&pairs)
+    }
 }
 
-#[derive(Deserialize, Debug)]
+/// 药品列表允许排序的字段
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
 pub enum OrderKey {
-    Validity,
+    Name,
     Category,
+    BatchNumber,
+    Validity,
     CreatedAt,
+    Count,
 }
 
 impl OrderKey {
-    pub fn to_string(&self) -> String {
+    /// 查询参数中的值
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderKey::Name => "name",
+            OrderKey::Category => "category",
+            OrderKey::BatchNumber => "batch_number",
+            OrderKey::Validity => "validity",
+            OrderKey::CreatedAt => "created_at",
+            OrderKey::Count => "count",
+        }
+    }
+
+    /// 对应的排序表达式, 数量是文本字段, 取开头的数字按数值排序
+    pub fn column(&self) -> &'static str {
         match self {
-            OrderKey::Validity => "validity".to_string(),
-            OrderKey::Category => "category".to_string(),
-            OrderKey::CreatedAt => "created_at".to_string(),
+            OrderKey::Count => "substring(count FROM '[0-9]+[.]?[0-9]*')::numeric",
+            key => key.as_str(),
         }
     }
 }
@@ -82,20 +177,59 @@ impl OrderKey {
 impl From<&str> for OrderKey {
     fn from(s: &str) -> Self {
         match s {
-            "validity" => OrderKey::Validity,
+            "name" => OrderKey::Name,
             "category" => OrderKey::Category,
+            "batch_number" => OrderKey::BatchNumber,
+            "validity" => OrderKey::Validity,
             "created_at" => OrderKey::CreatedAt,
+            "count" => OrderKey::Count,
             _ => OrderKey::Validity,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// 按 (有效期, ID) 翻页的游标, 格式为 `有效期_ID`, 如 `2024-05-01_42`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub validity: NaiveDate,
+    pub id: i32,
+}
+
+impl Cursor {
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || AppError::invalid_argument("游标格式错误");
+        let (validity, id) = s.split_once('_').ok_or_else(invalid)?;
+        Ok(Self {
+            validity: NaiveDate::parse_from_str(validity, "%Y-%m-%d").map_err(|_| invalid())?,
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}_{}", self.validity.format("%Y-%m-%d"), self.id)
+    }
+}
+
+/// 把查询参数编码成 `a=1&b=2` 的形式
+fn encode_query(pairs: &[(&str, String)]) -> String {
+    serde_urlencoded::to_string(pairs).unwrap_or_default()
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct BackendQueryArg {
+    /// 页码, 从 0 开始
     pub page: Option<u32>,
+    /// 关键字
     pub keyword: Option<String>,
+    /// 提示信息, 仅后台页面使用
     pub msg: Option<String>,
+    /// 是否查询已删除的数据
     pub is_del: Option<bool>,
+    /// 每页的记录数, 限制在配置的范围内, 指定后会记住作为该管理员的默认值
+    pub page_size: Option<u32>,
 }
 
 impl BackendQueryArg {
@@ -119,4 +253,121 @@ impl BackendQueryArg {
             None => false,
         }
     }
+
+    /// 除页码外的查询参数, 用于生成分页链接
+    pub fn query_string(&self) -> String {
+        encode_query(&[
+            ("keyword", self.keyword().to_string()),
+            ("is_del", self.is_del().to_string()),
+        ])
+    }
+}
+
+/// 通知发送记录的查询参数
+#[derive(Debug, Deserialize)]
+pub struct OutboxQueryArg {
+    /// 页码, 从 0 开始
+    pub page: Option<u32>,
+    /// 接收人或标题的关键字
+    pub keyword: Option<String>,
+    /// 状态: pending, sending, sent, failed, 为空表示所有
+    pub status: Option<String>,
+    /// 提示信息
+    pub msg: Option<String>,
+    /// 每页的记录数
+    pub page_size: Option<u32>,
+}
+
+impl OutboxQueryArg {
+    pub fn page(&self) -> u32 {
+        self.page.unwrap_or(0)
+    }
+
+    pub fn keyword(&self) -> &str {
+        self.keyword.as_deref().unwrap_or("")
+    }
+
+    pub fn status(&self) -> &str {
+        self.status.as_deref().unwrap_or("")
+    }
+
+    /// 除页码外的查询参数, 用于生成分页链接
+    pub fn query_string(&self) -> String {
+        encode_query(&[
+            ("keyword", self.keyword().to_string()),
+            ("status", self.status().to_string()),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn medicinal_arg(order: Option<&str>, desc: Option<bool>) -> MedicinalBackendQueryArg {
+        MedicinalBackendQueryArg {
+            page: Some(2),
+            keyword: Some("维生素 C&D".to_string()),
+            msg: None,
+            is_del: None,
+            expired: None,
+            category: None,
+            order: order.map(|s| s.to_string()),
+            desc,
+            page_size: None,
+            cursor: None,
+        }
+    }
+
+    #[test]
+    fn test_order_key() {
+        assert_eq!(medicinal_arg(None, None).order(), OrderKey::Validity);
+        assert_eq!(medicinal_arg(Some("count"), None).order(), OrderKey::Count);
+        // 不在白名单中的字段按有效期排序
+        let arg = medicinal_arg(Some("id; DROP TABLE medicinal"), None);
+        assert_eq!(arg.order(), OrderKey::Validity);
+        assert_eq!(OrderKey::BatchNumber.column(), "batch_number");
+    }
+
+    #[test]
+    fn test_query_string() {
+        let arg = medicinal_arg(Some("name"), Some(true));
+        assert_eq!(
+            arg.query_string(),
+            "keyword=%E7%BB%B4%E7%94%9F%E7%B4%A0+C%26D&is_del=false&expired=0&category=&order=name&desc=true"
+        );
+        // 再次点击当前排序的字段切换方向, 点击其它字段按升序
+        assert!(arg.sort_query("name").ends_with("order=name&desc=false"));
+        assert!(arg
+            .sort_query("validity")
+            .ends_with("order=validity&desc=false"));
+        assert_eq!(arg.sort_icon("name"), "fa-sort-down");
+        assert_eq!(arg.sort_icon("validity"), "fa-sort");
+
+        // 有关键字没有排序字段时按相关度排序, 翻页时不带排序字段
+        let arg = medicinal_arg(None, None);
+        assert!(arg.is_ranked());
+        assert!(!arg.query_string().contains("order="));
+        assert_eq!(arg.sort_icon("validity"), "fa-sort");
+        assert!(arg
+            .sort_query("validity")
+            .ends_with("order=validity&desc=false"));
+    }
+
+    #[test]
+    fn test_cursor() {
+        let cursor = Cursor::parse("2024-05-01_42").unwrap();
+        assert_eq!(cursor.id, 42);
+        assert_eq!(cursor.to_string(), "2024-05-01_42");
+        assert!(Cursor::parse("2024-05-01").is_err());
+        assert!(Cursor::parse("2024-13-01_42").is_err());
+        assert!(Cursor::parse("2024-05-01_x").is_err());
+
+        let mut arg = medicinal_arg(None, None);
+        assert_eq!(arg.cursor().unwrap(), None);
+        arg.cursor = Some("".to_string());
+        assert_eq!(arg.cursor().unwrap(), Some(None));
+        arg.cursor = Some("2024-05-01_42".to_string());
+        assert_eq!(arg.cursor().unwrap(), Some(Some(cursor)));
+    }
 }
@@ -1,21 +1,56 @@
 pub mod redisdb;
 pub mod sleddb;
 
-use crate::error::AppError;
+use crate::cache::redisdb::RedisDB;
+use crate::cache::sleddb::SledDb;
+use crate::config::{CacheConfig, RedisConfig};
+use crate::error::{AppError, AppErrorType};
+use axum::async_trait;
 use serde::{Deserialize, Serialize};
+use tracing::info;
 
 use crate::Result;
 
 /// 对缓存的抽象,我们不关心数据存在哪儿,但需要定义外界如何在存储打交道
+///
+/// 有两种实现: redis 和嵌入式的 sled, 在配置 `CACHE.BACKEND` 中选择
+#[async_trait]
 pub trait Cache: Send + Sync + 'static {
-    fn get(&self, key: &str) -> Result<Option<StrValue>>;
+    async fn get(&self, key: &str) -> Result<Option<StrValue>>;
     // 设置一个key的value,返回旧的value
-    fn set(&self, key: String, value: StrValue) -> Result<Option<StrValue>>;
+    async fn set(&self, key: String, value: StrValue) -> Result<Option<StrValue>>;
     // 删除key值,返回旧的value
-    fn del(&self, key: &str) -> Result<Option<StrValue>>;
+    async fn del(&self, key: &str) -> Result<Option<StrValue>>;
 
     // 是否存在
-    fn is_exists(&self, key: &str) -> Result<bool>;
+    async fn is_exists(&self, key: &str) -> Result<bool>;
+
+    /// 计数加一, 第一次计数时设置有效期, 返回加一后的值
+    async fn incr(&self, key: &str, sec: usize) -> Result<i64>;
+
+    /// 剩余的有效期, 单位秒; 没有有效期时返回 -1, 不存在时返回 -2
+    async fn ttl(&self, key: &str) -> Result<i64>;
+
+    /// 向集合中添加成员, 并重新设置集合的有效期
+    async fn sadd(&self, key: &str, member: &str, sec: usize) -> Result<()>;
+
+    /// 集合中的所有成员
+    async fn smembers(&self, key: &str) -> Result<Vec<String>>;
+
+    /// 从集合中删除成员
+    async fn srem(&self, key: &str, member: &str) -> Result<()>;
+
+    /// 写入字符串并设置有效期, 单位秒
+    async fn set_ex(&self, key: &str, value: &str, sec: usize) -> Result<()> {
+        self.set(key.to_string(), StrValue::with_ttl(value, sec))
+            .await
+            .map(|_| ())
+    }
+
+    /// 读取字符串
+    async fn get_str(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.get(key).await?.map(|v| v.value))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -25,9 +60,30 @@ pub struct StrValue {
 }
 
 impl StrValue {
+    /// 从现在开始 `sec` 秒后过期
+    pub fn with_ttl(value: &str, sec: usize) -> Self {
+        let mut v = StrValue::from(value);
+        v.set_expired(now() + sec as i64);
+        v
+    }
+
     pub fn set_expired(&mut self, expired: i64) {
         self.expired = Some(expired);
     }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// 在 `now` 这个时间点是否已经过期
+    pub fn is_expired(&self, now: i64) -> bool {
+        matches!(self.expired, Some(expired) if expired <= now)
+    }
+
+    /// 剩余的有效期, 没有有效期时返回 None
+    pub fn ttl(&self, now: i64) -> Option<i64> {
+        self.expired.map(|expired| (expired - now).max(0))
+    }
 }
 
 impl From<&str> for StrValue {
@@ -48,43 +104,130 @@ impl From<String> for StrValue {
     }
 }
 
+/// 当前的 unix 时间戳
+fn now() -> i64 {
+    chrono::Local::now().timestamp()
+}
+
+/// 根据配置创建缓存, sled 会同时启动清理过期数据的后台任务
+pub async fn from_config(
+    cfg: &CacheConfig,
+    redis_cfg: Option<&RedisConfig>,
+) -> Result<Box<dyn Cache>> {
+    match cfg.backend.as_str() {
+        "redis" => {
+            let redis_cfg = redis_cfg.ok_or_else(|| {
+                AppError::from_str("使用 redis 缓存时必须配置 REDIS.DSN", AppErrorType::Common)
+            })?;
+            let client = redis::Client::open(redis_cfg.dsn.as_str())?;
+            info!("cache backend: redis");
+            Ok(Box::new(RedisDB::new(client).await?))
+        }
+        "sled" => {
+            let db = SledDb::open(&cfg.sled_path)?;
+            tokio::spawn(db.clone().purge_schedule(cfg.purge_interval));
+            info!("cache backend: sled, path: {}", &cfg.sled_path);
+            Ok(Box::new(db))
+        }
+        backend => Err(AppError::from_str(
+            &format!("不支持的缓存类型: {}, 可选 redis 或 sled", backend),
+            AppErrorType::Common,
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::cache::sleddb::SledDb;
 
-    #[test]
-    fn sleddb_basic_interface_should_work() {
+    #[tokio::test]
+    async fn sleddb_basic_interface_should_work() {
         let cache = SledDb::new("./test_sleddb");
-        test_basic_interface(cache);
+        test_basic_interface(cache).await;
     }
 
-    fn test_basic_interface(cache: impl Cache) {
+    #[tokio::test]
+    async fn sleddb_expired_should_work() {
+        let cache = SledDb::new("./test_sleddb_expired");
+        test_expired(cache).await;
+    }
+
+    #[tokio::test]
+    async fn sleddb_counter_and_set_should_work() {
+        let cache = SledDb::new("./test_sleddb_counter");
+        test_counter_and_set(cache).await;
+    }
+
+    async fn test_basic_interface(cache: impl Cache) {
         // 第一次set值
-        let v1 = cache.set("k1".into(), "v1".into());
+        let v1 = cache.set("k1".into(), "v1".into()).await;
         assert!(v1.unwrap().is_none());
 
         // 第二次set值,会返回旧的值
-        let v2 = cache.set("k1".into(), "v2".into());
+        let v2 = cache.set("k1".into(), "v2".into()).await;
         assert_eq!(v2.unwrap(), Some("v1".into()));
 
         // get 存在的key会得到最新的值
-        let v = cache.get("k1");
+        let v = cache.get("k1").await;
         assert_eq!(v.unwrap(), Some("v2".into()));
 
         // get不存在的key会返回None
-        let v = cache.get("k2");
+        let v = cache.get("k2").await;
         assert_eq!(v.unwrap(), None);
 
         // is_exists 存在的key会返回true, 否则返回false
-        assert!(cache.is_exists("k1").unwrap());
-        assert!(!cache.is_exists("k2").unwrap());
+        assert!(cache.is_exists("k1").await.unwrap());
+        assert!(!cache.is_exists("k2").await.unwrap());
 
         // del存在的key会返回旧的值
-        let v = cache.del("k1");
+        let v = cache.del("k1").await;
         assert_eq!(v.unwrap(), Some("v2".into()));
 
         // del不存在的key会返回None
-        assert_eq!(cache.del("k2").unwrap(), None);
+        assert_eq!(cache.del("k2").await.unwrap(), None);
+    }
+
+    async fn test_expired(cache: impl Cache) {
+        // 已经过期的值读不到
+        let mut v: StrValue = "v1".into();
+        v.set_expired(now() - 1);
+        cache.set("e1".into(), v).await.unwrap();
+        assert_eq!(cache.get("e1").await.unwrap(), None);
+        assert!(!cache.is_exists("e1").await.unwrap());
+        assert_eq!(cache.ttl("e1").await.unwrap(), -2);
+
+        // 没有过期的值可以读到, 并且有剩余有效期
+        cache.set_ex("e2", "v2", 60).await.unwrap();
+        assert_eq!(cache.get_str("e2").await.unwrap(), Some("v2".to_string()));
+        let ttl = cache.ttl("e2").await.unwrap();
+        assert!(ttl > 0 && ttl <= 60);
+
+        // 没有有效期
+        cache.set("e3".into(), "v3".into()).await.unwrap();
+        assert_eq!(cache.ttl("e3").await.unwrap(), -1);
+
+        cache.del("e2").await.unwrap();
+        cache.del("e3").await.unwrap();
+    }
+
+    async fn test_counter_and_set(cache: impl Cache) {
+        cache.del("c1").await.unwrap();
+        assert_eq!(cache.incr("c1", 60).await.unwrap(), 1);
+        assert_eq!(cache.incr("c1", 60).await.unwrap(), 2);
+        assert_eq!(cache.get_str("c1").await.unwrap(), Some("2".to_string()));
+        cache.del("c1").await.unwrap();
+
+        cache.del("s1").await.unwrap();
+        cache.sadd("s1", "a", 60).await.unwrap();
+        cache.sadd("s1", "b", 60).await.unwrap();
+        cache.sadd("s1", "a", 60).await.unwrap();
+        let mut members = cache.smembers("s1").await.unwrap();
+        members.sort();
+        assert_eq!(members, vec!["a".to_string(), "b".to_string()]);
+        cache.srem("s1", "a").await.unwrap();
+        assert_eq!(cache.smembers("s1").await.unwrap(), vec!["b".to_string()]);
+        assert!(cache.smembers("s2").await.unwrap().is_empty());
+        cache.del("s1").await.unwrap();
     }
 }
@@ -1,36 +1,120 @@
-use crate::cache::{Cache, StrValue};
+use crate::cache::{now, Cache, StrValue};
 use crate::error::AppError;
 use crate::Result;
-use redis::aio::Connection;
+use axum::async_trait;
+use redis::aio::ConnectionManager;
 use redis::AsyncCommands;
 use redis::Client;
 
-pub struct RedisDB(Client);
+lazy_static::lazy_static! {
+    /// 删除并返回旧的值, 只有字符串类型才返回, 集合等其它类型直接删除
+    static ref DEL_SCRIPT: redis::Script = redis::Script::new(
+        r"
+        local v = false
+        if redis.call('TYPE', KEYS[1]).ok == 'string' then
+            v = redis.call('GET', KEYS[1])
+        end
+        redis.call('DEL', KEYS[1])
+        return v
+        ",
+    );
+
+    /// 计数加一并在没有有效期时设置有效期, 在一个脚本中执行, 不会留下没有有效期的计数
+    static ref INCR_SCRIPT: redis::Script = redis::Script::new(
+        r"
+        local n = redis.call('INCR', KEYS[1])
+        if redis.call('TTL', KEYS[1]) == -1 then
+            redis.call('EXPIRE', KEYS[1], ARGV[1])
+        end
+        return n
+        ",
+    );
+}
+
+/// redis 缓存, 值以字符串保存, 有效期交给 redis 处理
+///
+/// 所有请求共用一个连接, 断开后自动重连
+#[derive(Clone)]
+pub struct RedisDB(ConnectionManager);
 
 impl RedisDB {
-    pub fn new(client: Client) -> RedisDB {
-        RedisDB(client)
+    pub async fn new(client: Client) -> Result<RedisDB> {
+        let conn = ConnectionManager::new(client).await?;
+        Ok(RedisDB(conn))
     }
 
-    pub async fn get_conn(&self) -> Result<Connection> {
-        self.0.get_async_connection().await.map_err(AppError::from)
+    fn conn(&self) -> ConnectionManager {
+        self.0.clone()
     }
 }
 
+#[async_trait]
 impl Cache for RedisDB {
-    fn get(&self, key: &str) -> Result<Option<StrValue>> {
-        todo!()
+    async fn get(&self, key: &str) -> Result<Option<StrValue>> {
+        let s: Option<String> = self.conn().get(key).await?;
+        Ok(s.map(StrValue::from))
+    }
+
+    async fn set(&self, key: String, value: StrValue) -> Result<Option<StrValue>> {
+        let mut pipe = redis::pipe();
+        pipe.atomic().get(&key);
+        match value.ttl(now()) {
+            // redis 不接受 0 秒的有效期
+            Some(sec) => pipe.set_ex(&key, value.value(), sec.max(1) as usize),
+            None => pipe.set(&key, value.value()),
+        };
+        pipe.ignore();
+        let (old,): (Option<String>,) = pipe
+            .query_async(&mut self.conn())
+            .await
+            .map_err(AppError::from)?;
+        Ok(old.map(StrValue::from))
+    }
+
+    async fn del(&self, key: &str) -> Result<Option<StrValue>> {
+        let old: Option<String> = DEL_SCRIPT
+            .key(key)
+            .invoke_async(&mut self.conn())
+            .await?;
+        Ok(old.map(StrValue::from))
+    }
+
+    async fn is_exists(&self, key: &str) -> Result<bool> {
+        let s: bool = self.conn().exists(key).await?;
+        Ok(s)
+    }
+
+    async fn incr(&self, key: &str, sec: usize) -> Result<i64> {
+        let n: i64 = INCR_SCRIPT
+            .key(key)
+            .arg(sec)
+            .invoke_async(&mut self.conn())
+            .await?;
+        Ok(n)
+    }
+
+    async fn ttl(&self, key: &str) -> Result<i64> {
+        let n: i64 = self.conn().ttl(key).await?;
+        Ok(n)
     }
 
-    fn set(&self, key: String, value: StrValue) -> Result<Option<StrValue>> {
-        todo!()
+    async fn sadd(&self, key: &str, member: &str, sec: usize) -> Result<()> {
+        redis::pipe()
+            .sadd(key, member)
+            .ignore()
+            .expire(key, sec)
+            .ignore()
+            .query_async(&mut self.conn())
+            .await
+            .map_err(AppError::from)
     }
 
-    fn del(&self, key: &str) -> Result<Option<StrValue>> {
-        todo!()
+    async fn smembers(&self, key: &str) -> Result<Vec<String>> {
+        let members: Vec<String> = self.conn().smembers(key).await?;
+        Ok(members)
     }
 
-    fn is_exists(&self, key: &str) -> Result<bool> {
-        todo!()
+    async fn srem(&self, key: &str, member: &str) -> Result<()> {
+        self.conn().srem(key, member).await.map_err(AppError::from)
     }
 }
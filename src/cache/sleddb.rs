@@ -1,50 +1,183 @@
-use crate::cache::{Cache, StrValue};
+use crate::cache::{now, Cache, StrValue};
 use crate::error::AppError;
 use crate::error::AppErrorType::SledError;
 use crate::Result;
+use axum::async_trait;
 use sled::Db;
 use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, error};
 
-#[derive(Debug)]
+/// 嵌入式的缓存, 适合单机部署, 不需要 redis
+///
+/// 过期的数据在读取时删除, 另外由后台任务定期清理
+#[derive(Debug, Clone)]
 pub struct SledDb(Db);
 
 impl SledDb {
     pub fn new(path: impl AsRef<Path>) -> Self {
-        Self(sled::open(path).unwrap())
+        Self::open(path).unwrap()
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self(sled::open(path)?))
+    }
+
+    /// 读取没有过期的值, 已经过期的顺便删除
+    fn get_alive(&self, key: &str) -> Result<Option<StrValue>> {
+        let raw = match self.0.get(key.as_bytes())? {
+            Some(raw) => raw,
+            None => return Ok(None),
+        };
+        let v = decode(raw.clone())?;
+        if v.is_expired(now()) {
+            // 删除之前可能已经被重新写入, 只删除读到的这个值
+            let _ = self
+                .0
+                .compare_and_swap(key.as_bytes(), Some(raw), None as Option<&[u8]>)?;
+            return Ok(None);
+        }
+        Ok(Some(v))
+    }
+
+    /// 原子地修改一个值, 过期的值当作不存在, `f` 返回 None 时删除
+    fn update<F>(&self, key: &str, mut f: F) -> Result<Option<StrValue>>
+    where
+        F: FnMut(Option<StrValue>) -> Option<StrValue>,
+    {
+        let now = now();
+        let result = self.0.update_and_fetch(key.as_bytes(), |old| {
+            let old = old
+                .and_then(|v| serde_json::from_slice::<StrValue>(v).ok())
+                .filter(|v| !v.is_expired(now));
+            f(old).and_then(|v| serde_json::to_vec(&v).ok())
+        })?;
+        flip(result.map(decode))
+    }
+
+    /// 集合以 json 数组保存在值中
+    fn members(v: &Option<StrValue>) -> Vec<String> {
+        v.as_ref()
+            .and_then(|v| serde_json::from_str(&v.value).ok())
+            .unwrap_or_default()
+    }
+
+    /// 删除所有已经过期的数据, 返回删除的数量
+    pub fn purge_expired(&self) -> Result<usize> {
+        let now = now();
+        let mut count = 0;
+        for item in self.0.iter() {
+            let (key, value) = item?;
+            let expired = serde_json::from_slice::<StrValue>(value.as_ref())
+                .map(|v| v.is_expired(now))
+                .unwrap_or(false);
+            if expired
+                && self
+                    .0
+                    .compare_and_swap(key, Some(value), None as Option<&[u8]>)?
+                    .is_ok()
+            {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// 定期清理过期的数据, 间隔单位秒
+    pub async fn purge_schedule(self, interval: u64) {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval.max(1)));
+        loop {
+            interval.tick().await;
+            match self.purge_expired() {
+                Ok(count) => debug!("sled purge expired: {}", count),
+                Err(err) => error!("sled purge expired failed: {:?}", err),
+            }
+        }
     }
 }
 
+fn decode(v: sled::IVec) -> Result<StrValue> {
+    serde_json::from_slice(v.as_ref()).map_err(|e| AppError::from_err(e, SledError))
+}
+
 /// 把Option<Result<T, E>> flip 成 Result<Option<T>, E>
 /// 从这个函数里,你可以看到函数式编程的优雅
 fn flip<T>(x: Option<Result<T>>) -> Result<Option<T>> {
     x.map_or(Ok(None), |x| x.map(Some))
 }
 
+#[async_trait]
 impl Cache for SledDb {
-    fn get(&self, key: &str) -> Result<Option<StrValue>> {
-        let result: Option<Result<StrValue>> = self.0.get(key.as_bytes())?.map(|v| {
-            serde_json::from_slice(v.as_ref()).map_err(|e| AppError::from_err(e, SledError))
-        });
-        flip(result)
+    async fn get(&self, key: &str) -> Result<Option<StrValue>> {
+        self.get_alive(key)
     }
 
-    fn set(&self, key: String, value: StrValue) -> Result<Option<StrValue>> {
+    async fn set(&self, key: String, value: StrValue) -> Result<Option<StrValue>> {
         // 需要将StrValue序列化为json字符串之后再做存储
         let data = serde_json::to_vec(&value)?;
-        let result: Option<Result<StrValue>> = self.0.insert(key, data)?.map(|v| {
-            serde_json::from_slice(v.as_ref()).map_err(|e| AppError::from_err(e, SledError))
-        });
-        flip(result)
+        let result: Option<Result<StrValue>> = self.0.insert(key, data)?.map(decode);
+        Ok(flip(result)?.filter(|v| !v.is_expired(now())))
+    }
+
+    async fn del(&self, key: &str) -> Result<Option<StrValue>> {
+        let result: Option<Result<StrValue>> = self.0.remove(key.as_bytes())?.map(decode);
+        Ok(flip(result)?.filter(|v| !v.is_expired(now())))
+    }
+
+    async fn is_exists(&self, key: &str) -> Result<bool> {
+        Ok(self.get_alive(key)?.is_some())
+    }
+
+    async fn incr(&self, key: &str, sec: usize) -> Result<i64> {
+        let v = self.update(key, |old| match old {
+            Some(mut old) => {
+                let n = old.value.parse::<i64>().unwrap_or(0) + 1;
+                old.value = n.to_string();
+                Some(old)
+            }
+            None => Some(StrValue::with_ttl("1", sec)),
+        })?;
+        Ok(v.and_then(|v| v.value.parse().ok()).unwrap_or(0))
+    }
+
+    async fn ttl(&self, key: &str) -> Result<i64> {
+        Ok(match self.get_alive(key)? {
+            Some(v) => v.ttl(now()).unwrap_or(-1),
+            None => -2,
+        })
+    }
+
+    async fn sadd(&self, key: &str, member: &str, sec: usize) -> Result<()> {
+        self.update(key, |old| {
+            let mut members = Self::members(&old);
+            if !members.iter().any(|m| m == member) {
+                members.push(member.to_string());
+            }
+            let value = serde_json::to_string(&members).unwrap_or_default();
+            Some(StrValue::with_ttl(&value, sec))
+        })?;
+        Ok(())
     }
 
-    fn del(&self, key: &str) -> Result<Option<StrValue>> {
-        let result: Option<Result<StrValue>> = self.0.remove(key.as_bytes())?.map(|v| {
-            serde_json::from_slice(v.as_ref()).map_err(|e| AppError::from_err(e, SledError))
-        });
-        flip(result)
+    async fn smembers(&self, key: &str) -> Result<Vec<String>> {
+        Ok(Self::members(&self.get_alive(key)?))
     }
 
-    fn is_exists(&self, key: &str) -> Result<bool> {
-        Ok(self.0.contains_key(key.as_bytes())?)
+    async fn srem(&self, key: &str, member: &str) -> Result<()> {
+        self.update(key, |old| {
+            let members: Vec<String> = Self::members(&old)
+                .into_iter()
+                .filter(|m| m != member)
+                .collect();
+            // 和 redis 一样, 集合为空时删除
+            if members.is_empty() {
+                return None;
+            }
+            let value = serde_json::to_string(&members).unwrap_or_default();
+            let mut v = old?;
+            v.value = value;
+            Some(v)
+        })?;
+        Ok(())
     }
 }
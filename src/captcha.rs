@@ -0,0 +1,310 @@
+//! 登录验证码
+//!
+//! 支持三种提供方, 在配置中选择:
+//!
+//! * `none` - 不使用验证码
+//! * `hcaptcha` - 使用 hcaptcha.com, 需要能访问外网
+//! * `image` - 内置的算术图片验证码, 答案保存在缓存中, 不依赖外部服务
+
+use crate::config::CaptchaConfig;
+use crate::error::AppError;
+use crate::form::AdminLogin;
+use crate::model::AppState;
+use crate::{hcaptcha, login_guard, session, Result};
+use rand::{thread_rng, Rng};
+use std::fmt::Write;
+
+/// 验证码提供方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    None,
+    HCaptcha,
+    Image,
+}
+
+impl Provider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Provider::None => "none",
+            Provider::HCaptcha => "hcaptcha",
+            Provider::Image => "image",
+        }
+    }
+}
+
+impl From<&str> for Provider {
+    fn from(s: &str) -> Self {
+        match s {
+            "hcaptcha" => Provider::HCaptcha,
+            "image" => Provider::Image,
+            _ => Provider::None,
+        }
+    }
+}
+
+/// 登录页面需要展示的验证码
+pub struct Widget {
+    /// 为 `none` 时不展示
+    pub provider: &'static str,
+    /// hcaptcha 的 site key
+    pub site_key: String,
+    /// 内置图片验证码的 ID
+    pub id: String,
+    /// 内置图片验证码的 svg 图片
+    pub svg: String,
+}
+
+impl Widget {
+    fn none() -> Self {
+        Self {
+            provider: Provider::None.as_str(),
+            site_key: String::new(),
+            id: String::new(),
+            svg: String::new(),
+        }
+    }
+}
+
+/// 算术题
+pub struct Challenge {
+    /// 题目, 例如 `12+7=?`
+    pub question: String,
+    pub answer: i32,
+}
+
+/// 随机生成一道 20 以内的加减法, 减法的结果不为负数
+pub fn challenge() -> Challenge {
+    let mut rng = thread_rng();
+    let a: i32 = rng.gen_range(1..=20);
+    let b: i32 = rng.gen_range(1..=20);
+    if rng.gen_bool(0.5) {
+        Challenge {
+            question: format!("{}+{}=?", a, b),
+            answer: a + b,
+        }
+    } else {
+        let (a, b) = if a < b { (b, a) } else { (a, b) };
+        Challenge {
+            question: format!("{}-{}=?", a, b),
+            answer: a - b,
+        }
+    }
+}
+
+/// 七段数码管的线段, 坐标是字符格子内的比例 (x1, y1, x2, y2), 顺序为 a ~ g
+const SEGMENTS: [(f32, f32, f32, f32); 7] = [
+    (0.0, 0.0, 1.0, 0.0),
+    (1.0, 0.0, 1.0, 0.5),
+    (1.0, 0.5, 1.0, 1.0),
+    (0.0, 1.0, 1.0, 1.0),
+    (0.0, 0.5, 0.0, 1.0),
+    (0.0, 0.0, 0.0, 0.5),
+    (0.0, 0.5, 1.0, 0.5),
+];
+
+/// 数字 0 ~ 9 点亮的线段, 从低位到高位依次是 a ~ g
+const DIGITS: [u8; 10] = [
+    0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110, 0b1101101, 0b1111101, 0b0000111,
+    0b1111111, 0b1101111,
+];
+
+/// 字符由哪些线段组成
+fn glyph(c: char) -> Vec<(f32, f32, f32, f32)> {
+    match c {
+        '0'..='9' => {
+            let mask = DIGITS[c as usize - '0' as usize];
+            SEGMENTS
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, s)| *s)
+                .collect()
+        }
+        '+' => vec![(0.5, 0.25, 0.5, 0.75), (0.1, 0.5, 0.9, 0.5)],
+        '-' => vec![(0.1, 0.5, 0.9, 0.5)],
+        '=' => vec![(0.1, 0.35, 0.9, 0.35), (0.1, 0.65, 0.9, 0.65)],
+        '?' => vec![
+            (0.0, 0.0, 1.0, 0.0),
+            (1.0, 0.0, 1.0, 0.5),
+            (1.0, 0.5, 0.5, 0.5),
+            (0.5, 0.5, 0.5, 0.75),
+            (0.5, 0.9, 0.5, 1.0),
+        ],
+        _ => vec![],
+    }
+}
+
+/// 把题目画成 svg, 每个字符由抖动的线段组成, 再加上干扰线, 图片里没有可以直接读取的文字
+pub fn render_svg(text: &str) -> String {
+    const CELL: f32 = 24.0;
+    const GLYPH_W: f32 = 14.0;
+    const GLYPH_H: f32 = 28.0;
+    const HEIGHT: f32 = 50.0;
+    let mut rng = thread_rng();
+    let width = CELL * text.chars().count() as f32 + 20.0;
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}"><rect width="100%" height="100%" fill="#f4f6f9"/>"##,
+        w = width,
+        h = HEIGHT
+    );
+    for (i, c) in text.chars().enumerate() {
+        let x0 = 10.0 + CELL * i as f32 + rng.gen_range(-2.0..2.0);
+        let y0 = (HEIGHT - GLYPH_H) / 2.0 + rng.gen_range(-4.0..4.0);
+        let color = format!(
+            "rgb({},{},{})",
+            rng.gen_range(0..120),
+            rng.gen_range(0..120),
+            rng.gen_range(0..120)
+        );
+        for (x1, y1, x2, y2) in glyph(c) {
+            let _ = write!(
+                svg,
+                r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="3" stroke-linecap="round"/>"#,
+                x0 + x1 * GLYPH_W + rng.gen_range(-1.5..1.5),
+                y0 + y1 * GLYPH_H + rng.gen_range(-1.5..1.5),
+                x0 + x2 * GLYPH_W + rng.gen_range(-1.5..1.5),
+                y0 + y2 * GLYPH_H + rng.gen_range(-1.5..1.5),
+                color
+            );
+        }
+    }
+    for _ in 0..6 {
+        let _ = write!(
+            svg,
+            r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="rgb({},{},{})" stroke-width="1"/>"#,
+            rng.gen_range(0.0..width),
+            rng.gen_range(0.0..HEIGHT),
+            rng.gen_range(0.0..width),
+            rng.gen_range(0.0..HEIGHT),
+            rng.gen_range(100..200),
+            rng.gen_range(100..200),
+            rng.gen_range(100..200)
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn answer_key(cfg: &CaptchaConfig, id: &str) -> String {
+    format!("{}{}", cfg.prefix, id)
+}
+
+/// 当前 IP 登录是否需要验证码
+pub async fn required(state: &AppState, ip: &str) -> Result<bool> {
+    let cfg = &state.captcha_cfg;
+    if Provider::from(cfg.provider.as_str()) == Provider::None {
+        return Ok(false);
+    }
+    if cfg.after_failures == 0 {
+        return Ok(true);
+    }
+    let failures = login_guard::ip_failures(state.cache.as_ref(), &state.login_cfg, ip).await?;
+    Ok(failures >= cfg.after_failures)
+}
+
+/// 生成登录页面需要展示的验证码, 内置图片验证码的答案写入缓存
+pub async fn widget(state: &AppState, ip: &str) -> Result<Widget> {
+    if !required(state, ip).await? {
+        return Ok(Widget::none());
+    }
+    let cfg = &state.captcha_cfg;
+    let provider = Provider::from(cfg.provider.as_str());
+    match provider {
+        Provider::None => Ok(Widget::none()),
+        Provider::HCaptcha => Ok(Widget {
+            provider: provider.as_str(),
+            site_key: state.hcap_cfg.site_key.clone(),
+            ..Widget::none()
+        }),
+        Provider::Image => {
+            let id = session::id();
+            let challenge = challenge();
+            state
+                .cache
+                .set_ex(&answer_key(cfg, &id), &challenge.answer.to_string(), cfg.ttl)
+                .await?;
+            Ok(Widget {
+                provider: provider.as_str(),
+                svg: render_svg(&challenge.question),
+                id,
+                ..Widget::none()
+            })
+        }
+    }
+}
+
+/// 校验内置图片验证码, 无论对错答案都只能使用一次
+async fn verify_image(state: &AppState, id: &str, answer: &str) -> Result<bool> {
+    // ID 是 uuid, 其它格式的直接拒绝, 避免拼出任意的缓存键
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(false);
+    }
+    let key = answer_key(&state.captcha_cfg, id);
+    let expected = state.cache.get_str(&key).await?;
+    state.cache.del(&key).await?;
+    Ok(matches!(expected, Some(expected) if expected == answer.trim()))
+}
+
+/// 登录时校验验证码, 不需要验证码时直接通过
+pub async fn verify(state: &AppState, ip: &str, login: &AdminLogin) -> Result<()> {
+    if !required(state, ip).await? {
+        return Ok(());
+    }
+    let is_valid = match Provider::from(state.captcha_cfg.provider.as_str()) {
+        Provider::None => true,
+        Provider::HCaptcha => {
+            hcaptcha::verify(
+                login.hcaptcha_response.clone(),
+                state.hcap_cfg.secret_key.clone(),
+            )
+            .await?
+        }
+        Provider::Image => verify_image(state, &login.captcha_id, &login.captcha_answer).await?,
+    };
+    if !is_valid {
+        return Err(AppError::auth_error("验证码错误或已过期, 请刷新页面后重试"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_from_str() {
+        assert_eq!(Provider::from("image"), Provider::Image);
+        assert_eq!(Provider::from("hcaptcha"), Provider::HCaptcha);
+        assert_eq!(Provider::from(""), Provider::None);
+        assert_eq!(Provider::from("unknown"), Provider::None);
+    }
+
+    #[test]
+    fn test_challenge() {
+        for _ in 0..100 {
+            let c = challenge();
+            assert!(c.question.ends_with("=?"));
+            let expr = c.question.trim_end_matches("=?");
+            let answer = if let Some((a, b)) = expr.split_once('+') {
+                a.parse::<i32>().unwrap() + b.parse::<i32>().unwrap()
+            } else {
+                let (a, b) = expr.split_once('-').unwrap();
+                a.parse::<i32>().unwrap() - b.parse::<i32>().unwrap()
+            };
+            assert_eq!(answer, c.answer);
+            assert!(c.answer >= 0);
+        }
+    }
+
+    #[test]
+    fn test_render_svg() {
+        let svg = render_svg("12+7=?");
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>"));
+        assert!(!svg.contains("<text"));
+        // 1 和 2 一共 7 条线段, 加号 2 条, 7 有 3 条, 等号 2 条, 问号 5 条, 再加 6 条干扰线
+        assert_eq!(svg.matches("<line").count(), 7 + 2 + 3 + 2 + 5 + 6);
+    }
+}
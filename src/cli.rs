@@ -0,0 +1,325 @@
+//! 命令行: 除了启动 web 服务, 还提供账号、导入导出、短信提醒和数据库迁移等运维命令, 都使用同一份配置
+
+use crate::arg::MedicinalBackendQueryArg;
+use crate::config::Config;
+use crate::db::{admin, medicinal, migration};
+use crate::error::{AppError, AppErrorType};
+use crate::form::CreateAdmin;
+use crate::handler::backend::excel;
+use crate::handler::backend::medicinal::{insert_all, load_csv_file};
+use crate::handler::helper::get_client;
+use crate::model::AppState;
+use crate::notify::schedule::Plan;
+use crate::notify::Notifier;
+use crate::rbac::Role;
+use crate::sms::sms;
+use crate::{password, session, Result};
+use clap::{Parser, Subcommand};
+use deadpool_postgres::Pool;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Parser)]
+#[clap(name = "strangers", version, about = "药品有效期管理")]
+pub struct Cli {
+    /// 配置文件(toml 或 yaml), 环境变量中的配置会覆盖文件中的值
+    #[clap(long, short, global = true)]
+    pub config: Option<PathBuf>,
+    /// 不指定命令时启动 web 服务
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// 启动 web 服务
+    Serve,
+    /// 管理员账号
+    #[clap(subcommand)]
+    Admin(AdminCommand),
+    /// 从 csv 文件导入药品, 与后台的批量导入相同, 不支持 Excel 文件
+    Import {
+        /// csv 文件(GBK 或 UTF-8 编码), 只读取不修改
+        file: PathBuf,
+    },
+    /// 按列表页的过滤条件及排序导出药品
+    Export {
+        /// 列表页的查询参数, 如 `category=急救&expired=2&order=name`
+        #[clap(long, default_value = "")]
+        filter: String,
+        /// 导出格式: `xlsx` 或者 `json`
+        #[clap(long, default_value = "xlsx")]
+        format: String,
+        /// 输出的文件, 不指定时 xlsx 保存在当前目录, json 输出到标准输出
+        #[clap(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// 立即检查已经过期、即将过期和库存不足的药品并发送到所有的通知渠道
+    Notify {
+        /// 只列出需要提醒的药品, 不发送
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// 数据库迁移, 默认执行还没有执行的迁移
+    Migrate {
+        #[clap(subcommand)]
+        action: Option<MigrateCommand>,
+        /// 只列出将要执行的迁移
+        #[clap(long, global = true)]
+        dry_run: bool,
+    },
+    /// 配置
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand)]
+pub enum AdminCommand {
+    /// 添加管理员, 密码随机生成, 登录后必须修改
+    Create {
+        username: String,
+        /// 角色: viewer, operator, pharmacist, administrator, auditor
+        #[clap(long, default_value = "administrator")]
+        role: String,
+        /// 系统账号, 不能被禁用、删除或修改角色
+        #[clap(long)]
+        sys: bool,
+    },
+    /// 重置密码, 生成新的临时密码并注销该账号的所有登录
+    ResetPassword { username: String },
+    /// 禁用账号并注销该账号的所有登录
+    Disable { username: String },
+}
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// 执行还没有执行的迁移
+    Up,
+    /// 查看每个迁移的状态
+    Status,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// 检查配置并输出生效的配置, 密码等敏感信息不输出
+    Check,
+}
+
+/// 检查配置, 有问题时返回 false
+pub fn check_config(cfg: &Config) -> bool {
+    println!("{}", cfg.masked());
+    let problems = cfg.validate();
+    if problems.is_empty() {
+        eprintln!("配置检查通过");
+        let interval = cfg.sms.as_ref().and_then(|c| c.check_interval);
+        if let Ok(plan) = Plan::new(&cfg.schedule, interval) {
+            if let Some(next) = plan.next() {
+                eprintln!(
+                    "下一次检查通知: {} ({})",
+                    next.format("%Y-%m-%d %H:%M:%S"),
+                    plan
+                );
+            }
+        }
+        return true;
+    }
+    eprintln!("配置有 {} 个问题:", problems.len());
+    for problem in &problems {
+        eprintln!("  - {}", problem);
+    }
+    false
+}
+
+/// 是否只使用数据库, 这些命令只创建连接池, 缓存不可用时也能执行
+pub fn only_needs_db(command: &Command) -> bool {
+    matches!(command, Command::Export { .. } | Command::Migrate { .. })
+}
+
+/// 执行除 `serve` 和 `config` 以外的命令
+pub async fn run(state: Arc<AppState>, command: Command) -> Result<()> {
+    match command {
+        Command::Serve | Command::Config(_) => Ok(()),
+        Command::Admin(command) => run_admin(&state, command).await,
+        Command::Import { file } => import(&state, file).await,
+        Command::Notify { dry_run } => notify(state, dry_run).await,
+        command => run_db(&state.pool, command).await,
+    }
+}
+
+/// 执行只使用数据库的命令, 见 [`only_needs_db`]
+pub async fn run_db(pool: &Pool, command: Command) -> Result<()> {
+    match command {
+        Command::Export {
+            filter,
+            format,
+            output,
+        } => export(pool, &filter, &format, output).await,
+        Command::Migrate { action, dry_run } => migrate(pool, action, dry_run).await,
+        _ => Ok(()),
+    }
+}
+
+async fn run_admin(state: &AppState, command: AdminCommand) -> Result<()> {
+    let client = get_client(state, "命令行:管理员").await?;
+    match command {
+        AdminCommand::Create {
+            username,
+            role,
+            sys,
+        } => {
+            if username.trim().is_empty() {
+                return Err(AppError::invalid_argument("用户名不能为空"));
+            }
+            let role: Role = role.parse()?;
+            let temporary = password::temporary();
+            let hashed = password::hash(&temporary)?;
+            let id = admin::create(
+                &client,
+                CreateAdmin {
+                    username: username.clone(),
+                    password: hashed.clone(),
+                    re_password: hashed.clone(),
+                    role: role.as_str().to_string(),
+                },
+            )
+            .await?
+            .id;
+            admin::update_password(&client, id, &hashed, true).await?;
+            if sys {
+                admin::set_sys(&client, id).await?;
+            }
+            println!("已添加管理员 {}, 临时密码: {}", username, temporary);
+        }
+        AdminCommand::ResetPassword { username } => {
+            let item = admin::find(&client, &username).await?;
+            let temporary = password::temporary();
+            let hashed = password::hash(&temporary)?;
+            admin::update_password(&client, item.id, &hashed, true).await?;
+            session::revoke_admin(state.cache.as_ref(), &state.sess_cfg, item.id).await?;
+            println!("已重置 {} 的密码, 临时密码: {}", username, temporary);
+        }
+        AdminCommand::Disable { username } => {
+            let item = admin::find(&client, &username).await?;
+            if admin::disable_or_enable(&client, item.id, true).await? == 0 {
+                return Err(AppError::from_str(
+                    "系统账号不能被禁用",
+                    AppErrorType::Common,
+                ));
+            }
+            session::revoke_admin(state.cache.as_ref(), &state.sess_cfg, item.id).await?;
+            println!("已禁用 {}", username);
+        }
+    }
+    Ok(())
+}
+
+async fn import(state: &AppState, file: PathBuf) -> Result<()> {
+    if file.extension().and_then(|s| s.to_str()) != Some("csv") {
+        return Err(AppError::from_str(
+            "只支持导入 csv 文件, 不支持 Excel, 请先将文件另存为 csv",
+            AppErrorType::UploadError,
+        ));
+    }
+    let (result, total_count, _) = load_csv_file(&file.to_string_lossy()).await?;
+    let client = get_client(state, "命令行:导入").await?;
+    let insert_count = insert_all(state, &client, &result, |_| true).await;
+    println!(
+        "导入 {}: 成功 {} 条, 失败 {} 条",
+        file.display(),
+        insert_count,
+        total_count - insert_count
+    );
+    Ok(())
+}
+
+async fn export(pool: &Pool, filter: &str, format: &str, output: Option<PathBuf>) -> Result<()> {
+    let args: MedicinalBackendQueryArg = serde_urlencoded::from_str(filter)
+        .map_err(|err| AppError::invalid_argument(&format!("过滤条件错误: {}", err)))?;
+    let client = pool.get().await?;
+    let list = medicinal::all_by_arg(&client, &args, &[]).await?;
+    match format {
+        "json" => {
+            let json = serde_json::to_string_pretty(&list)?;
+            match output {
+                Some(output) => tokio::fs::write(&output, json)
+                    .await
+                    .map_err(|err| AppError::from_err(err, AppErrorType::Common))?,
+                None => println!("{}", json),
+            }
+        }
+        "xlsx" => {
+            let file_name = excel::create_xlsx_for_medicinal(&list)?;
+            let saved = match output {
+                Some(output) => {
+                    let to_excel_error =
+                        |err: std::io::Error| AppError::from_err(err, AppErrorType::ExcelError);
+                    tokio::fs::copy(&file_name, &output)
+                        .await
+                        .map_err(to_excel_error)?;
+                    tokio::fs::remove_file(&file_name)
+                        .await
+                        .map_err(to_excel_error)?;
+                    output.display().to_string()
+                }
+                None => file_name,
+            };
+            eprintln!("已导出 {} 条药品到 {}", list.len(), saved);
+        }
+        _ => return Err(AppError::invalid_argument("导出格式只能是 xlsx 或者 json")),
+    }
+    Ok(())
+}
+
+async fn notify(state: Arc<AppState>, dry_run: bool) -> Result<()> {
+    if dry_run {
+        let client = get_client(&state, "命令行:通知").await?;
+        let events = sms::due(&state, &client)
+            .await?
+            .into_events(&state.notify_cfg);
+        for event in &events {
+            println!("{}: {}", event.title(), event.summary());
+            if let Some(digest) = event.digest() {
+                println!("{}", digest.text());
+            }
+        }
+        println!("共 {} 条消息需要发送", events.len());
+        return Ok(());
+    }
+    let notifier = Notifier::from_state(&state)?;
+    if notifier.is_empty() {
+        return Err(AppError::from_str("没有配置通知渠道", AppErrorType::Common));
+    }
+    let sent = sms::do_work(state, Arc::new(notifier)).await?;
+    println!("发送成功 {} 条, 失败的消息由服务按退避时间重试", sent);
+    Ok(())
+}
+
+async fn migrate(pool: &Pool, action: Option<MigrateCommand>, dry_run: bool) -> Result<()> {
+    let mut client = pool.get().await?;
+    match action.unwrap_or(MigrateCommand::Up) {
+        MigrateCommand::Status => {
+            for s in migration::status(&client).await? {
+                let label = match (s.applied_at, s.modified) {
+                    (None, _) => "未执行".to_string(),
+                    (Some(at), false) => format!("已执行 {}", at.format("%Y-%m-%d %H:%M:%S")),
+                    (Some(at), true) => format!(
+                        "已执行 {}, 之后迁移文件被修改过",
+                        at.format("%Y-%m-%d %H:%M:%S")
+                    ),
+                };
+                println!("{:04} {:<20} {}", s.version, s.name, label);
+            }
+        }
+        MigrateCommand::Up => {
+            let done = migration::up(&mut client, dry_run).await?;
+            if done.is_empty() {
+                println!("没有需要执行的迁移");
+            }
+            for m in done {
+                let action = if dry_run { "将执行" } else { "已执行" };
+                println!("{} {:04} {}", action, m.version, m.name);
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,16 +1,63 @@
 //! 配置
 
-use serde::Deserialize;
+use crate::notify::schedule::{Plan, QuietHours};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::path::Path;
 
-#[derive(Debug, Deserialize, Default, PartialEq)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringList {
+    List(Vec<String>),
+    Text(String),
+    Number(u64),
+}
+
+/// 列表可以是数组, 也可以是逗号分隔的字符串(环境变量中只有一个手机号码时会被解析为数字)
+fn list<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<String>, D::Error> {
+    Ok(match StringList::deserialize(d)? {
+        StringList::List(list) => list,
+        StringList::Text(s) => s
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        StringList::Number(n) => vec![n.to_string()],
+    })
+}
+
+/// 手机号码, 格式同 [`list`]
+fn phones<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(transparent)]
+    struct Wrap(#[serde(deserialize_with = "list")] Vec<String>);
+    Ok(Option::<Wrap>::deserialize(d)?.map(|Wrap(phones)| phones))
+}
+
+/// 短信的配置, 密钥、签名、模板和接收号码也可以在后台的短信设置中修改, 后台保存的值优先
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq, Clone)]
 pub struct SmsConfig {
+    /// 没有配置 `SCHEDULE.CRON` 时的检查间隔, 单位秒
     pub check_interval: Option<u64>,
     pub send_sms_toggle: Option<bool>,
     pub expired_days: Option<i64>,
+    /// 接收短信的手机号码, 环境变量中用逗号分隔多个号码
+    #[serde(default, deserialize_with = "phones")]
     pub phones: Option<Vec<String>>,
+    /// 阿里云的 AccessKey ID
+    pub access_key_id: Option<String>,
+    /// 阿里云的 AccessKey Secret
+    pub access_key_secret: Option<String>,
+    /// 短信签名
+    pub sign_name: Option<String>,
+    /// 短信模板, 模板中有 `${name}` 和 `${code}` 两个变量
+    pub template_code: Option<String>,
+    /// 安静时段, 不配置时使用 `SCHEDULE.QUIET_HOURS`, 为空表示没有安静时段
+    pub quiet_hours: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct WebConfig {
     /// web服务监听地址
     pub addr: String,
@@ -18,39 +65,334 @@ pub struct WebConfig {
     pub secret_key: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct RedisConfig {
     pub dsn: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// 缓存的配置, 保存登录 session、登录失败次数、验证码等临时数据
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// 缓存类型: redis, 或者嵌入式的 sled(单机部署时可以不安装 redis)
+    pub backend: String,
+    /// sled 数据目录
+    pub sled_path: String,
+    /// sled 清理过期数据的间隔, 单位秒
+    pub purge_interval: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            backend: "redis".to_string(),
+            sled_path: "data/cache".to_string(),
+            purge_interval: 600,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SessionConfig {
     pub prefix: String,
     pub id_name: String,
+    /// 最长有效期, 单位秒, 从登录开始计算, 到期后必须重新登录
     pub expired: usize,
+    /// 空闲超时, 单位秒, 超过这个时间没有访问就失效, 0 表示不启用
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: usize,
+    /// cookie 只通过 https 发送, 启用 `SSL_ENABLE` 时自动开启, 部署在 https 反向代理之后时需要手动开启
+    #[serde(default)]
+    pub secure: bool,
+}
+
+fn default_idle_timeout() -> usize {
+    7200
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct HCaptchaConfig {
     pub site_key: String,
     pub secret_key: String,
 }
 
+/// 登录验证码的配置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CaptchaConfig {
+    /// 验证码提供方: none, hcaptcha, image
+    pub provider: String,
+    /// 同一 IP 失败多少次之后才需要验证码, 0 表示每次登录都需要
+    pub after_failures: u32,
+    /// 内置图片验证码在缓存中的前缀
+    pub prefix: String,
+    /// 内置图片验证码的有效期, 单位秒
+    pub ttl: usize,
+}
+
+impl Default for CaptchaConfig {
+    fn default() -> Self {
+        Self {
+            provider: "none".to_string(),
+            after_failures: 0,
+            prefix: "strangers:captcha:".to_string(),
+            ttl: 300,
+        }
+    }
+}
+
+/// 登录防暴力破解的配置, 不配置时使用默认值
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LoginConfig {
+    /// 缓存键的前缀
+    pub prefix: String,
+    /// 同一个用户名连续失败多少次后锁定
+    pub max_attempts: u32,
+    /// 同一个 IP 连续失败多少次后锁定
+    pub max_ip_attempts: u32,
+    /// 统计失败次数的时间窗口, 单位秒
+    pub window: usize,
+    /// 锁定时长, 单位秒
+    pub lock_seconds: usize,
+    /// 第一次失败后的延迟, 之后每次失败翻倍, 单位毫秒
+    pub delay_ms: u64,
+    /// 延迟的上限, 单位毫秒
+    pub max_delay_ms: u64,
+    /// 部署在反向代理之后时, 从 `X-Forwarded-For` 中获取客户端 IP
+    pub trust_proxy: bool,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        Self {
+            prefix: "strangers:login:".to_string(),
+            max_attempts: 5,
+            max_ip_attempts: 20,
+            window: 900,
+            lock_seconds: 900,
+            delay_ms: 500,
+            max_delay_ms: 8000,
+            trust_proxy: false,
+        }
+    }
+}
+
+/// 列表分页的配置, 不配置时使用默认值
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PageConfig {
+    /// 默认每页的记录数
+    pub default_size: u32,
+    /// 每页记录数的下限
+    pub min_size: u32,
+    /// 每页记录数的上限
+    pub max_size: u32,
+}
+
+impl Default for PageConfig {
+    fn default() -> Self {
+        Self {
+            default_size: 30,
+            min_size: 10,
+            max_size: 200,
+        }
+    }
+}
+
+impl PageConfig {
+    /// 把每页记录数限制在配置的范围内, 没有指定时使用默认值
+    pub fn clamp(&self, size: Option<u32>) -> u32 {
+        let min = self.min_size.max(1);
+        let max = self.max_size.max(min);
+        size.unwrap_or(self.default_size).clamp(min, max)
+    }
+
+    /// 列表页可以选择的每页记录数
+    pub fn choices(&self) -> Vec<u32> {
+        let mut sizes: Vec<u32> = [10, 20, 30, 50, 100, 200]
+            .into_iter()
+            .chain([self.clamp(None)])
+            .map(|size| self.clamp(Some(size)))
+            .collect();
+        sizes.sort_unstable();
+        sizes.dedup();
+        sizes
+    }
+}
+
+/// 数据库迁移的配置, 不配置时使用默认值
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MigrateConfig {
+    /// 启动时执行还没有执行的迁移
+    pub on_start: bool,
+}
+
+impl Default for MigrateConfig {
+    fn default() -> Self {
+        Self { on_start: true }
+    }
+}
+
+/// 通知渠道的配置, 药品到期和库存不足的提醒发送到所有配置的渠道, 短信渠道使用 `SMS` 的配置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct NotifyConfig {
+    /// 发送方式: digest 每次检查只发送一条按类目汇总的消息, item 每个药品发送一条
+    pub mode: String,
+    /// 汇总时仍然逐个发送的事件: expired, expiring, low_stock
+    pub critical: Vec<String>,
+    /// 后台的访问地址, 如 `https://emacsvi.com:9998`, 汇总消息中带上列表页的链接
+    pub base_url: Option<String>,
+    /// 数量(`count` 开头的数字)低于这个值时提醒库存不足, 不配置时不检查库存
+    pub low_stock: Option<i32>,
+    /// 邮件
+    pub email: Option<EmailConfig>,
+    /// webhook, 可以配置多个
+    pub webhooks: Vec<WebhookConfig>,
+    /// 每条消息最多尝试发送的次数, 之后只能在后台重新发送
+    pub max_attempts: i32,
+    /// 第一次失败后多久重试, 之后每次翻倍, 单位秒
+    pub retry_delay: u64,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            mode: "digest".to_string(),
+            critical: vec![],
+            base_url: None,
+            low_stock: None,
+            email: None,
+            webhooks: vec![],
+            max_attempts: 5,
+            retry_delay: 60,
+        }
+    }
+}
+
+/// 通过 SMTP 发送邮件
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmailConfig {
+    pub host: String,
+    /// 不配置时使用加密方式的默认端口
+    pub port: Option<u16>,
+    /// 加密方式: tls(465 端口), starttls(587 端口) 或 none(25 端口, 只用于内网)
+    #[serde(default = "default_email_tls")]
+    pub tls: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// 发件人, 如 `药品提醒 <alert@example.com>`
+    pub from: String,
+    /// 收件人
+    pub to: Vec<String>,
+    /// 安静时段, 不配置时使用 `SCHEDULE.QUIET_HOURS`, 为空表示没有安静时段
+    pub quiet_hours: Option<String>,
+}
+
+fn default_email_tls() -> String {
+    "starttls".to_string()
+}
+
+/// 通过 HTTP POST 发送通知
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookConfig {
+    /// 类型: dingtalk(钉钉), wecom(企业微信), feishu(飞书) 群机器人, 或者 generic(通用 JSON)
+    #[serde(default = "default_webhook_kind")]
+    pub kind: String,
+    /// 群机器人的地址, 包含 access_token 或 key
+    pub url: String,
+    /// 钉钉和飞书群机器人"加签"的密钥, 没有开启加签时不用配置
+    pub secret: Option<String>,
+    /// 安静时段, 不配置时使用 `SCHEDULE.QUIET_HOURS`, 为空表示没有安静时段
+    pub quiet_hours: Option<String>,
+}
+
+fn default_webhook_kind() -> String {
+    "generic".to_string()
+}
+
+/// 定时检查通知的计划, 不配置时使用默认值
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    /// cron 表达式(`分 时 日 月 周` 或者带秒的六个字段), 如 `30 9 * * Mon-Fri`;
+    /// 不配置时每隔 `SMS.CHECK_INTERVAL` 秒检查一次
+    pub cron: Option<String>,
+    /// 时区, cron 表达式、安静时段和节假日都按这个时区计算
+    pub timezone: String,
+    /// 安静时段, 如 `18:00-08:00`, 这段时间内不发送通知, 各渠道可以单独配置; 为空表示没有安静时段
+    pub quiet_hours: String,
+    /// 周末不检查
+    pub skip_weekends: bool,
+    /// 不检查的节假日, 如 `2022-10-01`, 环境变量中用逗号分隔
+    #[serde(deserialize_with = "list")]
+    pub holidays: Vec<String>,
+    /// 调休上班的周末, 开启 `SKIP_WEEKENDS` 时这些日子仍然检查
+    #[serde(deserialize_with = "list")]
+    pub workdays: Vec<String>,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            cron: None,
+            timezone: "Asia/Shanghai".to_string(),
+            quiet_hours: "18:00-08:00".to_string(),
+            skip_weekends: false,
+            holidays: vec![],
+            workdays: vec![],
+        }
+    }
+}
+
 /// 配置
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     /// web配置
     pub web: WebConfig,
     /// Postgres配置
     pub pg: deadpool_postgres::Config,
-    pub redis: RedisConfig,
+    /// 缓存类型为 sled 时可以不配置
+    pub redis: Option<RedisConfig>,
+    #[serde(default)]
+    pub cache: CacheConfig,
     pub session: SessionConfig,
     pub hcaptcha: HCaptchaConfig,
+    #[serde(default)]
+    pub captcha: CaptchaConfig,
+    #[serde(default)]
+    pub login: LoginConfig,
+    #[serde(default)]
+    pub page: PageConfig,
+    #[serde(default)]
+    pub migrate: MigrateConfig,
     pub upload_dir: Option<String>,
     pub sms: Option<SmsConfig>,
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
     pub ssl_enable: bool,
 }
 
+/// https 证书
+pub const CERT_FILE: &str = "certs/emacsvi.com.cer";
+/// https 证书的私钥
+pub const KEY_FILE: &str = "certs/emacsvi.com.key";
+/// 没有指定配置文件时, 在当前目录查找 `strangers.toml` 或 `strangers.yaml`, 找不到时只使用环境变量
+const DEFAULT_FILE: &str = "strangers";
+/// 输出配置时需要隐藏的字段
+const SECRET_KEYS: &[&str] = &[
+    "secret_key",
+    "password",
+    "dsn",
+    "secret",
+    "url",
+    "access_key_secret",
+];
+
 impl Config {
     // https://github.com/mehcode/config-rs/blob/master/examples/hierarchical-env/settings.rs
     pub fn from_env() -> Result<Self, config::ConfigError> {
@@ -60,6 +402,295 @@ impl Config {
 
         cfg.try_deserialize()
     }
+
+    /// 读取配置文件(toml 或 yaml, 按扩展名识别), 环境变量(包括 `.env`)中的同名配置覆盖文件中的值
+    ///
+    /// # 参数
+    ///
+    /// * `file` - 配置文件, 为空时使用当前目录下可选的 `strangers.toml` 或 `strangers.yaml`
+    pub fn load(file: Option<&Path>) -> Result<Self, config::ConfigError> {
+        let file = match file {
+            Some(path) => config::File::from(path).required(true),
+            None => config::File::with_name(DEFAULT_FILE).required(false),
+        };
+        let cfg = config::Config::builder()
+            .add_source(file)
+            .add_source(config::Environment::default().try_parsing(true))
+            .build()?;
+
+        cfg.try_deserialize()
+    }
+
+    /// 检查所有的配置项, 返回发现的所有问题, 为空表示没有问题
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let mut check = |ok: bool, msg: String| {
+            if !ok {
+                problems.push(msg);
+            }
+        };
+
+        check(
+            self.web.addr.parse::<SocketAddr>().is_ok(),
+            format!("WEB.ADDR 不是合法的监听地址: {}", self.web.addr),
+        );
+        check(
+            !self.web.secret_key.is_empty(),
+            "WEB.SECRET_KEY 不能为空".to_string(),
+        );
+        check(
+            self.pg.host.is_some() || self.pg.hosts.is_some(),
+            "没有配置 PG.HOST".to_string(),
+        );
+        check(self.pg.dbname.is_some(), "没有配置 PG.DBNAME".to_string());
+
+        match self.cache.backend.as_str() {
+            "redis" => match &self.redis {
+                Some(redis) => check(
+                    ["redis://", "rediss://", "redis+unix://", "unix://"]
+                        .iter()
+                        .any(|scheme| redis.dsn.starts_with(scheme)),
+                    "REDIS.DSN 应该以 redis:// 开头".to_string(),
+                ),
+                None => check(false, "使用 redis 缓存时必须配置 REDIS.DSN".to_string()),
+            },
+            "sled" => {
+                check(
+                    !self.cache.sled_path.is_empty(),
+                    "CACHE.SLED_PATH 不能为空".to_string(),
+                );
+                check(
+                    self.cache.purge_interval > 0,
+                    "CACHE.PURGE_INTERVAL 必须大于 0".to_string(),
+                );
+            }
+            backend => check(
+                false,
+                format!("CACHE.BACKEND 不支持 {}, 可选 redis 或 sled", backend),
+            ),
+        }
+
+        check(
+            !self.session.prefix.is_empty() && !self.session.id_name.is_empty(),
+            "SESSION.PREFIX 和 SESSION.ID_NAME 不能为空".to_string(),
+        );
+        check(
+            self.session.expired > 0,
+            "SESSION.EXPIRED 必须大于 0".to_string(),
+        );
+
+        match self.captcha.provider.as_str() {
+            "none" | "image" => {}
+            "hcaptcha" => check(
+                !self.hcaptcha.site_key.is_empty() && !self.hcaptcha.secret_key.is_empty(),
+                "使用 hcaptcha 时必须配置 HCAPTCHA.SITE_KEY 和 HCAPTCHA.SECRET_KEY".to_string(),
+            ),
+            provider => check(
+                false,
+                format!(
+                    "CAPTCHA.PROVIDER 不支持 {}, 可选 none, image 或 hcaptcha",
+                    provider
+                ),
+            ),
+        }
+        check(self.captcha.ttl > 0, "CAPTCHA.TTL 必须大于 0".to_string());
+
+        check(
+            self.login.max_attempts > 0 && self.login.max_ip_attempts > 0,
+            "LOGIN.MAX_ATTEMPTS 和 LOGIN.MAX_IP_ATTEMPTS 必须大于 0".to_string(),
+        );
+        check(
+            self.login.window > 0 && self.login.lock_seconds > 0,
+            "LOGIN.WINDOW 和 LOGIN.LOCK_SECONDS 必须大于 0".to_string(),
+        );
+        check(
+            self.login.delay_ms <= self.login.max_delay_ms,
+            "LOGIN.DELAY_MS 不能大于 LOGIN.MAX_DELAY_MS".to_string(),
+        );
+
+        check(
+            self.page.min_size >= 1
+                && self.page.min_size <= self.page.default_size
+                && self.page.default_size <= self.page.max_size,
+            format!(
+                "分页大小应该满足 1 <= PAGE.MIN_SIZE({}) <= PAGE.DEFAULT_SIZE({}) <= PAGE.MAX_SIZE({})",
+                self.page.min_size, self.page.default_size, self.page.max_size
+            ),
+        );
+
+        if let Some(dir) = &self.upload_dir {
+            check(
+                Path::new(dir).is_dir(),
+                format!("UPLOAD_DIR 不是已经存在的目录: {}", dir),
+            );
+        }
+        if self.ssl_enable {
+            for file in [CERT_FILE, KEY_FILE] {
+                check(
+                    Path::new(file).is_file(),
+                    format!("启用了 SSL_ENABLE, 但是找不到证书文件: {}", file),
+                );
+            }
+        }
+
+        if let Err(err) = Plan::new(
+            &self.schedule,
+            self.sms.as_ref().and_then(|c| c.check_interval),
+        ) {
+            check(false, err);
+        }
+        // 渠道的安静时段
+        let mut quiet_hours = vec![];
+        if let Some(sms) = &self.sms {
+            quiet_hours.push(("SMS.QUIET_HOURS".to_string(), sms.quiet_hours.as_ref()));
+            check(
+                sms.expired_days.map(|d| d > 0).unwrap_or(true),
+                "SMS.EXPIRED_DAYS 必须大于 0".to_string(),
+            );
+            for phone in sms.phones.iter().flatten() {
+                check(
+                    is_phone(phone),
+                    format!("SMS.PHONES 中的手机号码格式错误: {}", phone),
+                );
+            }
+        }
+
+        check(
+            ["digest", "item"].contains(&self.notify.mode.as_str()),
+            format!(
+                "NOTIFY.MODE 不支持 {}, 可选 digest 或 item",
+                self.notify.mode
+            ),
+        );
+        for kind in &self.notify.critical {
+            check(
+                ["expired", "expiring", "low_stock"].contains(&kind.as_str()),
+                format!(
+                    "NOTIFY.CRITICAL 不支持 {}, 可选 expired, expiring 或 low_stock",
+                    kind
+                ),
+            );
+        }
+        check(
+            self.notify.max_attempts > 0 && self.notify.retry_delay > 0,
+            "NOTIFY.MAX_ATTEMPTS 和 NOTIFY.RETRY_DELAY 必须大于 0".to_string(),
+        );
+        if let Some(base_url) = &self.notify.base_url {
+            check(
+                base_url.starts_with("http://") || base_url.starts_with("https://"),
+                "NOTIFY.BASE_URL 应该以 http:// 或 https:// 开头".to_string(),
+            );
+        }
+        if let Some(email) = &self.notify.email {
+            quiet_hours.push((
+                "NOTIFY.EMAIL.QUIET_HOURS".to_string(),
+                email.quiet_hours.as_ref(),
+            ));
+            check(
+                !email.host.is_empty(),
+                "NOTIFY.EMAIL.HOST 不能为空".to_string(),
+            );
+            check(
+                ["tls", "starttls", "none"].contains(&email.tls.as_str()),
+                format!(
+                    "NOTIFY.EMAIL.TLS 不支持 {}, 可选 tls, starttls 或 none",
+                    email.tls
+                ),
+            );
+            check(
+                email.from.contains('@'),
+                format!("NOTIFY.EMAIL.FROM 不是合法的邮件地址: {}", email.from),
+            );
+            check(
+                !email.to.is_empty(),
+                "NOTIFY.EMAIL.TO 至少需要一个收件人".to_string(),
+            );
+            for to in email.to.iter().filter(|to| !to.contains('@')) {
+                check(
+                    false,
+                    format!("NOTIFY.EMAIL.TO 中的邮件地址格式错误: {}", to),
+                );
+            }
+        }
+        for (i, webhook) in self.notify.webhooks.iter().enumerate() {
+            check(
+                ["dingtalk", "wecom", "feishu", "generic"].contains(&webhook.kind.as_str()),
+                format!(
+                    "NOTIFY.WEBHOOKS.{}.KIND 不支持 {}, 可选 dingtalk, wecom, feishu 或 generic",
+                    i, webhook.kind
+                ),
+            );
+            check(
+                webhook.url.starts_with("http://") || webhook.url.starts_with("https://"),
+                format!("NOTIFY.WEBHOOKS.{}.URL 应该以 http:// 或 https:// 开头", i),
+            );
+            quiet_hours.push((
+                format!("NOTIFY.WEBHOOKS.{}.QUIET_HOURS", i),
+                webhook.quiet_hours.as_ref(),
+            ));
+        }
+        for (name, value) in quiet_hours {
+            if let Some(Err(err)) = value.map(|s| QuietHours::parse(s)) {
+                check(false, format!("{} {}", name, err));
+            }
+        }
+
+        problems
+    }
+
+    /// 生效的配置, 每行一项, 格式与环境变量相同, 密码等敏感信息以 `******` 代替
+    pub fn masked(&self) -> String {
+        let mut lines = vec![];
+        if let Ok(value) = serde_json::to_value(self) {
+            flatten("", &value, &mut lines);
+        }
+        lines.join("\n")
+    }
+}
+
+/// 大陆的手机号码
+pub fn is_phone(s: &str) -> bool {
+    s.len() == 11 && s.starts_with('1') && s.chars().all(|c| c.is_ascii_digit())
+}
+
+/// 把配置展开成 `PG.HOST=localhost` 的形式, 没有配置的项不输出
+fn flatten(prefix: &str, value: &Value, lines: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key.to_uppercase()
+                } else {
+                    format!("{}.{}", prefix, key.to_uppercase())
+                };
+                flatten(&key, value, lines);
+            }
+        }
+        Value::Null => {}
+        Value::Array(items) if items.iter().any(|v| v.is_object()) => {
+            for (i, value) in items.iter().enumerate() {
+                flatten(&format!("{}.{}", prefix, i), value, lines);
+            }
+        }
+        value => {
+            let name = prefix.rsplit('.').next().unwrap_or(prefix).to_lowercase();
+            let value = match value {
+                _ if SECRET_KEYS.contains(&name.as_str()) => "******".to_string(),
+                Value::String(s) => s.clone(),
+                Value::Array(items) => items
+                    .iter()
+                    .map(|v| {
+                        v.as_str()
+                            .map(|s| s.to_string())
+                            .unwrap_or_else(|| v.to_string())
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                value => value.to_string(),
+            };
+            lines.push(format!("{}={}", prefix, value));
+        }
+    }
 }
 
 mod tests {
@@ -74,6 +705,100 @@ mod tests {
         assert_eq!(cfg.web.addr, "0.0.0.0:9528".to_string());
     }
 
+    #[test]
+    fn test_page_config() {
+        let cfg = super::PageConfig {
+            default_size: 30,
+            min_size: 10,
+            max_size: 100,
+        };
+        assert_eq!(cfg.clamp(None), 30);
+        assert_eq!(cfg.clamp(Some(5)), 10);
+        assert_eq!(cfg.clamp(Some(500)), 100);
+        assert_eq!(cfg.choices(), vec![10, 20, 30, 50, 100]);
+    }
+
+    #[test]
+    fn test_validate_and_mask() {
+        let cfg: Config = serde_json::from_value(serde_json::json!({
+            "web": {"addr": "0.0.0.0:99999", "secret_key": "nn8fktkr"},
+            "pg": {"host": "localhost", "dbname": "dev_strangers", "password": "pg-pass"},
+            "redis": {"dsn": "redis://:redis-pass@localhost:6379"},
+            "session": {"prefix": "strangers:session", "id_name": "strangers_session", "expired": 0},
+            "hcaptcha": {"site_key": "", "secret_key": ""},
+            "captcha": {"provider": "hcaptcha"},
+            "page": {"default_size": 5, "min_size": 10, "max_size": 200},
+            "sms": {"phones": ["18280835550", "1828083555"]},
+            "notify": {"webhooks": [{"kind": "dingtalk", "url": "https://oapi.dingtalk.com/robot/send?access_token=abc", "secret": "SECabc"}]},
+            "ssl_enable": false
+        }))
+        .unwrap();
+
+        let problems = cfg.validate();
+        assert_eq!(problems.len(), 5, "{:#?}", problems);
+        assert!(problems[0].contains("WEB.ADDR"));
+        assert!(problems.iter().any(|p| p.contains("1828083555")));
+
+        let masked = cfg.masked();
+        assert!(masked.contains("PG.HOST=localhost"));
+        assert!(masked.contains("PG.PASSWORD=******"));
+        assert!(masked.contains("REDIS.DSN=******"));
+        assert!(masked.contains("WEB.SECRET_KEY=******"));
+        assert!(masked.contains("SMS.PHONES=18280835550 1828083555"));
+        assert!(masked.contains("NOTIFY.WEBHOOKS.0.KIND=dingtalk"));
+        assert!(masked.contains("NOTIFY.WEBHOOKS.0.SECRET=******"));
+        assert!(!masked.contains("pg-pass"));
+        assert!(!masked.contains("access_token"));
+    }
+
+    #[test]
+    fn test_sms_phones() {
+        let cfg: super::SmsConfig =
+            serde_json::from_value(serde_json::json!({"phones": "18280835550, 18180815129"}))
+                .unwrap();
+        assert_eq!(
+            cfg.phones,
+            Some(vec!["18280835550".to_string(), "18180815129".to_string()])
+        );
+        let cfg: super::SmsConfig =
+            serde_json::from_value(serde_json::json!({"phones": 18280835550u64})).unwrap();
+        assert_eq!(cfg.phones, Some(vec!["18280835550".to_string()]));
+        let cfg: super::SmsConfig = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(cfg.phones, None);
+    }
+
+    #[test]
+    fn test_schedule_config() {
+        let cfg: super::ScheduleConfig = serde_json::from_value(serde_json::json!({
+            "cron": "30 9 * * Mon-Fri",
+            "holidays": "2022-10-03, 2022-10-04"
+        }))
+        .unwrap();
+        assert_eq!(cfg.timezone, "Asia/Shanghai");
+        assert_eq!(cfg.quiet_hours, "18:00-08:00");
+        assert_eq!(cfg.holidays, vec!["2022-10-03", "2022-10-04"]);
+
+        let cfg: Config = serde_json::from_value(serde_json::json!({
+            "web": {"addr": "0.0.0.0:9528", "secret_key": "nn8fktkr"},
+            "pg": {"host": "localhost", "dbname": "dev_strangers"},
+            "cache": {"backend": "sled"},
+            "session": {"prefix": "strangers:session", "id_name": "strangers_session", "expired": 3600},
+            "hcaptcha": {"site_key": "", "secret_key": ""},
+            "sms": {"check_interval": 0, "quiet_hours": ""},
+            "notify": {"webhooks": [{"url": "http://127.0.0.1/alert", "quiet_hours": "22:00"}]},
+            "schedule": {"timezone": "Asia/Beijing"},
+            "ssl_enable": false
+        }))
+        .unwrap();
+        assert_eq!(
+            cfg.validate(),
+            vec![
+                "SCHEDULE.TIMEZONE 不是合法的时区: Asia/Beijing".to_string(),
+                "NOTIFY.WEBHOOKS.0.QUIET_HOURS 安静时段的格式应该是 18:00-08:00: 22:00".to_string(),
+            ]
+        );
+    }
+
     #[derive(Debug, Default, serde::Deserialize, PartialEq)]
     struct AppConfig {
         list: Vec<String>,
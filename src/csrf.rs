@@ -0,0 +1,173 @@
+//! 防跨站请求伪造 (CSRF)
+//!
+//! 登录时生成随机 token 保存在 session 中, 同时写入一个脚本可以读取的 cookie,
+//! 页面提交表单时由 `static/backend/csrf.js` 带上. 使用 cookie 登录的非 GET 请求必须带上与 session 一致的 token:
+//!
+//! * 请求头 `X-CSRF-Token`, 用于 ajax 请求
+//! * 表单字段 `csrf_token`
+//! * 上传文件的表单放在地址的 `csrf_token` 参数中, 避免为了校验把整个文件读入内存
+//!
+//! 使用 API token 认证的请求不依赖 cookie, 不需要 CSRF token.
+
+use crate::config::SessionConfig;
+use crate::error::AppError;
+use crate::Result;
+use axum::body::{Bytes, HttpBody};
+use axum::extract::{FromRequest, RequestParts};
+use axum::http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::{Method, Request};
+use axum::BoxError;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+/// 表单字段和地址参数的名称
+pub const FIELD: &str = "csrf_token";
+/// 请求头的名称
+pub const HEADER: &str = "x-csrf-token";
+/// token 的长度
+const TOKEN_LEN: usize = 32;
+/// 为了读取 token 允许读入内存的表单大小
+const MAX_FORM_SIZE: u64 = 1024 * 1024;
+
+/// 生成随机 token, 只包含字母和数字, 放在地址和表单中都不需要编码
+pub fn generate() -> String {
+    thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(TOKEN_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// 提供给页面脚本读取 token 的 cookie 名称
+pub fn cookie_name(cfg: &SessionConfig) -> String {
+    format!("{}_csrf", &cfg.id_name)
+}
+
+/// 不修改数据的请求不需要校验
+pub fn is_safe_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// 比较 token, 耗时与内容无关, 没有 token 的 session 一律不通过
+pub fn token_matches(expected: &str, actual: &str) -> bool {
+    if expected.is_empty() || expected.len() != actual.len() {
+        return false;
+    }
+    expected
+        .bytes()
+        .zip(actual.bytes())
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+/// 从 `a=1&csrf_token=xxx` 格式的字符串中取出 token
+fn from_urlencoded(s: &str) -> Option<String> {
+    s.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == FIELD)
+        .map(|(_, v)| v.to_string())
+}
+
+/// 读取普通表单的内容, 读完后放回请求中, 后面的 `Form` 还能正常使用
+async fn read_form<B>(req: &mut RequestParts<B>) -> Result<Option<Bytes>>
+where
+    B: HttpBody + From<Bytes> + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    let headers = match req.headers() {
+        Some(headers) => headers,
+        None => return Ok(None),
+    };
+    let is_form = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("application/x-www-form-urlencoded"))
+        .unwrap_or(false);
+    if !is_form {
+        return Ok(None);
+    }
+    let size = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if !matches!(size, Some(size) if size <= MAX_FORM_SIZE) {
+        return Err(AppError::invalid_argument("表单内容过大"));
+    }
+    let body = match req.body_mut() {
+        Some(body) => std::mem::replace(body, B::from(Bytes::new())),
+        None => return Ok(None),
+    };
+    let bytes = Bytes::from_request(&mut RequestParts::new(Request::new(body)))
+        .await
+        .map_err(|_| AppError::invalid_argument("读取表单失败"))?;
+    if let Some(body) = req.body_mut() {
+        *body = B::from(bytes.clone());
+    }
+    Ok(Some(bytes))
+}
+
+/// 校验请求中的 token
+pub async fn verify<B>(req: &mut RequestParts<B>, expected: &str) -> Result<()>
+where
+    B: HttpBody + From<Bytes> + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    let mut token = req
+        .headers()
+        .and_then(|h| h.get(HEADER))
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+        .or_else(|| req.uri().query().and_then(from_urlencoded));
+    if token.is_none() {
+        token = read_form(req)
+            .await?
+            .and_then(|bytes| from_urlencoded(&String::from_utf8_lossy(&bytes)));
+    }
+    match token {
+        Some(token) if token_matches(expected, &token) => Ok(()),
+        _ => Err(AppError::forbidden(
+            "页面已过期或请求来源不正确, 请刷新页面后重试",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let token = generate();
+        assert_eq!(token.len(), TOKEN_LEN);
+        assert!(token.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(token, generate());
+    }
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("abc", "abc"));
+        assert!(!token_matches("abc", "abd"));
+        assert!(!token_matches("abc", "ab"));
+        assert!(!token_matches("", ""));
+    }
+
+    #[test]
+    fn test_from_urlencoded() {
+        assert_eq!(
+            from_urlencoded("name=a&csrf_token=xyz&b=2"),
+            Some("xyz".to_string())
+        );
+        assert_eq!(from_urlencoded("csrf_token="), Some("".to_string()));
+        assert_eq!(from_urlencoded("name=a"), None);
+        assert_eq!(from_urlencoded("my_csrf_token=a"), None);
+    }
+
+    #[test]
+    fn test_is_safe_method() {
+        assert!(is_safe_method(&Method::GET));
+        assert!(!is_safe_method(&Method::POST));
+        assert!(!is_safe_method(&Method::DELETE));
+    }
+}
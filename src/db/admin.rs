@@ -1,77 +1,224 @@
 use crate::db::pagination::Pagination;
-use crate::db::select_stmt::SelectStmt;
-use crate::db::PAGE_SIZE;
+use crate::db::query::{Direction, Filter, Select, Update};
 use crate::error::AppError;
-use crate::form::{CreateAdmin, UpdateAdmin};
-use crate::model::{Admin, AdminID};
+use crate::form::CreateAdmin;
+use crate::model::{Admin, AdminID, AdminPageSize, AdminScope};
 use crate::Result;
-use tokio_postgres::types::ToSql;
-use tokio_postgres::Client;
+use deadpool_postgres::Client;
 
-pub async fn find_by_condition(
-    client: &Client,
-    condition: &str,
-    args: &[&(dyn ToSql + Sync)],
-) -> Result<Admin> {
-    let sql = SelectStmt::builder()
-        .table("admin")
-        .fields("id, username, password, is_sys, is_del")
-        .condition(Some(condition))
-        .limit(Some(1))
+/// 管理员表的字段
+const FIELDS: &str = "id, username, password, is_sys, is_del, role, must_change_password, is_disabled, totp_secret, totp_enabled, totp_last_step";
+
+pub async fn find_by_condition(client: &Client, filter: Filter) -> Result<Admin> {
+    let stmt = Select::table("admin")
+        .fields(FIELDS)
+        .filter(filter)
+        .limit(1)
         .build();
-    Ok(super::query_one(client, &sql, args, Some("不存在的管理员")).await?)
+    Ok(super::query_one(client, &stmt.sql, &stmt.params(), Some("不存在的管理员")).await?)
 }
 pub async fn find(client: &Client, username: &str) -> Result<Admin> {
-    find_by_condition(client, "username=$1 AND is_del=false", &[&username]).await
+    let filter = Filter::eq("username", username.to_string()).and(Filter::eq("is_del", false));
+    find_by_condition(client, filter).await
 }
 pub async fn find_by_id(client: &Client, id: i32) -> Result<Admin> {
-    find_by_condition(client, "id=$1 AND is_del=false", &[&id]).await
+    find_by_condition(
+        client,
+        Filter::eq("id", id).and(Filter::eq("is_del", false)),
+    )
+    .await
 }
 
 pub async fn select(
     client: &Client,
-    condition: Option<&str>,
-    args: &[&(dyn ToSql + Sync)],
+    filter: Filter,
     page: u32,
+    page_size: u32,
 ) -> Result<Pagination<Vec<Admin>>> {
-    let sql = SelectStmt::builder()
-        .table("admin")
-        .fields("id, username, password, is_sys, is_del")
-        .condition(condition)
-        .limit(Some(PAGE_SIZE))
-        .offset(Some(page * PAGE_SIZE as u32))
-        .build();
-    let count_sql = SelectStmt::builder()
-        .table("admin")
-        .fields("count(*)")
-        .condition(condition)
+    let select = Select::table("admin")
+        .fields(FIELDS)
+        .filter(filter)
+        .order_by("id", Direction::Asc);
+    Ok(super::select(client, select, page, page_size).await?)
+}
+
+/// 管理员记住的每页记录数, 没有设置过时为空
+pub async fn page_size(client: &Client, id: i32) -> Result<Option<i32>> {
+    let stmt = Select::table("admin")
+        .fields("page_size")
+        .filter(Filter::eq("id", id))
         .build();
-    Ok(super::select(client, &sql, &count_sql, args, page).await?)
+    let row: AdminPageSize =
+        super::query_one(client, &stmt.sql, &stmt.params(), Some("不存在的管理员")).await?;
+    Ok(row.page_size)
+}
+
+/// 记住管理员选择的每页记录数
+pub async fn update_page_size(client: &Client, id: i32, page_size: i32) -> Result<u64> {
+    let stmt = Update::table("admin")
+        .set("page_size", page_size)
+        .filter(Filter::eq("id", id))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await
 }
 
 pub async fn create(client: &Client, ca: CreateAdmin) -> Result<AdminID> {
-    let sql = "SELECT COUNT(*) FROM admin WHERE username=$1";
-    let c = super::count(client, sql, &[&ca.username]).await?;
+    let stmt = Select::table("admin")
+        .filter(Filter::eq("username", ca.username.clone()))
+        .count();
+    let c = super::count(client, &stmt.sql, &stmt.params()).await?;
     if c > 0 {
         return Err(AppError::is_exists("管理员名称已存在"));
     }
-    let sql = "INSERT INTO admin (username, password) VALUES ($1, $2) RETURNING id";
+    let sql = "INSERT INTO admin (username, password, role) VALUES ($1, $2, $3) RETURNING id";
     Ok(super::query_one(
         client,
         &sql,
-        &[&ca.username, &ca.password],
+        &[&ca.username, &ca.password, &ca.role],
         Some("添加管理员失败"),
     )
     .await?)
 }
 
-pub async fn update(client: &Client, ua: UpdateAdmin) -> Result<u64> {
-    let sql = "UPDATE admin SET password=$1 WHERE id=$2";
-    super::execute(client, sql, &[&ua.password, &ua.id]).await
+/// 修改密码, `must_change` 为 true 表示这是管理员重置的临时密码
+pub async fn update_password(
+    client: &Client,
+    id: i32,
+    hashed_password: &str,
+    must_change: bool,
+) -> Result<u64> {
+    let stmt = Update::table("admin")
+        .set("password", hashed_password.to_string())
+        .set("must_change_password", must_change)
+        .filter(Filter::eq("id", id))
+        .filter(Filter::eq("is_del", false))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await
+}
+
+/// 设为系统账号, 用于命令行添加的第一个管理员
+pub async fn set_sys(client: &Client, id: i32) -> Result<u64> {
+    let stmt = Update::table("admin")
+        .set("is_sys", true)
+        .filter(Filter::eq("id", id))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await
+}
+
+/// 禁用或启用账号, 系统账号不能被禁用
+pub async fn disable_or_enable(client: &Client, id: i32, is_disabled: bool) -> Result<u64> {
+    let stmt = Update::table("admin")
+        .set("is_disabled", is_disabled)
+        .filter(Filter::eq("id", id))
+        .filter(Filter::eq("is_sys", false))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await
 }
 
 pub async fn del_or_recover(client: &Client, id: i32, is_del_opt: bool) -> Result<()> {
-    let sql = "UPDATE admin SET is_del=$1 WHERE id=$2 AND is_sys=false";
-    super::execute(client, sql, &[&is_del_opt, &id]).await?;
+    let stmt = Update::table("admin")
+        .set("is_del", is_del_opt)
+        .filter(Filter::eq("id", id))
+        .filter(Filter::eq("is_sys", false))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await?;
+    Ok(())
+}
+
+/// 管理员被限定的类目, 为空表示不限制
+pub async fn categories(client: &Client, admin_id: i32) -> Result<Vec<String>> {
+    let stmt = Select::table("admin_scope")
+        .fields("category")
+        .filter(Filter::eq("admin_id", admin_id))
+        .order_by("category", Direction::Asc)
+        .build();
+    let scopes: Vec<AdminScope> = super::fetch(client, &stmt).await?;
+    Ok(scopes.into_iter().map(|s| s.category).collect())
+}
+
+/// 修改管理员的角色及限定的类目, 系统账号的角色不能修改
+pub async fn update_role(
+    client: &Client,
+    id: i32,
+    role: &str,
+    categories: &Vec<String>,
+) -> Result<()> {
+    let stmt = Update::table("admin")
+        .set("role", role.to_string())
+        .filter(Filter::eq("id", id))
+        .filter(Filter::eq("is_sys", false))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await?;
+    // 用一条语句完成删除和插入, 保证类目要么全部更新要么都不更新
+    let sql = "WITH d AS (DELETE FROM admin_scope WHERE admin_id=$1) INSERT INTO admin_scope (admin_id, category) SELECT $1, unnest($2::varchar[])";
+    super::execute(client, sql, &[&id, categories]).await?;
+    Ok(())
+}
+
+/// 启用二步验证, `step` 是确认时使用的动态码所在的周期
+pub async fn enable_totp(client: &Client, id: i32, secret: &str, step: i64) -> Result<u64> {
+    let stmt = Update::table("admin")
+        .set("totp_secret", secret.to_string())
+        .set("totp_enabled", true)
+        .set("totp_last_step", step)
+        .filter(Filter::eq("id", id))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await
+}
+
+/// 关闭二步验证, 同时删除恢复码
+pub async fn disable_totp(client: &Client, id: i32) -> Result<()> {
+    let stmt = Update::table("admin")
+        .set_sql("totp_secret", "NULL", vec![])
+        .set("totp_enabled", false)
+        .set("totp_last_step", 0i64)
+        .filter(Filter::eq("id", id))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await?;
+    let sql = "DELETE FROM admin_recovery_code WHERE admin_id=$1";
+    super::execute(client, sql, &[&id]).await?;
+    Ok(())
+}
+
+/// 记录使用过的动态码周期, 周期不大于上次使用的周期时返回 false, 表示动态码被重复使用
+pub async fn use_totp_step(client: &Client, id: i32, step: i64) -> Result<bool> {
+    let stmt = Update::table("admin")
+        .set("totp_last_step", step)
+        .filter(Filter::eq("id", id))
+        .filter(Filter::lt("totp_last_step", step))
+        .build()?;
+    let n = super::execute(client, &stmt.sql, &stmt.params()).await?;
+    Ok(n == 1)
+}
+
+/// 用新的一组恢复码替换旧的
+pub async fn replace_recovery_codes(
+    client: &Client,
+    id: i32,
+    code_hashes: &Vec<String>,
+) -> Result<()> {
+    let sql = "WITH d AS (DELETE FROM admin_recovery_code WHERE admin_id=$1) INSERT INTO admin_recovery_code (admin_id, code_hash) SELECT $1, unnest($2::varchar[])";
+    super::execute(client, sql, &[&id, code_hashes]).await?;
     Ok(())
 }
+
+/// 使用恢复码, 恢复码不存在或已使用时返回 false
+pub async fn use_recovery_code(client: &Client, id: i32, code_hash: &str) -> Result<bool> {
+    let stmt = Update::table("admin_recovery_code")
+        .set_sql("used_at", "now()", vec![])
+        .filter(Filter::eq("admin_id", id))
+        .filter(Filter::eq("code_hash", code_hash.to_string()))
+        .filter(Filter::is_null("used_at"))
+        .build()?;
+    let n = super::execute(client, &stmt.sql, &stmt.params()).await?;
+    Ok(n > 0)
+}
+
+/// 剩余可用的恢复码数量
+pub async fn unused_recovery_codes(client: &Client, id: i32) -> Result<i64> {
+    let stmt = Select::table("admin_recovery_code")
+        .filter(Filter::eq("admin_id", id))
+        .filter(Filter::is_null("used_at"))
+        .count();
+    super::count(client, &stmt.sql, &stmt.params()).await
+}
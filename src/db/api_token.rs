@@ -0,0 +1,106 @@
+use crate::db::query::{Direction, Filter, Select, Update};
+use crate::model::{ApiToken, ApiTokenID, TokenIdentity};
+use crate::Result;
+use deadpool_postgres::Client;
+
+/// 表名
+const TABLE_NAME: &str = "api_token";
+
+/// 创建 token, 返回新 token 的 ID
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `admin_id` - token 所属的管理员
+/// * `name` - token 名称
+/// * `token_hash` - token 的摘要
+/// * `scope` - 权限范围
+/// * `expires_at` - 过期时间, `None` 表示永不过期
+pub async fn create(
+    client: &Client,
+    admin_id: i32,
+    name: &str,
+    token_hash: &str,
+    scope: &str,
+    expires_at: Option<chrono::DateTime<chrono::Local>>,
+) -> Result<ApiTokenID> {
+    let sql = format!(
+        "INSERT INTO {} (admin_id, name, token_hash, scope, expires_at) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        TABLE_NAME
+    );
+    super::query_one(
+        client,
+        &sql,
+        &[&admin_id, &name, &token_hash, &scope, &expires_at],
+        Some("创建 token 失败"),
+    )
+    .await
+}
+
+/// 某个管理员的所有 token
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `admin_id` - 管理员 ID
+pub async fn select_by_admin(client: &Client, admin_id: i32) -> Result<Vec<ApiToken>> {
+    let stmt = Select::table(TABLE_NAME)
+        .fields("id, admin_id, name, scope, expires_at, last_used_at, is_revoked, created_at")
+        .filter(Filter::eq("admin_id", admin_id))
+        .order_by("id", Direction::Desc)
+        .build();
+    super::fetch(client, &stmt).await
+}
+
+/// 根据 token 的摘要查找有效的 token: 未撤销、未过期且所属的管理员未被删除
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `token_hash` - token 的摘要
+pub async fn find_valid(client: &Client, token_hash: &str) -> Result<TokenIdentity> {
+    let stmt = Select::table("api_token AS t")
+        .fields("t.id, t.admin_id, a.username, a.role, t.scope")
+        .join("admin AS a", "a.id=t.admin_id")
+        .filter(Filter::eq("t.token_hash", token_hash.to_string()))
+        .filter(Filter::eq("t.is_revoked", false))
+        .filter(Filter::sql(
+            "t.expires_at IS NULL OR t.expires_at > now()",
+            vec![],
+        ))
+        .filter(Filter::eq("a.is_del", false))
+        .filter(Filter::eq("a.is_disabled", false))
+        .build();
+    super::query_one(client, &stmt.sql, &stmt.params(), Some("无效的 token")).await
+}
+
+/// 记录 token 的最后使用时间
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `id` - token ID
+pub async fn touch(client: &Client, id: i32) -> Result<u64> {
+    let stmt = Update::table(TABLE_NAME)
+        .set_sql("last_used_at", "now()", vec![])
+        .filter(Filter::eq("id", id))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await
+}
+
+/// 撤销 token, 只能撤销自己的 token
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `id` - token ID
+/// * `admin_id` - 管理员 ID
+pub async fn revoke(client: &Client, id: i32, admin_id: i32) -> Result<bool> {
+    let stmt = Update::table(TABLE_NAME)
+        .set("is_revoked", true)
+        .filter(Filter::eq("id", id))
+        .filter(Filter::eq("admin_id", admin_id))
+        .build()?;
+    let result = super::execute(client, &stmt.sql, &stmt.params()).await?;
+    Ok(result == 1)
+}
@@ -0,0 +1,38 @@
+use crate::db::pagination::Pagination;
+use crate::db::query::{Direction, Filter, Select};
+use crate::model::LoginEvent;
+use crate::Result;
+use deadpool_postgres::Client;
+
+/// 表名
+const TABLE_NAME: &str = "login_event";
+
+/// 记录一次登录失败
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `username` - 尝试登录的用户名
+/// * `ip` - 客户端 IP
+/// * `reason` - 失败原因
+pub async fn create(client: &Client, username: &str, ip: &str, reason: &str) -> Result<u64> {
+    let sql = format!(
+        "INSERT INTO {} (username, ip, reason) VALUES ($1, $2, $3)",
+        TABLE_NAME
+    );
+    super::execute(client, &sql, &[&username, &ip, &reason]).await
+}
+
+/// 分页查询登录事件, 最新的在前
+pub async fn select(
+    client: &Client,
+    filter: Filter,
+    page: u32,
+    page_size: u32,
+) -> Result<Pagination<Vec<LoginEvent>>> {
+    let select = Select::table(TABLE_NAME)
+        .fields("id, username, ip, reason, created_at")
+        .filter(filter)
+        .order_by("id", Direction::Desc);
+    super::select(client, select, page, page_size).await
+}
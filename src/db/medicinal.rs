@@ -1,72 +1,366 @@
+use crate::arg::{Cursor, MedicinalBackendQueryArg, OrderKey};
+use crate::cache::Cache;
+use crate::db::execute;
 use crate::db::pagination::Pagination;
-use crate::db::select_stmt::SelectStmt;
-use crate::db::{execute, PAGE_SIZE};
-use crate::error::{AppError, AppErrorType};
+use crate::db::query::{param, Direction, Filter, Param, Select, Stmt, Update};
+use crate::error::AppError;
 use crate::form::{CreateMedicinal, UpdateMedicinal};
-use crate::model::{Category, MedicinalID, MedicinalList};
-use crate::Result;
-use chrono::Local;
-use std::str::FromStr;
-use tokio_postgres::types::ToSql;
-use tokio_postgres::Client;
-use tracing::debug;
-use tracing::field::debug;
+use crate::model::{Category, MedicinalID, MedicinalList, MedicinalName};
+use crate::{hanzi, Result};
+use chrono::{DateTime, Local};
+use deadpool_postgres::Client;
+use tokio_pg_mapper::FromTokioPostgresRow;
+use tracing::{debug, warn};
 
 /// 表名
 const TABLE_NAME: &str = "medicinal";
+/// 搜索时使用的字段, 另外加上匹配的字段 `matched`
+const COLUMNS: &str = "id, category, name, batch_number, spec, count, validity, is_del, created_at";
+/// 列表使用的字段, 不搜索时没有匹配的字段
+const FIELDS: &str =
+    "id, category, name, batch_number, spec, count, validity, is_del, created_at, NULL::varchar AS matched";
+/// 关键字匹配的字段: 先看包含关键字或拼音的字段, 再看模糊匹配的字段
+const MATCHED: &str = "CASE WHEN name ILIKE ? OR name_pinyin LIKE ? OR name_initials LIKE ? THEN 'name' \
+    WHEN batch_number ILIKE ? THEN 'batch_number' WHEN spec ILIKE ? THEN 'spec' \
+    WHEN category ILIKE ? OR category_pinyin LIKE ? OR category_initials LIKE ? THEN 'category' \
+    WHEN ? <% name THEN 'name' WHEN ? <% batch_number THEN 'batch_number' WHEN ? <% spec THEN 'spec' \
+    ELSE 'category' END AS matched";
+/// 相关度: 名称包含关键字或拼音的排在前面, 其次按各字段与关键字的相似度
+const RELEVANCE: &str = "CASE WHEN name ILIKE ? OR name_pinyin LIKE ? OR name_initials LIKE ? THEN 1 ELSE 0 END \
+    + GREATEST(word_similarity(?, name), word_similarity(?, batch_number), word_similarity(?, spec), word_similarity(?, category))";
+/// 模糊匹配, 使用 pg_trgm 的 word_similarity, 阈值为 `pg_trgm.word_similarity_threshold`
+const FUZZY: &str = "? <% name OR ? <% batch_number OR ? <% spec OR ? <% category";
+
+/// 类目列表在缓存中的键
+const CATEGORIES_KEY: &str = "strangers:medicinal:categories";
+/// 类目列表缓存的有效期, 单位秒. 添加或修改药品时会让缓存失效,
+/// 其他进程(如命令行导入)使用 sled 缓存时不能通知到这里, 最多过这么久就能看到新的类目
+const CATEGORIES_TTL: usize = 300;
+
+/// 列表查询, 排序字段相同时按 ID 排序, 保证翻页时顺序稳定
+///
+/// # 参数
+///
+/// * `filter` - 条件
+/// * `order` - 排序字段
+/// * `desc` - 是否倒序
+fn list(filter: Filter, order_key: OrderKey, desc: bool) -> Select {
+    let select = Select::table(TABLE_NAME).fields(FIELDS).filter(filter);
+    order(select, order_key, desc)
+}
+
+/// 按指定字段排序, 排序字段相同时按 ID 排序
+fn order(select: Select, order_key: OrderKey, desc: bool) -> Select {
+    let dir = if desc {
+        Direction::Desc
+    } else {
+        Direction::Asc
+    };
+    select.order_by(order_key.column(), dir).order_by("id", dir)
+}
 
 /// 获取药品列表,返回满足条件的药品列表及分页信息或者包含AppError的错误信息
 ///
 /// # 参数
 ///
 /// * `client` - 数据库连接对象
-/// * `condition` - 条件
-/// * `args` - 条件对应的参数
+/// * `filter` - 条件
+/// * `order` - 排序字段
+/// * `desc` - 是否倒序
 /// * `page` - 当前分页的页码
+/// * `page_size` - 每页的记录数
 pub async fn select(
     client: &Client,
-    condition: &str,
-    args: &[&(dyn ToSql + Sync)],
+    filter: Filter,
+    order: OrderKey,
+    desc: bool,
     page: u32,
+    page_size: u32,
 ) -> Result<Pagination<Vec<MedicinalList>>> {
-    let sql = SelectStmt::builder()
-        .table(TABLE_NAME)
-        .fields("id, category, name, batch_number, spec, count, validity, is_del")
-        .condition(Some(condition))
-        .order(Some("validity ASC"))
-        .limit(Some(PAGE_SIZE))
-        .offset(Some(page * PAGE_SIZE as u32))
-        .build();
-    let count_sql = SelectStmt::builder()
-        .table(TABLE_NAME)
-        .fields("COUNT(*)")
-        .condition(Some(condition))
+    Ok(super::select(client, list(filter, order, desc), page, page_size).await?)
+}
+
+/// 搜索关键字的几种形式
+struct Keyword {
+    /// 原样的关键字, 用于模糊匹配
+    raw: String,
+    /// `%关键字%`
+    pattern: String,
+    /// `%拼音%`, 只有字母和数字的关键字才有
+    spelled: Option<String>,
+}
+
+impl Keyword {
+    fn new(keyword: &str) -> Self {
+        Self {
+            raw: keyword.to_string(),
+            pattern: format!("%{}%", keyword),
+            spelled: hanzi::keyword(keyword).map(|s| format!("%{}%", s)),
+        }
+    }
+
+    /// 任意字段包含关键字, 或者名称、类目的拼音包含关键字, 或者模糊匹配
+    fn filter(&self) -> Filter {
+        let mut any = vec![];
+        for column in ["name", "batch_number", "spec", "category"] {
+            any.push(Filter::ilike(column, self.pattern.clone()));
+        }
+        if let Some(spelled) = &self.spelled {
+            for column in [
+                "name_pinyin",
+                "name_initials",
+                "category_pinyin",
+                "category_initials",
+            ] {
+                any.push(Filter::like(column, spelled.clone()));
+            }
+        }
+        any.push(Filter::sql(FUZZY, vec![param(self.raw.clone()); 4]));
+        Filter::any(any)
+    }
+
+    /// [`MATCHED`] 的参数, 没有拼音时以 NULL 比较, 结果为不匹配
+    fn matched_params(&self) -> Vec<Param> {
+        let raw = param(self.raw.clone());
+        let pattern = param(self.pattern.clone());
+        let spelled = param(self.spelled.clone());
+        vec![
+            pattern.clone(),
+            spelled.clone(),
+            spelled.clone(),
+            pattern.clone(),
+            pattern.clone(),
+            pattern,
+            spelled.clone(),
+            spelled,
+            raw.clone(),
+            raw.clone(),
+            raw,
+        ]
+    }
+
+    /// [`RELEVANCE`] 的参数
+    fn relevance_params(&self) -> Vec<Param> {
+        let raw = param(self.raw.clone());
+        let spelled = param(self.spelled.clone());
+        vec![
+            param(self.pattern.clone()),
+            spelled.clone(),
+            spelled,
+            raw.clone(),
+            raw.clone(),
+            raw.clone(),
+            raw,
+        ]
+    }
+}
+
+/// 根据列表页的查询参数生成查询条件
+///
+/// # 参数
+///
+/// * `args` - 列表页的查询参数
+/// * `keyword` - 搜索关键字
+/// * `categories` - 允许查看的类目, 为空表示不限制
+fn filter_by_arg(
+    args: &MedicinalBackendQueryArg,
+    keyword: Option<&Keyword>,
+    categories: &[String],
+) -> Filter {
+    let mut filter = Filter::eq("is_del", args.is_del())
+        .and(Filter::like("category", format!("%{}%", args.category())));
+    if let Some(keyword) = keyword {
+        filter = filter.and(keyword.filter());
+    }
+    // 处理查询日期(月份)
+    let today = Local::today().naive_local();
+    filter = match args.expired() {
+        0 => filter,
+        // 表示已经过期的数据
+        1 => filter.and(Filter::le("validity", today)),
+        // 表示between 当前时间到 (q_expired-1)*30 天之间的数据
+        q_expired => filter.and(Filter::between(
+            "validity",
+            today,
+            today + chrono::Duration::days((q_expired - 1) as i64 * 30),
+        )),
+    };
+    if !categories.is_empty() {
+        filter = filter.and(Filter::in_list("category", categories.to_vec()));
+    }
+    filter
+}
+
+/// 根据列表页的查询参数生成不含排序的查询, 有关键字时标出匹配的字段
+///
+/// # 参数
+///
+/// * `args` - 列表页的查询参数
+/// * `keyword` - 搜索关键字
+/// * `categories` - 允许查看的类目, 为空表示不限制
+fn search_by_arg(
+    args: &MedicinalBackendQueryArg,
+    keyword: Option<&Keyword>,
+    categories: &[String],
+) -> Select {
+    let filter = filter_by_arg(args, keyword, categories);
+    let select = match keyword {
+        None => Select::table(TABLE_NAME).fields(FIELDS),
+        Some(keyword) => Select::table(TABLE_NAME)
+            .fields(COLUMNS)
+            .column(MATCHED, keyword.matched_params()),
+    };
+    select.filter(filter)
+}
+
+/// 根据列表页的查询参数生成查询, 有关键字并且没有指定排序时按相关度排序
+///
+/// # 参数
+///
+/// * `args` - 列表页的查询参数
+/// * `categories` - 允许查看的类目, 为空表示不限制
+fn select_by_arg_stmt(args: &MedicinalBackendQueryArg, categories: &[String]) -> Select {
+    let keyword = Some(args.keyword())
+        .filter(|k| !k.is_empty())
+        .map(Keyword::new);
+    let select = search_by_arg(args, keyword.as_ref(), categories);
+    match keyword {
+        Some(keyword) if args.is_ranked() => select
+            .order_by_sql(RELEVANCE, keyword.relevance_params(), Direction::Desc)
+            .order_by("validity", Direction::Asc)
+            .order_by("id", Direction::Asc),
+        _ => order(select, args.order(), args.desc()),
+    }
+}
+
+/// 按列表页的查询参数分页获取药品列表, 供后台列表页和 API 共用
+///
+/// 指定了游标时按 (有效期, ID) 翻页, 否则按页码翻页
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `args` - 列表页的查询参数
+/// * `categories` - 允许查看的类目, 为空表示不限制
+/// * `page_size` - 每页的记录数
+pub async fn select_by_arg(
+    client: &Client,
+    args: &MedicinalBackendQueryArg,
+    categories: &[String],
+    page_size: u32,
+) -> Result<Pagination<Vec<MedicinalList>>> {
+    match args.cursor()? {
+        None => {
+            let select = select_by_arg_stmt(args, categories);
+            Ok(super::select(client, select, args.page(), page_size).await?)
+        }
+        Some(cursor) => select_after(client, args, categories, cursor, page_size).await,
+    }
+}
+
+/// 按 (有效期, ID) 获取游标之后的一页药品, 多取一条用来判断是否还有下一页
+///
+/// 与 `OFFSET` 不同, 翻到很后面的页时不需要扫描前面的记录, 翻页期间增删药品也不会重复或遗漏.
+/// 总记录数单独统计, 不受游标影响
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `args` - 列表页的查询参数, 忽略其中的页码和排序
+/// * `categories` - 允许查看的类目, 为空表示不限制
+/// * `cursor` - 上一页最后一条药品的游标, 为空表示第一页
+/// * `page_size` - 每页的记录数
+async fn select_after(
+    client: &Client,
+    args: &MedicinalBackendQueryArg,
+    categories: &[String],
+    cursor: Option<Cursor>,
+    page_size: u32,
+) -> Result<Pagination<Vec<MedicinalList>>> {
+    let (count_stmt, stmt) = select_after_stmt(args, categories, cursor, page_size);
+    let total_records = super::count(client, &count_stmt.sql, &count_stmt.params()).await?;
+    let rows = super::rows(client, &stmt.sql, &stmt.params()).await?;
+    let mut data = rows
+        .iter()
+        .map(|row| MedicinalList::from_row_ref(row).map_err(AppError::from))
+        .collect::<Result<Vec<MedicinalList>>>()?;
+    let next_cursor = if data.len() > page_size as usize {
+        data.truncate(page_size as usize);
+        data.last().map(|m| {
+            Cursor {
+                validity: m.validity,
+                id: m.id,
+            }
+            .to_string()
+        })
+    } else {
+        None
+    };
+    Ok(Pagination::new(0, page_size, total_records, data).with_cursor(next_cursor))
+}
+
+/// 游标翻页的统计语句和查询语句, 统计语句不含游标条件
+fn select_after_stmt(
+    args: &MedicinalBackendQueryArg,
+    categories: &[String],
+    cursor: Option<Cursor>,
+    page_size: u32,
+) -> (Stmt, Stmt) {
+    let keyword = Some(args.keyword())
+        .filter(|k| !k.is_empty())
+        .map(Keyword::new);
+    let mut select = search_by_arg(args, keyword.as_ref(), categories);
+    let count_stmt = select.count();
+    if let Some(cursor) = cursor {
+        select = select.filter(Filter::sql(
+            "(validity, id) > (?, ?)",
+            vec![param(cursor.validity), param(cursor.id)],
+        ));
+    }
+    let stmt = select
+        .order_by("validity", Direction::Asc)
+        .order_by("id", Direction::Asc)
+        .limit(page_size as i64 + 1)
         .build();
-    debug!("medicinal select sql: {}", sql);
-    debug!("medicinal select count sql: {}", count_sql);
-    Ok(super::select(client, &sql, &count_sql, args, page).await?)
+    (count_stmt, stmt)
 }
 
-/// 根据条件获取药品，返回药品，或者包含AppError的错误信息
+/// 按列表页的查询参数获取所有符合条件的药品(不分页), 用于导出, 排序与列表页一致
 ///
 /// # 参数
 ///
 /// * `client` - 数据库连接对象
-/// * `condition` - 条件
-/// * `args` - 条件对应的参数
-pub async fn find(
+/// * `args` - 列表页的查询参数
+/// * `categories` - 允许查看的类目, 为空表示不限制
+pub async fn all_by_arg(
     client: &Client,
-    condition: Option<&str>,
-    args: &[&(dyn ToSql + Sync)],
-) -> Result<MedicinalList> {
-    let sql = SelectStmt::builder()
-        .table(TABLE_NAME)
-        .fields("id, category, name, batch_number, spec, count, validity, is_del")
-        .condition(condition)
-        .limit(Some(1))
+    args: &MedicinalBackendQueryArg,
+    categories: &[String],
+) -> Result<Vec<MedicinalList>> {
+    let stmt = select_by_arg_stmt(args, categories).build();
+    Ok(super::fetch(client, &stmt).await?)
+}
+
+/// 根据 ID 获取药品，返回药品，或者包含AppError的错误信息
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `id` - 药品 ID
+pub async fn find(client: &Client, id: i32) -> Result<MedicinalList> {
+    let stmt = Select::table(TABLE_NAME)
+        .fields(FIELDS)
+        .filter(Filter::eq("id", id))
+        .limit(1)
         .build();
-    debug!("medicinal find sql: {}", sql);
-    Ok(super::query_one(client, &sql, args, Some("没有找到符合条件的药品")).await?)
+    debug!("medicinal find sql: {}", stmt.sql);
+    Ok(super::query_one(
+        client,
+        &stmt.sql,
+        &stmt.params(),
+        Some("没有找到符合条件的药品"),
+    )
+    .await?)
 }
 
 /// 创建药品，返回新创建的药品的 ID 或者包含AppError的错误信息
@@ -85,7 +379,7 @@ pub async fn create(client: &Client, medicinal: &CreateMedicinal) -> Result<Medi
     )
     .await?
     {
-        return Err(crate::error::AppError::is_exists(&format!(
+        return Err(AppError::is_exists(&format!(
             "药品名称:'{}' 或类目:'{}' 或者批号: '{}' 已存在",
             medicinal.name, medicinal.category, medicinal.batch_number
         )));
@@ -101,9 +395,9 @@ pub async fn create(client: &Client, medicinal: &CreateMedicinal) -> Result<Medi
     //     })?;
     // debug!("medicinal create: {:?}", validity);
 
-    let sql = "INSERT INTO medicinal (category, name, batch_number, spec, count, validity) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id";
+    let sql = "INSERT INTO medicinal (category, name, batch_number, spec, count, validity, name_pinyin, name_initials, category_pinyin, category_initials) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING id";
     debug!("medicinal create sql: {}", sql);
-    Ok(super::query_one(
+    let id = super::query_one(
         client,
         sql,
         &[
@@ -113,10 +407,15 @@ pub async fn create(client: &Client, medicinal: &CreateMedicinal) -> Result<Medi
             &medicinal.spec,
             &medicinal.count,
             &medicinal.validity,
+            &hanzi::full(&medicinal.name),
+            &hanzi::initials(&medicinal.name),
+            &hanzi::full(&medicinal.category),
+            &hanzi::initials(&medicinal.category),
         ],
         Some("插入药品失败"),
     )
-    .await?)
+    .await?;
+    Ok(id)
 }
 
 /// 根据条件判断药品是否存在，返回药品是否存在，或者包含AppError的错误信息
@@ -124,14 +423,9 @@ pub async fn create(client: &Client, medicinal: &CreateMedicinal) -> Result<Medi
 /// # 参数
 ///
 /// * `client` - 数据库连接对象
-/// * `condition` - 条件
-/// * `args` - 条件对应的参数
-async fn is_exists(
-    client: &Client,
-    condition: Option<&str>,
-    args: &[&(dyn ToSql + Sync)],
-) -> Result<bool> {
-    let c = count(client, condition, args).await?;
+/// * `filter` - 条件
+async fn is_exists(client: &Client, filter: Filter) -> Result<bool> {
+    let c = count(client, filter).await?;
     Ok(c > 0)
 }
 
@@ -140,20 +434,11 @@ async fn is_exists(
 /// # 参数
 ///
 /// * `client` - 数据库连接对象
-/// * `condition` - 条件
-/// * `args` - 条件对应的参数
-async fn count(
-    client: &Client,
-    condition: Option<&str>,
-    args: &[&(dyn ToSql + Sync)],
-) -> Result<i64> {
-    let sql = SelectStmt::builder()
-        .table(TABLE_NAME)
-        .fields("COUNT(*)")
-        .condition(condition)
-        .build();
-    debug!("medicinal count sql: {}", sql);
-    super::count(client, &sql, args).await
+/// * `filter` - 条件
+async fn count(client: &Client, filter: Filter) -> Result<i64> {
+    let stmt = Select::table(TABLE_NAME).filter(filter).count();
+    debug!("medicinal count sql: {}", stmt.sql);
+    super::count(client, &stmt.sql, &stmt.params()).await
 }
 
 /// 判断药品名称和批号是否存在，或者包含AppError的错误信息
@@ -168,9 +453,9 @@ pub async fn is_exists_name_batch_number(
     name: &str,
     batch_number: &str,
 ) -> Result<bool> {
-    let condition = Some("name = $1 AND batch_number = $2");
-    // let args = &[name, batch_number];
-    is_exists(client, condition, &[&name, &batch_number]).await
+    let filter = Filter::eq("name", name.to_string())
+        .and(Filter::eq("batch_number", batch_number.to_string()));
+    is_exists(client, filter).await
 }
 
 /// 判断药品名称和批号是否存在，或者包含AppError的错误信息
@@ -187,8 +472,10 @@ pub async fn is_exists_name_category_batch_number(
     category: &str,
     batch_number: &str,
 ) -> Result<bool> {
-    let condition = Some("name = $1 AND category = $2 AND batch_number = $3");
-    is_exists(client, condition, &[&name, &category, &batch_number]).await
+    let filter = Filter::eq("name", name.to_string())
+        .and(Filter::eq("category", category.to_string()))
+        .and(Filter::eq("batch_number", batch_number.to_string()));
+    is_exists(client, filter).await
 }
 
 /// 删除或者恢复药品，返回操作结果，或者包含AppError的错误信息
@@ -241,8 +528,20 @@ pub async fn update(client: &Client, med: &UpdateMedicinal) -> Result<bool> {
     // 直接更新
     let result = execute(
         client,
-        "UPDATE medicinal set name=$1, category=$2, batch_number=$3, spec=$4, count=$5, validity=$6 WHERE id=$7",
-        &[&med.name, &med.category, &med.batch_number, &med.spec, &med.count, &med.validity, &med.id],
+        "UPDATE medicinal set name=$1, category=$2, batch_number=$3, spec=$4, count=$5, validity=$6, name_pinyin=$7, name_initials=$8, category_pinyin=$9, category_initials=$10 WHERE id=$11",
+        &[
+            &med.name,
+            &med.category,
+            &med.batch_number,
+            &med.spec,
+            &med.count,
+            &med.validity,
+            &hanzi::full(&med.name),
+            &hanzi::initials(&med.name),
+            &hanzi::full(&med.category),
+            &hanzi::initials(&med.category),
+            &med.id,
+        ],
     )
         .await?;
 
@@ -252,46 +551,136 @@ pub async fn update(client: &Client, med: &UpdateMedicinal) -> Result<bool> {
     }
 }
 
-/// 更新通知时间，返回更新结果，或者包含AppError的错误信息
+/// 更新通知时间，返回更新的记录数，或者包含AppError的错误信息
 ///
 /// # 参数
 ///
-/// * `cleint` - 数据库连接对象
-/// * `id` - 对象 id
-/// * `time` - 时间
-pub async fn update_notify_at(client: &Client, condition: &str, update_date: &str) -> Result<bool> {
-    // 直接更新可能有很多 id
-    let sql = format!("UPDATE medicinal set {} WHERE {}", update_date, condition);
-    let result = execute(client, &sql, &[]).await?;
-    debug!("update_notify_at: {sql}, {result}");
-    match result {
-        ref updated if *updated == 1 => Ok(true),
-        _ => Ok(false),
+/// * `client` - 数据库连接对象
+/// * `ids` - 药品 ID 列表
+/// * `notify_at` - 下次通知的时间
+pub async fn update_notify_at(
+    client: &Client,
+    ids: &[i32],
+    notify_at: DateTime<Local>,
+) -> Result<u64> {
+    let stmt = Update::table(TABLE_NAME)
+        .set("notify_at", notify_at)
+        .filter(Filter::in_list("id", ids.to_vec()))
+        .build()?;
+    let result = execute(client, &stmt.sql, &stmt.params()).await?;
+    debug!("update_notify_at: {}, {}", stmt.sql, result);
+    Ok(result)
+}
+
+/// 为还没有拼音的药品补全拼音, 返回补全的数量
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+pub async fn backfill_pinyin(client: &Client) -> Result<u64> {
+    let mut total = 0;
+    loop {
+        let stmt = Select::table(TABLE_NAME)
+            .fields("id, name, category")
+            .filter(Filter::is_null("name_pinyin"))
+            .order_by("id", Direction::Asc)
+            .limit(500)
+            .build();
+        let rows: Vec<MedicinalName> = super::fetch(client, &stmt).await?;
+        if rows.is_empty() {
+            return Ok(total);
+        }
+        for row in rows {
+            let stmt = Update::table(TABLE_NAME)
+                .set("name_pinyin", hanzi::full(&row.name))
+                .set("name_initials", hanzi::initials(&row.name))
+                .set("category_pinyin", hanzi::full(&row.category))
+                .set("category_initials", hanzi::initials(&row.category))
+                .filter(Filter::eq("id", row.id))
+                .build()?;
+            total += execute(client, &stmt.sql, &stmt.params()).await?;
+        }
     }
 }
 
-/// 查询所有数据
-pub async fn all(
-    client: &Client,
-    condition: &str,
-    args: &[&(dyn ToSql + Sync)],
-) -> Result<Vec<MedicinalList>> {
-    let sql = SelectStmt::builder()
-        .table(TABLE_NAME)
-        .fields("id, category, name, batch_number, spec, count, validity, is_del")
-        .order(Some("validity ASC"))
-        .condition(Some(condition))
-        .build();
-    debug!("medicinal all sql: {}", sql);
-    Ok(super::query(client, &sql, args).await?)
+/// 查询所有符合条件的数据, 按有效期排序
+pub async fn all(client: &Client, filter: Filter) -> Result<Vec<MedicinalList>> {
+    let stmt = list(filter, OrderKey::Validity, false).build();
+    debug!("medicinal all sql: {}", stmt.sql);
+    Ok(super::fetch(client, &stmt).await?)
 }
 
-/// 查询所有分类数据
-pub async fn categories(client: &Client) -> Result<Vec<Category>> {
-    let sql = SelectStmt::builder()
-        .table(TABLE_NAME)
-        .fields("distinct(category)")
+/// 查询所有分类数据, 优先使用缓存
+///
+/// 类目列表在每次打开列表页时都要用到, 只在添加或修改药品时才会变化.
+/// 已删除的药品也算在类目中, 删除和恢复不影响类目列表
+pub async fn categories(client: &Client, cache: &dyn Cache) -> Result<Vec<Category>> {
+    if let Some(json) = cache.get_str(CATEGORIES_KEY).await? {
+        if let Ok(list) = serde_json::from_str(&json) {
+            return Ok(list);
+        }
+    }
+    let stmt = Select::table(TABLE_NAME)
+        .fields("DISTINCT category")
+        .order_by("category", Direction::Asc)
         .build();
-    debug!("categories sql: {}", sql);
-    Ok(super::query(client, &sql, &[]).await?)
+    debug!("categories sql: {}", stmt.sql);
+    let list: Vec<Category> = super::fetch(client, &stmt).await?;
+    cache
+        .set_ex(
+            CATEGORIES_KEY,
+            &serde_json::to_string(&list)?,
+            CATEGORIES_TTL,
+        )
+        .await?;
+    Ok(list)
+}
+
+/// 添加、修改或导入药品后让类目列表的缓存失效, 药品已经保存, 失败时只记录日志
+pub async fn invalidate_categories(cache: &dyn Cache) {
+    if let Err(err) = cache.del(CATEGORIES_KEY).await {
+        warn!("类目列表的缓存失效失败: {:?}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn args() -> MedicinalBackendQueryArg {
+        MedicinalBackendQueryArg {
+            page: None,
+            keyword: Some("感冒".to_string()),
+            msg: None,
+            is_del: None,
+            expired: None,
+            category: None,
+            order: None,
+            desc: None,
+            page_size: None,
+            cursor: Some(String::new()),
+        }
+    }
+
+    #[test]
+    fn test_select_after_total_should_ignore_cursor() {
+        let args = args();
+        let categories = vec!["家庭药箱".to_string()];
+        let (first_count, first) = select_after_stmt(&args, &categories, None, 10);
+        let cursor = Cursor {
+            validity: NaiveDate::from_ymd(2024, 5, 1),
+            id: 42,
+        };
+        let (second_count, second) = select_after_stmt(&args, &categories, Some(cursor), 10);
+        // 两页统计总记录数的语句和参数完全相同
+        assert_eq!(first_count.sql, second_count.sql);
+        assert_eq!(
+            format!("{:?}", first_count.params()),
+            format!("{:?}", second_count.params())
+        );
+        assert!(!second_count.sql.contains("(validity, id)"));
+        assert!(!first.sql.contains("(validity, id)"));
+        assert!(second.sql.contains("(validity, id) > ("));
+    }
 }
@@ -0,0 +1,163 @@
+//! 内嵌在程序中的数据库迁移
+//!
+//! 迁移文件放在 `migrations` 目录, 文件名为 `版本号_说明.sql`, 新增时在 [`MIGRATIONS`] 的末尾登记.
+//! 执行过的迁移记录在 `schema_version` 表中, 已经发布的迁移不要再修改, 改动要写成新的迁移.
+
+use crate::db::query::{Direction, Filter, Select};
+use crate::error::{AppError, AppErrorType};
+use crate::model::SchemaVersion;
+use crate::Result;
+use chrono::{DateTime, Local};
+use deadpool_postgres::Client;
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+/// 一个版本的迁移
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    /// 迁移文件内容的 sha256, 用于发现执行后被修改过的迁移
+    pub fn checksum(&self) -> String {
+        format!("{:x}", Sha256::digest(self.sql.as_bytes()))
+    }
+}
+
+/// 所有的迁移, 按版本号从小到大排列
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "baseline",
+        sql: include_str!("../../migrations/0001_baseline.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "sms_setting",
+        sql: include_str!("../../migrations/0002_sms_setting.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "notify_outbox",
+        sql: include_str!("../../migrations/0003_notify_outbox.sql"),
+    },
+];
+
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_version (version integer PRIMARY KEY, name varchar NOT NULL, checksum char(64) NOT NULL, applied_at TIMESTAMPTZ NOT NULL DEFAULT (now()))";
+
+/// 多个实例同时启动时用来互斥的 advisory lock
+const LOCK_KEY: i64 = 0x5354_5241_4e47;
+
+/// 迁移的状态
+pub struct MigrationStatus {
+    pub version: i32,
+    pub name: &'static str,
+    /// 执行的时间, 还没有执行时为空
+    pub applied_at: Option<DateTime<Local>>,
+    /// 执行之后迁移文件被修改过
+    pub modified: bool,
+}
+
+/// 已经执行的迁移, 还没有 `schema_version` 表时为空
+async fn applied(client: &Client) -> Result<Vec<SchemaVersion>> {
+    let stmt = Select::table("pg_tables")
+        .filter(Filter::sql("schemaname=current_schema()", vec![]))
+        .filter(Filter::eq("tablename", "schema_version"))
+        .count();
+    if super::count(client, &stmt.sql, &stmt.params()).await? == 0 {
+        return Ok(vec![]);
+    }
+    let stmt = Select::table("schema_version")
+        .fields("version, name, checksum, applied_at")
+        .order_by("version", Direction::Asc)
+        .build();
+    super::fetch(client, &stmt).await
+}
+
+/// 所有迁移的状态
+pub async fn status(client: &Client) -> Result<Vec<MigrationStatus>> {
+    let applied = applied(client).await?;
+    Ok(MIGRATIONS
+        .iter()
+        .map(|m| {
+            let done = applied.iter().find(|a| a.version == m.version);
+            MigrationStatus {
+                version: m.version,
+                name: m.name,
+                applied_at: done.map(|a| a.applied_at),
+                modified: done.map(|a| a.checksum != m.checksum()).unwrap_or(false),
+            }
+        })
+        .collect())
+}
+
+/// 还没有执行的迁移
+pub async fn pending(client: &Client) -> Result<Vec<&'static Migration>> {
+    let applied = applied(client).await?;
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .collect())
+}
+
+/// 按版本号依次执行还没有执行的迁移, 每个迁移在单独的事务中执行, 返回执行了的迁移
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `dry_run` - 只返回将要执行的迁移, 不执行
+pub async fn up(client: &mut Client, dry_run: bool) -> Result<Vec<&'static Migration>> {
+    let pending = pending(client).await?;
+    if dry_run || pending.is_empty() {
+        return Ok(pending);
+    }
+    super::execute(&*client, CREATE_TABLE, &[]).await?;
+    let mut done = vec![];
+    for migration in pending {
+        let tx = client.transaction().await?;
+        super::execute(&tx, "SELECT pg_advisory_xact_lock($1)", &[&LOCK_KEY]).await?;
+        // 拿到锁之后再检查一次, 其它实例可能已经执行过了
+        let stmt = Select::table("schema_version")
+            .filter(Filter::eq("version", migration.version))
+            .count();
+        if super::count(&tx, &stmt.sql, &stmt.params()).await? > 0 {
+            continue;
+        }
+        tx.batch_execute(migration.sql).await.map_err(|err| {
+            AppError::from_str(
+                &format!(
+                    "执行迁移 {} {} 失败: {}",
+                    migration.version, migration.name, err
+                ),
+                AppErrorType::DbError,
+            )
+        })?;
+        let sql = "INSERT INTO schema_version (version, name, checksum) VALUES ($1, $2, $3)";
+        super::execute(
+            &tx,
+            sql,
+            &[&migration.version, &migration.name, &migration.checksum()],
+        )
+        .await?;
+        tx.commit().await?;
+        info!("已执行迁移: {} {}", migration.version, migration.name);
+        done.push(migration);
+    }
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (i, m) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(m.version, i as i32 + 1, "迁移 {} 的版本号不连续", m.name);
+            assert!(!m.sql.trim().is_empty());
+        }
+        assert_eq!(MIGRATIONS[0].checksum().len(), 64);
+    }
+}
@@ -1,19 +1,27 @@
 pub mod admin;
+pub mod api_token;
+pub mod login_event;
 pub mod medicinal;
+pub mod migration;
+pub mod notify_outbox;
 pub mod pagination;
-pub mod select_stmt;
+pub mod query;
+pub mod role_setting;
+pub mod sms_setting;
 
 use crate::db::pagination::Pagination;
+use crate::db::query::{Filter, Select, Stmt, Update};
 use crate::error::AppError;
 use crate::Result;
+use deadpool_postgres::GenericClient;
 use tokio_pg_mapper::FromTokioPostgresRow;
 use tokio_postgres::types::ToSql;
-use tokio_postgres::{GenericClient, Statement};
+use tokio_postgres::{Row, Statement};
 
-/// 默认分页大小
-const PAGE_SIZE: u8 = 30;
+/// 分页查询时用窗口函数在同一条语句中统计总记录数
+const TOTAL_RECORDS: &str = "COUNT(*) OVER() AS total_records";
 
-/// 获取[`Statement`] 对象
+/// 获取[`Statement`] 对象, 同一个连接上相同的 SQL 只准备一次
 ///
 /// # 参数
 ///
@@ -23,7 +31,22 @@ async fn get_stmt<C>(client: &C, sql: &str) -> Result<Statement>
 where
     C: GenericClient,
 {
-    client.prepare(sql).await.map_err(AppError::from)
+    client.prepare_cached(sql).await.map_err(AppError::from)
+}
+
+/// 查询数据库, 返回原始的数据行
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `sql` - SQL语句
+/// * `args` -  查询参数
+async fn rows<C>(client: &C, sql: &str, args: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>>
+where
+    C: GenericClient,
+{
+    let stmt = get_stmt(client, sql).await?;
+    client.query(&stmt, args).await.map_err(AppError::from)
 }
 
 /// 查询数据库
@@ -38,11 +61,8 @@ where
     T: FromTokioPostgresRow,
     C: GenericClient,
 {
-    let stmt = get_stmt(client, sql).await?;
-    let result = client
-        .query(&stmt, args)
-        .await
-        .map_err(AppError::from)?
+    let result = rows(client, sql, args)
+        .await?
         .iter()
         .map(|row| <T>::from_row_ref(row).unwrap())
         .collect::<Vec<T>>();
@@ -71,19 +91,21 @@ where
 
 async fn del_or_restore(
     client: &impl GenericClient,
-    table: &str,
-    id: &(dyn ToSql + Sync),
+    table: &'static str,
+    id: i32,
     is_del_opt: bool,
 ) -> Result<u64> {
-    let is_del = if is_del_opt { true } else { false };
-    let sql = format!("UPDATE {} SET is_del = $1 WHERE id = $2", table);
-    execute(client, &sql, &[&is_del, id]).await
+    let stmt = Update::table(table)
+        .set("is_del", is_del_opt)
+        .filter(Filter::eq("id", id))
+        .build()?;
+    execute(client, &stmt.sql, &stmt.params()).await
 }
 
-async fn del(client: &impl GenericClient, table: &str, id: &(dyn ToSql + Sync)) -> Result<u64> {
+async fn del(client: &impl GenericClient, table: &'static str, id: i32) -> Result<u64> {
     del_or_restore(client, table, id, true).await
 }
-async fn restore(client: &impl GenericClient, table: &str, id: &(dyn ToSql + Sync)) -> Result<u64> {
+async fn restore(client: &impl GenericClient, table: &'static str, id: i32) -> Result<u64> {
     del_or_restore(client, table, id, false).await
 }
 
@@ -124,14 +146,50 @@ async fn count(
     Ok(result)
 }
 
+/// 执行构造好的查询
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `stmt` - SQL语句及参数
+async fn fetch<T, C>(client: &C, stmt: &Stmt) -> Result<Vec<T>>
+where
+    T: FromTokioPostgresRow,
+    C: GenericClient,
+{
+    query(client, &stmt.sql, &stmt.params()).await
+}
+
+/// 分页查询, 根据页码设置 `LIMIT` 和 `OFFSET`, 总记录数用窗口函数在同一条语句中统计
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `select` - 不含分页的查询
+/// * `page` - 当前分页的页码
+/// * `page_size` - 每页的记录数
 async fn select<T: FromTokioPostgresRow, C: GenericClient>(
     client: &C,
-    sql: &str,
-    count_sql: &str,
-    args: &[&(dyn ToSql + Sync)],
+    select: Select,
     page: u32,
+    page_size: u32,
 ) -> Result<Pagination<Vec<T>>> {
-    let data = query::<T, C>(client, sql, args).await?;
-    let total_records = count(client, count_sql, args).await?;
-    Ok(Pagination::new(page, PAGE_SIZE, total_records, data))
+    let count_stmt = select.count();
+    let stmt = select
+        .column(TOTAL_RECORDS, vec![])
+        .limit(page_size as i64)
+        .offset(page as i64 * page_size as i64)
+        .build();
+    let rows = rows(client, &stmt.sql, &stmt.params()).await?;
+    let total_records = match rows.first() {
+        Some(row) => row.try_get("total_records").map_err(AppError::from)?,
+        // 页码超出范围时没有数据, 只能再单独统计
+        None if page > 0 => count(client, &count_stmt.sql, &count_stmt.params()).await?,
+        None => 0,
+    };
+    let data = rows
+        .iter()
+        .map(|row| <T>::from_row_ref(row).unwrap())
+        .collect::<Vec<T>>();
+    Ok(Pagination::new(page, page_size, total_records, data))
 }
@@ -0,0 +1,144 @@
+use crate::db::pagination::Pagination;
+use crate::db::query::{param, Direction, Filter, Select, Update};
+use crate::model::{NotifyOutbox, OutboxMessage};
+use crate::Result;
+use chrono::{DateTime, Local};
+use deadpool_postgres::{Client, GenericClient};
+
+/// 表名
+const TABLE_NAME: &str = "notify_outbox";
+const FIELDS: &str = "id, channel, channel_name, recipients, kind, title, summary, payload, status, attempts, next_attempt_at, response, created_at, sent_at";
+
+/// 写入发件箱, 等待发送, 可以在事务中执行
+pub async fn create(client: &impl GenericClient, msg: &OutboxMessage) -> Result<u64> {
+    let sql = format!(
+        "INSERT INTO {} (channel, channel_name, recipients, kind, title, summary, payload) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        TABLE_NAME
+    );
+    super::execute(
+        client,
+        &sql,
+        &[
+            &msg.channel,
+            &msg.channel_name,
+            &msg.recipients,
+            &msg.kind,
+            &msg.title,
+            &msg.summary,
+            &msg.payload,
+        ],
+    )
+    .await
+}
+
+/// 取出到期的消息并标记为正在发送, `next_attempt_at` 记为取出的时间, 同时取的任务不会取到同一条
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `limit` - 最多取出的条数
+pub async fn claim_due(client: &Client, limit: i64) -> Result<Vec<NotifyOutbox>> {
+    let stmt = Update::table(TABLE_NAME)
+        .set("status", NotifyOutbox::SENDING)
+        .set_sql("next_attempt_at", "CURRENT_TIMESTAMP", vec![])
+        .filter(Filter::sql(
+            "id IN (SELECT id FROM notify_outbox WHERE status=? AND next_attempt_at<=CURRENT_TIMESTAMP ORDER BY id LIMIT ? FOR UPDATE SKIP LOCKED)",
+            vec![param(NotifyOutbox::PENDING), param(limit)],
+        ))
+        .returning(FIELDS)
+        .build()?;
+    super::fetch(client, &stmt).await
+}
+
+/// 发送成功, 记录服务商的响应
+pub async fn sent(client: &Client, id: i32, response: &str) -> Result<u64> {
+    let stmt = Update::table(TABLE_NAME)
+        .set("status", NotifyOutbox::SENT)
+        .set_sql("attempts", "attempts+1", vec![])
+        .set("response", response.to_string())
+        .set_sql("sent_at", "CURRENT_TIMESTAMP", vec![])
+        .filter(Filter::eq("id", id))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await
+}
+
+/// 发送失败, 记录失败原因
+///
+/// # 参数
+///
+/// * `client` - 数据库连接对象
+/// * `id` - 消息的 ID
+/// * `response` - 服务商的响应或者失败原因
+/// * `retry_at` - 下次重试的时间, 为空时不再重试
+pub async fn failed(
+    client: &Client,
+    id: i32,
+    response: &str,
+    retry_at: Option<DateTime<Local>>,
+) -> Result<u64> {
+    let status = match retry_at {
+        Some(_) => NotifyOutbox::PENDING,
+        None => NotifyOutbox::FAILED,
+    };
+    let stmt = Update::table(TABLE_NAME)
+        .set("status", status)
+        .set_sql("attempts", "attempts+1", vec![])
+        .set("response", response.to_string())
+        .set_sql(
+            "next_attempt_at",
+            "COALESCE(?, next_attempt_at)",
+            vec![param(retry_at)],
+        )
+        .filter(Filter::eq("id", id))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await
+}
+
+/// 渠道在安静时段内, 推迟到 `until` 再发送, 不算尝试次数
+pub async fn defer(client: &Client, id: i32, until: DateTime<Local>) -> Result<u64> {
+    let stmt = Update::table(TABLE_NAME)
+        .set("status", NotifyOutbox::PENDING)
+        .set("next_attempt_at", until)
+        .filter(Filter::eq("id", id))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await
+}
+
+/// 重新发送失败的消息, 重新计算尝试次数, 返回更新的条数
+pub async fn resend(client: &Client, id: i32) -> Result<u64> {
+    let stmt = Update::table(TABLE_NAME)
+        .set("status", NotifyOutbox::PENDING)
+        .set("attempts", 0)
+        .set_sql("next_attempt_at", "CURRENT_TIMESTAMP", vec![])
+        .filter(Filter::eq("id", id))
+        .filter(Filter::eq("status", NotifyOutbox::FAILED))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await
+}
+
+/// 取出超过 10 分钟还没有结果的消息(如发送时进程退出了)放回去等待发送
+pub async fn release(client: &Client) -> Result<u64> {
+    let stmt = Update::table(TABLE_NAME)
+        .set("status", NotifyOutbox::PENDING)
+        .filter(Filter::eq("status", NotifyOutbox::SENDING))
+        .filter(Filter::sql(
+            "next_attempt_at < CURRENT_TIMESTAMP - INTERVAL '10 minutes'",
+            vec![],
+        ))
+        .build()?;
+    super::execute(client, &stmt.sql, &stmt.params()).await
+}
+
+/// 分页查询发送记录, 最新的在前
+pub async fn select(
+    client: &Client,
+    filter: Filter,
+    page: u32,
+    page_size: u32,
+) -> Result<Pagination<Vec<NotifyOutbox>>> {
+    let select = Select::table(TABLE_NAME)
+        .fields(FIELDS)
+        .filter(filter)
+        .order_by("id", Direction::Desc);
+    super::select(client, select, page, page_size).await
+}
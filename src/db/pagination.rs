@@ -1,20 +1,29 @@
+use crate::model::{AdminInfo, MedicinalList};
+use serde::Serialize;
+use utoipa::ToSchema;
+
 /// 分页
+#[derive(Serialize, ToSchema)]
+#[aliases(MedicinalPage = Pagination<Vec<MedicinalList>>, AdminPage = Pagination<Vec<AdminInfo>>)]
 pub struct Pagination<T> {
     /// 当前页码
     pub page: u32,
     /// 分页大小
-    pub page_size: u8,
+    pub page_size: u32,
     /// 总记录数
     pub total_records: i64,
     /// 分页数
     pub total_pages: i64,
     /// 数据
     pub data: T,
+    /// 按游标翻页时下一页的游标, 没有下一页时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 impl<T> Pagination<T> {
     /// 创建一个新的分页对象
-    pub fn new(page: u32, page_size: u8, total_records: i64, data: T) -> Self {
+    pub fn new(page: u32, page_size: u32, total_records: i64, data: T) -> Self {
         let total_pages = f64::ceil(total_records as f64 / page_size as f64) as i64;
         Pagination {
             page,
@@ -22,6 +31,27 @@ impl<T> Pagination<T> {
             total_records,
             total_pages,
             data,
+            next_cursor: None,
+        }
+    }
+
+    /// 设置下一页的游标
+    pub fn with_cursor(self, next_cursor: Option<String>) -> Self {
+        Pagination {
+            next_cursor,
+            ..self
+        }
+    }
+
+    /// 转换分页中的数据, 分页信息保持不变
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Pagination<U> {
+        Pagination {
+            page: self.page,
+            page_size: self.page_size,
+            total_records: self.total_records,
+            total_pages: self.total_pages,
+            data: f(self.data),
+            next_cursor: self.next_cursor,
         }
     }
 }
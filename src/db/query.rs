@@ -0,0 +1,581 @@
+//! 带绑定参数的 SQL 构造器
+//!
+//! 条件中的值一律作为绑定参数传给数据库, 占位符 `$n` 按出现的顺序自动编号.
+//! 表名、字段名、连接条件及排序字段只接受代码中的常量 (`&'static str`),
+//! 来自请求的排序字段需要先经过 [`whitelist`] 转换.
+//!
+//! 只有 SELECT 和 UPDATE 语句, INSERT(包括 `ON CONFLICT` 和 `WITH ... DELETE` 组合)的字段和参数是固定的,
+//! 直接写成带 `$n` 参数的 SQL.
+
+use crate::error::AppError;
+use crate::Result;
+use std::fmt::Write;
+use std::sync::Arc;
+use tokio_postgres::types::ToSql;
+
+/// 绑定参数
+pub type Param = Arc<dyn ToSql + Sync + Send>;
+
+/// 把值包装成绑定参数
+pub fn param<T: ToSql + Sync + Send + 'static>(value: T) -> Param {
+    Arc::new(value)
+}
+
+/// 生成好的 SQL 语句及其参数
+pub struct Stmt {
+    pub sql: String,
+    params: Vec<Param>,
+}
+
+impl Stmt {
+    /// 传给 [`tokio_postgres`] 的参数列表
+    pub fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.params
+            .iter()
+            .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+            .collect()
+    }
+}
+
+/// 拼接 SQL 的同时收集参数
+#[derive(Default)]
+struct Writer {
+    sql: String,
+    params: Vec<Param>,
+}
+
+impl Writer {
+    fn push(&mut self, s: &str) {
+        self.sql.push_str(s);
+    }
+
+    fn bind(&mut self, p: &Param) {
+        self.params.push(p.clone());
+        let _ = write!(self.sql, "${}", self.params.len());
+    }
+
+    /// 写入 SQL 片段, 其中的 `?` 依次替换为参数的占位符
+    fn fragment(&mut self, fragment: &str, params: &[Param]) {
+        let mut params = params.iter();
+        for (i, part) in fragment.split('?').enumerate() {
+            if i > 0 {
+                if let Some(p) = params.next() {
+                    self.bind(p);
+                }
+            }
+            self.push(part);
+        }
+    }
+
+    fn finish(self) -> Stmt {
+        Stmt {
+            sql: self.sql,
+            params: self.params,
+        }
+    }
+}
+
+enum Expr {
+    Cmp(&'static str, &'static str, Param),
+    Between(&'static str, Param, Param),
+    In(&'static str, Param),
+    IsNull(&'static str, bool),
+    Sql(&'static str, Vec<Param>),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+}
+
+/// 查询条件, 可以用 [`Filter::and`] 和 [`Filter::or`] 组合
+pub struct Filter(Expr);
+
+impl Filter {
+    fn compare<T: ToSql + Sync + Send + 'static>(
+        column: &'static str,
+        op: &'static str,
+        v: T,
+    ) -> Self {
+        Self(Expr::Cmp(column, op, param(v)))
+    }
+    pub fn eq<T: ToSql + Sync + Send + 'static>(column: &'static str, v: T) -> Self {
+        Self::compare(column, "=", v)
+    }
+    pub fn ne<T: ToSql + Sync + Send + 'static>(column: &'static str, v: T) -> Self {
+        Self::compare(column, "<>", v)
+    }
+    pub fn lt<T: ToSql + Sync + Send + 'static>(column: &'static str, v: T) -> Self {
+        Self::compare(column, "<", v)
+    }
+    pub fn le<T: ToSql + Sync + Send + 'static>(column: &'static str, v: T) -> Self {
+        Self::compare(column, "<=", v)
+    }
+    pub fn gt<T: ToSql + Sync + Send + 'static>(column: &'static str, v: T) -> Self {
+        Self::compare(column, ">", v)
+    }
+    pub fn ge<T: ToSql + Sync + Send + 'static>(column: &'static str, v: T) -> Self {
+        Self::compare(column, ">=", v)
+    }
+    pub fn like<T: ToSql + Sync + Send + 'static>(column: &'static str, v: T) -> Self {
+        Self::compare(column, "LIKE", v)
+    }
+    pub fn ilike<T: ToSql + Sync + Send + 'static>(column: &'static str, v: T) -> Self {
+        Self::compare(column, "ILIKE", v)
+    }
+    /// `column BETWEEN from AND to`
+    pub fn between<T: ToSql + Sync + Send + 'static>(column: &'static str, from: T, to: T) -> Self {
+        Self(Expr::Between(column, param(from), param(to)))
+    }
+    /// `column = ANY($n)`, 整个列表只占用一个参数, 空列表不匹配任何记录
+    pub fn in_list<T>(column: &'static str, values: Vec<T>) -> Self
+    where
+        Vec<T>: ToSql + Sync + Send + 'static,
+    {
+        Self(Expr::In(column, param(values)))
+    }
+    pub fn is_null(column: &'static str) -> Self {
+        Self(Expr::IsNull(column, true))
+    }
+    pub fn not_null(column: &'static str) -> Self {
+        Self(Expr::IsNull(column, false))
+    }
+    /// 固定的 SQL 片段, 其中的 `?` 依次替换为参数的占位符
+    pub fn sql(fragment: &'static str, params: Vec<Param>) -> Self {
+        debug_assert_eq!(fragment.matches('?').count(), params.len());
+        Self(Expr::Sql(fragment, params))
+    }
+    /// 所有条件都满足, 没有条件时为 TRUE
+    pub fn all(filters: Vec<Filter>) -> Self {
+        Self(Expr::And(filters))
+    }
+    /// 满足任意一个条件, 没有条件时为 FALSE
+    pub fn any(filters: Vec<Filter>) -> Self {
+        Self(Expr::Or(filters))
+    }
+    pub fn and(self, other: Filter) -> Self {
+        match self.0 {
+            Expr::And(mut list) => {
+                list.push(other);
+                Self(Expr::And(list))
+            }
+            expr => Self::all(vec![Self(expr), other]),
+        }
+    }
+    pub fn or(self, other: Filter) -> Self {
+        match self.0 {
+            Expr::Or(mut list) => {
+                list.push(other);
+                Self(Expr::Or(list))
+            }
+            expr => Self::any(vec![Self(expr), other]),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(&self.0, Expr::And(list) if list.is_empty())
+    }
+
+    /// 作为 AND/OR 的一项时是否需要加括号
+    fn is_compound(&self) -> bool {
+        match &self.0 {
+            Expr::And(list) | Expr::Or(list) => list.len() > 1,
+            Expr::Sql(..) => true,
+            _ => false,
+        }
+    }
+
+    fn write(&self, w: &mut Writer) {
+        match &self.0 {
+            Expr::Cmp(column, op, v) => {
+                w.push(&format!("{} {} ", column, op));
+                w.bind(v);
+            }
+            Expr::Between(column, from, to) => {
+                w.push(&format!("{} BETWEEN ", column));
+                w.bind(from);
+                w.push(" AND ");
+                w.bind(to);
+            }
+            Expr::In(column, v) => {
+                w.push(&format!("{} = ANY(", column));
+                w.bind(v);
+                w.push(")");
+            }
+            Expr::IsNull(column, true) => w.push(&format!("{} IS NULL", column)),
+            Expr::IsNull(column, false) => w.push(&format!("{} IS NOT NULL", column)),
+            Expr::Sql(fragment, params) => w.fragment(fragment, params),
+            Expr::And(list) => Self::write_list(w, list, " AND ", "TRUE"),
+            Expr::Or(list) => Self::write_list(w, list, " OR ", "FALSE"),
+        }
+    }
+
+    fn write_list(w: &mut Writer, list: &[Filter], sep: &str, empty: &str) {
+        match list {
+            [] => w.push(empty),
+            [only] => only.write(w),
+            _ => {
+                for (i, f) in list.iter().enumerate() {
+                    if i > 0 {
+                        w.push(sep);
+                    }
+                    if f.is_compound() {
+                        w.push("(");
+                        f.write(w);
+                        w.push(")");
+                    } else {
+                        f.write(w);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::all(vec![])
+    }
+}
+
+/// 排序方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Asc,
+    Desc,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Asc => "ASC",
+            Direction::Desc => "DESC",
+        }
+    }
+}
+
+/// 把请求中的排序字段转换为允许排序的列, `columns` 是 `(参数值, 列名)` 的列表
+pub fn whitelist(key: &str, columns: &[(&str, &'static str)]) -> Result<&'static str> {
+    columns
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, column)| *column)
+        .ok_or_else(|| AppError::invalid_argument(&format!("不支持的排序字段: {}", key)))
+}
+
+/// SELECT 语句
+pub struct Select {
+    table: &'static str,
+    fields: &'static str,
+    columns: Vec<(&'static str, Vec<Param>)>,
+    joins: Vec<(&'static str, &'static str, &'static str)>,
+    filter: Filter,
+    order: Vec<(&'static str, Vec<Param>, Direction)>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+impl Select {
+    pub fn table(table: &'static str) -> Self {
+        Self {
+            table,
+            fields: "*",
+            columns: vec![],
+            joins: vec![],
+            filter: Filter::default(),
+            order: vec![],
+            limit: None,
+            offset: None,
+        }
+    }
+    pub fn fields(mut self, fields: &'static str) -> Self {
+        self.fields = fields;
+        self
+    }
+    /// 在字段后追加带参数的表达式, 其中的 `?` 依次替换为参数的占位符, 如 `similarity(?, name) AS score`
+    pub fn column(mut self, fragment: &'static str, params: Vec<Param>) -> Self {
+        debug_assert_eq!(fragment.matches('?').count(), params.len());
+        self.columns.push((fragment, params));
+        self
+    }
+    /// `INNER JOIN table ON on`
+    pub fn join(mut self, table: &'static str, on: &'static str) -> Self {
+        self.joins.push(("INNER JOIN", table, on));
+        self
+    }
+    /// `LEFT JOIN table ON on`
+    pub fn left_join(mut self, table: &'static str, on: &'static str) -> Self {
+        self.joins.push(("LEFT JOIN", table, on));
+        self
+    }
+    /// 添加条件, 多次调用时以 AND 连接
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = if self.filter.is_empty() {
+            filter
+        } else {
+            self.filter.and(filter)
+        };
+        self
+    }
+    /// 添加排序, 多次调用时依次作为次要的排序
+    pub fn order_by(mut self, column: &'static str, dir: Direction) -> Self {
+        self.order.push((column, vec![], dir));
+        self
+    }
+    /// 按带参数的表达式排序, 其中的 `?` 依次替换为参数的占位符
+    pub fn order_by_sql(
+        mut self,
+        fragment: &'static str,
+        params: Vec<Param>,
+        dir: Direction,
+    ) -> Self {
+        debug_assert_eq!(fragment.matches('?').count(), params.len());
+        self.order.push((fragment, params, dir));
+        self
+    }
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    fn write_from(&self, w: &mut Writer) {
+        w.push(&format!(" FROM {}", self.table));
+        for (kind, table, on) in &self.joins {
+            w.push(&format!(" {} {} ON {}", kind, table, on));
+        }
+        if !self.filter.is_empty() {
+            w.push(" WHERE ");
+            self.filter.write(w);
+        }
+    }
+
+    pub fn build(&self) -> Stmt {
+        let mut w = Writer::default();
+        w.push(&format!("SELECT {}", self.fields));
+        for (fragment, params) in &self.columns {
+            w.push(", ");
+            w.fragment(fragment, params);
+        }
+        self.write_from(&mut w);
+        for (i, (fragment, params, dir)) in self.order.iter().enumerate() {
+            w.push(if i == 0 { " ORDER BY " } else { ", " });
+            w.fragment(fragment, params);
+            w.push(&format!(" {}", dir.as_str()));
+        }
+        if let Some(limit) = self.limit {
+            w.push(" LIMIT ");
+            w.bind(&param(limit));
+        }
+        if let Some(offset) = self.offset {
+            w.push(" OFFSET ");
+            w.bind(&param(offset));
+        }
+        w.finish()
+    }
+
+    /// 统计符合条件的记录数, 忽略排序和分页
+    pub fn count(&self) -> Stmt {
+        let mut w = Writer::default();
+        w.push("SELECT COUNT(*)");
+        self.write_from(&mut w);
+        w.finish()
+    }
+}
+
+/// UPDATE 语句
+pub struct Update {
+    table: &'static str,
+    sets: Vec<(&'static str, &'static str, Vec<Param>)>,
+    filter: Filter,
+    returning: Option<&'static str>,
+}
+
+impl Update {
+    pub fn table(table: &'static str) -> Self {
+        Self {
+            table,
+            sets: vec![],
+            filter: Filter::default(),
+            returning: None,
+        }
+    }
+    pub fn set<T: ToSql + Sync + Send + 'static>(self, column: &'static str, v: T) -> Self {
+        self.set_sql(column, "?", vec![param(v)])
+    }
+    /// 设为带参数的表达式, 其中的 `?` 依次替换为参数的占位符, 如 `attempts+1`、`COALESCE(?, next_attempt_at)`
+    pub fn set_sql(
+        mut self,
+        column: &'static str,
+        fragment: &'static str,
+        params: Vec<Param>,
+    ) -> Self {
+        debug_assert_eq!(fragment.matches('?').count(), params.len());
+        self.sets.push((column, fragment, params));
+        self
+    }
+    /// 添加条件, 多次调用时以 AND 连接
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = if self.filter.is_empty() {
+            filter
+        } else {
+            self.filter.and(filter)
+        };
+        self
+    }
+    /// 返回修改后的记录的字段
+    pub fn returning(mut self, fields: &'static str) -> Self {
+        self.returning = Some(fields);
+        self
+    }
+
+    /// 没有条件的 UPDATE 会修改整张表, 视为错误
+    pub fn build(&self) -> Result<Stmt> {
+        if self.sets.is_empty() || self.filter.is_empty() {
+            return Err(AppError::invalid_argument("UPDATE 语句缺少字段或条件"));
+        }
+        let mut w = Writer::default();
+        w.push(&format!("UPDATE {} SET ", self.table));
+        for (i, (column, fragment, params)) in self.sets.iter().enumerate() {
+            if i > 0 {
+                w.push(", ");
+            }
+            w.push(&format!("{}=", column));
+            w.fragment(fragment, params);
+        }
+        w.push(" WHERE ");
+        self.filter.write(&mut w);
+        if let Some(fields) = self.returning {
+            w.push(&format!(" RETURNING {}", fields));
+        }
+        Ok(w.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_numbering() {
+        let q = Select::table("medicinal")
+            .fields("id, name")
+            .filter(Filter::eq("is_del", false))
+            .filter(Filter::like("name", "%a%".to_string()))
+            .filter(Filter::in_list("category", vec!["x".to_string()]))
+            .order_by("validity", Direction::Asc)
+            .order_by("id", Direction::Desc)
+            .limit(30)
+            .offset(60);
+        let stmt = q.build();
+        assert_eq!(
+            stmt.sql,
+            "SELECT id, name FROM medicinal WHERE is_del = $1 AND name LIKE $2 AND category = ANY($3) ORDER BY validity ASC, id DESC LIMIT $4 OFFSET $5"
+        );
+        assert_eq!(stmt.params().len(), 5);
+
+        let count = q.count();
+        assert_eq!(
+            count.sql,
+            "SELECT COUNT(*) FROM medicinal WHERE is_del = $1 AND name LIKE $2 AND category = ANY($3)"
+        );
+        assert_eq!(count.params().len(), 3);
+    }
+
+    #[test]
+    fn test_and_or() {
+        let f = Filter::eq("a", 1)
+            .and(Filter::ilike("b", "x").or(Filter::like("c", "x")))
+            .and(Filter::between("d", 1, 2));
+        let stmt = Select::table("t").filter(f).build();
+        assert_eq!(
+            stmt.sql,
+            "SELECT * FROM t WHERE a = $1 AND (b ILIKE $2 OR c LIKE $3) AND d BETWEEN $4 AND $5"
+        );
+
+        let stmt = Select::table("t")
+            .filter(Filter::any(vec![]))
+            .filter(Filter::is_null("e"))
+            .build();
+        assert_eq!(stmt.sql, "SELECT * FROM t WHERE FALSE AND e IS NULL");
+
+        assert_eq!(Select::table("t").build().sql, "SELECT * FROM t");
+    }
+
+    #[test]
+    fn test_sql_fragment_and_join() {
+        let stmt = Select::table("api_token AS t")
+            .fields("t.id")
+            .join("admin AS a", "a.id=t.admin_id")
+            .filter(Filter::eq("t.id", 1))
+            .filter(Filter::sql(
+                "t.expires_at IS NULL OR t.expires_at > ?",
+                vec![param(2)],
+            ))
+            .build();
+        assert_eq!(
+            stmt.sql,
+            "SELECT t.id FROM api_token AS t INNER JOIN admin AS a ON a.id=t.admin_id WHERE t.id = $1 AND (t.expires_at IS NULL OR t.expires_at > $2)"
+        );
+    }
+
+    #[test]
+    fn test_column_and_order_params() {
+        let keyword = param("abc".to_string());
+        let stmt = Select::table("t")
+            .fields("id")
+            .column("similarity(?, name) AS score", vec![keyword.clone()])
+            .filter(Filter::sql("? <% name", vec![keyword.clone()]))
+            .order_by_sql("similarity(?, name)", vec![keyword], Direction::Desc)
+            .order_by("id", Direction::Asc)
+            .limit(10)
+            .build();
+        assert_eq!(
+            stmt.sql,
+            "SELECT id, similarity($1, name) AS score FROM t WHERE $2 <% name ORDER BY similarity($3, name) DESC, id ASC LIMIT $4"
+        );
+        assert_eq!(stmt.params().len(), 4);
+    }
+
+    #[test]
+    fn test_update() {
+        let stmt = Update::table("medicinal")
+            .set("notify_at", 1)
+            .filter(Filter::in_list("id", vec![1, 2, 3]))
+            .build()
+            .unwrap();
+        assert_eq!(
+            stmt.sql,
+            "UPDATE medicinal SET notify_at=$1 WHERE id = ANY($2)"
+        );
+        assert!(Update::table("medicinal").set("a", 1).build().is_err());
+    }
+
+    #[test]
+    fn test_update_sql_and_returning() {
+        let stmt = Update::table("notify_outbox")
+            .set("status", "pending")
+            .set_sql("attempts", "attempts+1", vec![])
+            .set_sql(
+                "next_attempt_at",
+                "COALESCE(?, next_attempt_at)",
+                vec![param(Some(1))],
+            )
+            .filter(Filter::eq("id", 1))
+            .returning("id, status")
+            .build()
+            .unwrap();
+        assert_eq!(
+            stmt.sql,
+            "UPDATE notify_outbox SET status=$1, attempts=attempts+1, next_attempt_at=COALESCE($2, next_attempt_at) WHERE id = $3 RETURNING id, status"
+        );
+        assert_eq!(stmt.params().len(), 3);
+    }
+
+    #[test]
+    fn test_whitelist() {
+        let columns = [("name", "name"), ("created", "created_at")];
+        assert_eq!(whitelist("created", &columns).unwrap(), "created_at");
+        assert!(whitelist("name; DROP TABLE admin", &columns).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+use crate::db::query::{Direction, Filter, Select};
+use crate::model::RoleSetting;
+use crate::Result;
+use deadpool_postgres::Client;
+
+/// 所有保存过设置的角色
+pub async fn all(client: &Client) -> Result<Vec<RoleSetting>> {
+    let stmt = Select::table("role_setting")
+        .fields("role, require_2fa")
+        .order_by("role", Direction::Asc)
+        .build();
+    super::fetch(client, &stmt).await
+}
+
+/// 角色是否要求二步验证, 没有设置时不要求
+pub async fn requires_2fa(client: &Client, role: &str) -> Result<bool> {
+    let stmt = Select::table("role_setting")
+        .filter(Filter::eq("role", role.to_string()))
+        .filter(Filter::eq("require_2fa", true))
+        .count();
+    Ok(super::count(client, &stmt.sql, &stmt.params()).await? > 0)
+}
+
+/// 保存角色的二步验证要求
+pub async fn save_require_2fa(client: &Client, role: &str, require_2fa: bool) -> Result<u64> {
+    let sql = "INSERT INTO role_setting (role, require_2fa) VALUES ($1, $2) ON CONFLICT (role) DO UPDATE SET require_2fa=EXCLUDED.require_2fa";
+    super::execute(client, sql, &[&role, &require_2fa]).await
+}
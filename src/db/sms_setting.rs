@@ -0,0 +1,30 @@
+use crate::db::query::{Filter, Select};
+use crate::model::SmsSetting;
+use crate::Result;
+use deadpool_postgres::Client;
+
+/// 后台保存的短信设置, 没有保存过时为空
+pub async fn find(client: &Client) -> Result<Option<SmsSetting>> {
+    let stmt = Select::table("sms_setting")
+        .fields("access_key_id, access_key_secret, sign_name, template_code, phones")
+        .filter(Filter::eq("id", 1))
+        .build();
+    Ok(super::fetch::<SmsSetting, _>(client, &stmt).await?.pop())
+}
+
+/// 保存短信设置
+pub async fn save(client: &Client, setting: &SmsSetting) -> Result<u64> {
+    let sql = "INSERT INTO sms_setting (id, access_key_id, access_key_secret, sign_name, template_code, phones) VALUES (1, $1, $2, $3, $4, $5) ON CONFLICT (id) DO UPDATE SET access_key_id=EXCLUDED.access_key_id, access_key_secret=EXCLUDED.access_key_secret, sign_name=EXCLUDED.sign_name, template_code=EXCLUDED.template_code, phones=EXCLUDED.phones, updated_at=CURRENT_TIMESTAMP";
+    super::execute(
+        client,
+        sql,
+        &[
+            &setting.access_key_id,
+            &setting.access_key_secret,
+            &setting.sign_name,
+            &setting.template_code,
+            &setting.phones,
+        ],
+    )
+    .await
+}
@@ -3,7 +3,10 @@ use askama::Error;
 use askama::Template;
 use axum::http::StatusCode;
 use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
 use redis::RedisError;
+use serde::Serialize;
+use utoipa::ToSchema;
 use std::fmt::Formatter;
 
 /// 应用错误类型
@@ -22,11 +25,42 @@ pub enum AppErrorType {
     ExcelError,
     CSVError,
     SledError,
+    /// 发送通知失败
+    NotifyError,
     ProtectedContentError,
+    /// 请求参数错误
+    InvalidArgument,
+    /// 没有权限
+    Forbidden,
     /// 通用错误
     Common,
 }
 
+impl AppErrorType {
+    /// 稳定的错误码, 供 API 调用方判断错误类型, 不要随意修改已有的值
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppErrorType::Template => "TEMPLATE_ERROR",
+            AppErrorType::AuthError => "UNAUTHENTICATED",
+            AppErrorType::HttpError => "HTTP_ERROR",
+            AppErrorType::DbError => "DB_ERROR",
+            AppErrorType::RedisError => "REDIS_ERROR",
+            AppErrorType::JsonError => "JSON_ERROR",
+            AppErrorType::NotFound => "NOT_FOUND",
+            AppErrorType::IsExists => "ALREADY_EXISTS",
+            AppErrorType::UploadError => "UPLOAD_ERROR",
+            AppErrorType::ExcelError => "EXCEL_ERROR",
+            AppErrorType::CSVError => "CSV_ERROR",
+            AppErrorType::SledError => "SLED_ERROR",
+            AppErrorType::NotifyError => "NOTIFY_ERROR",
+            AppErrorType::ProtectedContentError => "PROTECTED_CONTENT",
+            AppErrorType::InvalidArgument => "INVALID_ARGUMENT",
+            AppErrorType::Forbidden => "FORBIDDEN",
+            AppErrorType::Common => "COMMON_ERROR",
+        }
+    }
+}
+
 /// 应用错误
 #[derive(Debug)]
 pub struct AppError {
@@ -83,12 +117,32 @@ impl AppError {
     fn status_code(&self) -> StatusCode {
         match self.error_type {
             AppErrorType::Template => StatusCode::INTERNAL_SERVER_ERROR,
+            AppErrorType::Forbidden => StatusCode::FORBIDDEN,
             _ => StatusCode::BAD_REQUEST,
         }
     }
     pub fn auth_error(msg: &str) -> Self {
         Self::from_str(msg, AppErrorType::AuthError)
     }
+    pub fn invalid_argument(msg: &str) -> Self {
+        Self::from_str(msg, AppErrorType::InvalidArgument)
+    }
+    pub fn forbidden(msg: &str) -> Self {
+        Self::from_str(msg, AppErrorType::Forbidden)
+    }
+    /// 返回给用户看的错误信息
+    fn user_message(&self) -> String {
+        match self {
+            AppError {
+                message: Some(msg), ..
+            } => msg.clone(),
+            AppError {
+                error_type: AppErrorType::Template,
+                ..
+            } => "模板渲染出错".to_string(),
+            _ => "发生错误".to_string(),
+        }
+    }
 }
 
 impl std::error::Error for AppError {}
@@ -133,6 +187,12 @@ impl From<tokio_postgres::Error> for AppError {
     }
 }
 
+impl From<tokio_pg_mapper::Error> for AppError {
+    fn from(err: tokio_pg_mapper::Error) -> Self {
+        Self::db_error(err)
+    }
+}
+
 impl From<calamine::XlsxError> for AppError {
     fn from(err: calamine::XlsxError) -> Self {
         Self::from_err(err, AppErrorType::ExcelError)
@@ -166,16 +226,7 @@ impl From<sled::Error> for AppError {
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let status_code = (&self).status_code();
-        let msg = match self {
-            AppError{
-                message: Some(msg), .. // TODO: 这个是什么操作?
-            } => msg.clone(),
-            AppError {
-                error_type: AppErrorType::Template,
-                ..
-            } => "模板渲染出错".to_string(),
-            _ => "发生错误".to_string(),
-        };
+        let msg = self.user_message();
 
         let tmpl = ErrTemplate {
             err: msg.to_string(),
@@ -185,3 +236,81 @@ impl IntoResponse for AppError {
         (status_code, Html(msg)).into_response()
     }
 }
+
+/// API 错误, 以 json 的形式返回给调用方, 而不是渲染 `err.html`
+///
+/// ```json
+/// {"code": "NOT_FOUND", "message": "没有找到符合条件的药品"}
+/// ```
+#[derive(Debug)]
+pub struct ApiError(pub AppError);
+
+/// API 错误的响应体
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiErrorBody {
+    /// 稳定的错误码
+    #[schema(example = "NOT_FOUND")]
+    pub code: String,
+    /// 错误信息
+    pub message: String,
+}
+
+impl ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self.0.error_type {
+            AppErrorType::AuthError => StatusCode::UNAUTHORIZED,
+            AppErrorType::Forbidden => StatusCode::FORBIDDEN,
+            AppErrorType::NotFound => StatusCode::NOT_FOUND,
+            AppErrorType::IsExists => StatusCode::CONFLICT,
+            AppErrorType::InvalidArgument
+            | AppErrorType::JsonError
+            | AppErrorType::UploadError
+            | AppErrorType::ExcelError
+            | AppErrorType::CSVError
+            | AppErrorType::ProtectedContentError
+            | AppErrorType::Common => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn body(&self) -> ApiErrorBody {
+        ApiErrorBody {
+            code: self.0.error_type.code().to_string(),
+            message: self.0.user_message(),
+        }
+    }
+}
+
+impl From<AppError> for ApiError {
+    fn from(err: AppError) -> Self {
+        ApiError(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status_code(), Json(self.body())).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_error_should_use_stable_code() {
+        let err = ApiError::from(AppError::not_found("没有找到符合条件的药品"));
+        assert_eq!(err.status_code(), StatusCode::NOT_FOUND);
+        let body = err.body();
+        assert_eq!(body.code, "NOT_FOUND");
+        assert_eq!(body.message, "没有找到符合条件的药品");
+    }
+
+    #[test]
+    fn api_error_should_hide_cause() {
+        let err = ApiError::from(AppError::from_err("connection refused", AppErrorType::DbError));
+        assert_eq!(err.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(err.body().code, "DB_ERROR");
+        assert_eq!(err.body().message, "发生错误");
+    }
+}
@@ -1,13 +1,27 @@
 use serde::Deserialize;
+use utoipa::ToSchema;
 
 #[derive(Deserialize)]
 pub struct AdminLogin {
     pub username: String,
     pub password: String,
+    #[serde(default)]
     pub hcaptcha_response: String,
+    /// 内置图片验证码的 ID 和答案
+    #[serde(default)]
+    pub captcha_id: String,
+    #[serde(default)]
+    pub captcha_answer: String,
 }
 
-#[derive(Deserialize, Debug)]
+/// 退出登录, 需要带上 csrf token, 防止被其他网站的链接或图片退出
+#[derive(Deserialize)]
+pub struct Logout {
+    #[serde(default)]
+    pub csrf_token: String,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct CreateMedicinal {
     pub name: String,
     pub category: String,
@@ -17,8 +31,10 @@ pub struct CreateMedicinal {
     pub validity: chrono::NaiveDate,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct UpdateMedicinal {
+    /// API 以路径中的 id 为准, 所以请求体中可以不传
+    #[serde(default)]
     pub id: i32,
     pub name: String,
     pub category: String,
@@ -33,22 +49,65 @@ pub struct CreateAdmin {
     pub username: String,
     pub password: String,
     pub re_password: String,
+    /// 角色, 不传则为操作员
+    #[serde(default)]
+    pub role: String,
 }
 
+/// 二步验证的动态码或恢复码
 #[derive(Deserialize, Debug)]
-pub struct UpdateAdmin {
-    pub id: i32,
-    pub username: String,
+pub struct TwoFactorCode {
+    pub code: String,
+}
+
+/// 关闭二步验证, 需要同时输入密码和动态码
+#[derive(Deserialize, Debug)]
+pub struct DisableTwoFactor {
     pub password: String,
-    pub new_password: String,
-    pub re_password: String,
+    pub code: String,
 }
 
+/// 修改自己的密码, 账号取自登录状态, 必须输入原密码
 #[derive(Deserialize, Debug)]
 pub struct ChangeAdminPassword {
-    pub id: i32,
-    pub username: String,
     pub password: String,
     pub new_password: String,
     pub re_password: String,
 }
+
+#[derive(Deserialize, Debug)]
+pub struct CreateApiToken {
+    pub name: String,
+    /// 权限范围: read 或者 write
+    pub scope: String,
+    /// 有效天数, 不填或者为 0 表示永不过期
+    pub expires_days: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateAdminRole {
+    pub role: String,
+    /// 以逗号分隔的类目, 为空表示不限制
+    #[serde(default)]
+    pub categories: String,
+}
+
+/// 后台的短信设置, 留空的字段使用配置中的值
+#[derive(Deserialize, Debug)]
+pub struct SaveSmsSetting {
+    pub access_key_id: String,
+    /// 留空表示不修改
+    pub access_key_secret: String,
+    pub sign_name: String,
+    pub template_code: String,
+    /// 接收号码, 每行一个
+    pub phones: String,
+}
+
+/// 发送测试短信
+#[derive(Deserialize, Debug)]
+pub struct SmsTest {
+    /// 接收号码, 留空时发送到设置中的号码
+    #[serde(default)]
+    pub phone: String,
+}
@@ -0,0 +1,77 @@
+use crate::arg::BackendQueryArg;
+use crate::db::admin;
+use crate::db::pagination::{AdminPage, Pagination};
+use crate::db::query::Filter;
+use crate::error::{ApiError, ApiErrorBody, AppError};
+use crate::handler::api::ApiResult;
+use crate::handler::helper::{get_client, log_error, page_size};
+use crate::model::{AdminInfo, AppState};
+use crate::rbac::{CurrentAdmin, Permission};
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Extension, Path, Query};
+use axum::Json;
+use std::sync::Arc;
+
+/// 管理员列表
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin",
+    tag = "admin",
+    params(BackendQueryArg),
+    responses(
+        (status = 200, description = "管理员分页列表", body = AdminPage),
+        (status = 401, description = "未认证", body = ApiErrorBody)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn index(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    args: std::result::Result<Query<BackendQueryArg>, QueryRejection>,
+) -> ApiResult<Pagination<Vec<AdminInfo>>> {
+    let handler_name = "api_admin_index";
+    current.require(Permission::ManageUsers)?;
+    let args = args
+        .map(|q| q.0)
+        .map_err(|err| ApiError::from(AppError::invalid_argument(&err.to_string())))?;
+    let q_keyword = format!("%{}%", args.keyword());
+    let client = get_client(&state, handler_name).await?;
+    let filter = Filter::eq("is_del", args.is_del()).and(Filter::ilike("username", q_keyword));
+    let page_size = page_size(&state, &client, current.id, args.page_size)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let list = admin::select(&client, filter, args.page(), page_size)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    Ok(Json(list.map(|data| {
+        data.into_iter().map(AdminInfo::from).collect()
+    })))
+}
+
+/// 获取单个管理员
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/{id}",
+    tag = "admin",
+    params(("id" = i32, Path, description = "管理员 ID")),
+    responses(
+        (status = 200, description = "管理员", body = AdminInfo),
+        (status = 404, description = "管理员不存在", body = ApiErrorBody)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn find(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> ApiResult<AdminInfo> {
+    let handler_name = "api_admin_find";
+    if id != current.id {
+        current.require(Permission::ManageUsers)?;
+    }
+    let client = get_client(&state, handler_name).await?;
+    let item = admin::find_by_id(&client, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    Ok(Json(item.into()))
+}
@@ -0,0 +1,287 @@
+use crate::arg::MedicinalBackendQueryArg;
+use crate::db::medicinal;
+use crate::db::pagination::{MedicinalPage, Pagination};
+use crate::error::{ApiError, ApiErrorBody, AppError};
+use crate::form::{CreateMedicinal, UpdateMedicinal};
+use crate::handler::api::{ActionResult, ApiResult};
+use crate::handler::backend::medicinal::{accessible_categories, find_for, xlsx_attachment};
+use crate::handler::helper::{get_client, log_error, page_size};
+use crate::model::{AppState, Category, MedicinalID, MedicinalList};
+use crate::rbac::{CurrentAdmin, Permission};
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{Extension, Path, Query};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use std::sync::Arc;
+
+/// 导出参数
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportArg {
+    /// 导出格式: `json` 或者 `xlsx`, 默认为 `xlsx`
+    pub format: Option<String>,
+}
+
+/// 将查询参数的解析错误转为 [`ApiError`]
+fn query_arg<T>(args: std::result::Result<Query<T>, QueryRejection>) -> Result<T, ApiError> {
+    args.map(|q| q.0)
+        .map_err(|err| ApiError::from(AppError::invalid_argument(&err.to_string())))
+}
+
+/// 将 json 请求体的解析错误转为 [`ApiError`]
+fn json_body<T>(payload: std::result::Result<Json<T>, JsonRejection>) -> Result<T, ApiError> {
+    payload
+        .map(|p| p.0)
+        .map_err(|err| ApiError::from(AppError::invalid_argument(&err.to_string())))
+}
+
+/// 校验药品的必填字段
+fn validate(name: &str, category: &str) -> Result<(), ApiError> {
+    if name.trim().is_empty() {
+        return Err(AppError::invalid_argument("药品名称不能为空").into());
+    }
+    if category.trim().is_empty() {
+        return Err(AppError::invalid_argument("药品类目不能为空").into());
+    }
+    Ok(())
+}
+
+/// 药品列表, 支持与后台列表页相同的过滤及分页参数
+#[utoipa::path(
+    get,
+    path = "/api/v1/medicinal",
+    tag = "medicinal",
+    params(MedicinalBackendQueryArg),
+    responses(
+        (status = 200, description = "药品分页列表", body = MedicinalPage),
+        (status = 400, description = "参数错误", body = ApiErrorBody),
+        (status = 401, description = "未认证", body = ApiErrorBody)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn index(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    args: std::result::Result<Query<MedicinalBackendQueryArg>, QueryRejection>,
+) -> ApiResult<Pagination<Vec<MedicinalList>>> {
+    let handler_name = "api_medicinal_index";
+    current.require(Permission::View)?;
+    let args = query_arg(args)?;
+    let client = get_client(&state, handler_name).await?;
+    let page_size = page_size(&state, &client, current.id, args.page_size)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let list = medicinal::select_by_arg(&client, &args, &current.categories, page_size)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    Ok(Json(list))
+}
+
+/// 获取单个药品
+#[utoipa::path(
+    get,
+    path = "/api/v1/medicinal/{id}",
+    tag = "medicinal",
+    params(("id" = i32, Path, description = "药品 ID")),
+    responses(
+        (status = 200, description = "药品", body = MedicinalList),
+        (status = 404, description = "药品不存在", body = ApiErrorBody)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn find(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> ApiResult<MedicinalList> {
+    let handler_name = "api_medicinal_find";
+    let client = get_client(&state, handler_name).await?;
+    let med = find_for(&client, &current, Permission::View, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    Ok(Json(med))
+}
+
+/// 添加药品
+#[utoipa::path(
+    post,
+    path = "/api/v1/medicinal",
+    tag = "medicinal",
+    request_body = CreateMedicinal,
+    responses(
+        (status = 200, description = "新药品的 ID", body = MedicinalID),
+        (status = 400, description = "参数错误", body = ApiErrorBody),
+        (status = 409, description = "药品已存在", body = ApiErrorBody)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn create(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    payload: std::result::Result<Json<CreateMedicinal>, JsonRejection>,
+) -> ApiResult<MedicinalID> {
+    let handler_name = "api_medicinal_create";
+    let cm = json_body(payload)?;
+    validate(&cm.name, &cm.category)?;
+    current.require_category(Permission::Edit, &cm.category)?;
+    let client = get_client(&state, handler_name).await?;
+    let id = medicinal::create(&client, &cm)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    medicinal::invalidate_categories(state.cache.as_ref()).await;
+    Ok(Json(id))
+}
+
+/// 更新药品, 以路径中的 id 为准
+#[utoipa::path(
+    put,
+    path = "/api/v1/medicinal/{id}",
+    tag = "medicinal",
+    params(("id" = i32, Path, description = "药品 ID")),
+    request_body = UpdateMedicinal,
+    responses(
+        (status = 200, description = "更新后的药品", body = MedicinalList),
+        (status = 400, description = "参数错误", body = ApiErrorBody),
+        (status = 404, description = "药品不存在", body = ApiErrorBody)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn update(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+    payload: std::result::Result<Json<UpdateMedicinal>, JsonRejection>,
+) -> ApiResult<MedicinalList> {
+    let handler_name = "api_medicinal_update";
+    let mut um = json_body(payload)?;
+    um.id = id;
+    validate(&um.name, &um.category)?;
+    let client = get_client(&state, handler_name).await?;
+    find_for(&client, &current, Permission::Edit, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    current.require_category(Permission::Edit, &um.category)?;
+    if !medicinal::update(&client, &um)
+        .await
+        .map_err(log_error(handler_name.to_string()))?
+    {
+        return Err(AppError::not_found("没有找到符合条件的药品").into());
+    }
+    medicinal::invalidate_categories(state.cache.as_ref()).await;
+    let med = medicinal::find(&client, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    Ok(Json(med))
+}
+
+/// 删除药品
+#[utoipa::path(
+    delete,
+    path = "/api/v1/medicinal/{id}",
+    tag = "medicinal",
+    params(("id" = i32, Path, description = "药品 ID")),
+    responses((status = 200, description = "操作结果", body = ActionResult)),
+    security(("bearer" = []))
+)]
+pub async fn del(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> ApiResult<ActionResult> {
+    let handler_name = "api_medicinal_del";
+    let client = get_client(&state, handler_name).await?;
+    find_for(&client, &current, Permission::Delete, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let success = medicinal::delete(&client, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    Ok(Json(ActionResult { id, success }))
+}
+
+/// 恢复药品
+#[utoipa::path(
+    post,
+    path = "/api/v1/medicinal/{id}/recover",
+    tag = "medicinal",
+    params(("id" = i32, Path, description = "药品 ID")),
+    responses((status = 200, description = "操作结果", body = ActionResult)),
+    security(("bearer" = []))
+)]
+pub async fn recover(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> ApiResult<ActionResult> {
+    let handler_name = "api_medicinal_recover";
+    let client = get_client(&state, handler_name).await?;
+    find_for(&client, &current, Permission::Delete, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let success = medicinal::recover(&client, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    Ok(Json(ActionResult { id, success }))
+}
+
+/// 按过滤条件导出药品, `format=json` 时返回 json 数组, 否则返回 xlsx 附件
+#[utoipa::path(
+    get,
+    path = "/api/v1/medicinal/export",
+    tag = "medicinal",
+    params(MedicinalBackendQueryArg, ExportArg),
+    responses(
+        (status = 200, description = "json 数组或者 xlsx 附件", body = [MedicinalList]),
+        (status = 400, description = "不支持的导出格式", body = ApiErrorBody)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn export(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    args: std::result::Result<Query<MedicinalBackendQueryArg>, QueryRejection>,
+    export_arg: std::result::Result<Query<ExportArg>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let handler_name = "api_medicinal_export";
+    current.require(Permission::Export)?;
+    let args = query_arg(args)?;
+    let export_arg = query_arg(export_arg)?;
+    let client = get_client(&state, handler_name).await?;
+    let result = medicinal::all_by_arg(&client, &args, &current.categories)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    match export_arg.format.as_deref() {
+        Some("json") => Ok(Json(result).into_response()),
+        None | Some("xlsx") => Ok(xlsx_attachment(&result, handler_name)
+            .await?
+            .into_response()),
+        Some(format) => Err(AppError::invalid_argument(&format!(
+            "不支持的导出格式: {}",
+            format
+        ))
+        .into()),
+    }
+}
+
+/// 所有类目
+#[utoipa::path(
+    get,
+    path = "/api/v1/categories",
+    tag = "medicinal",
+    responses((status = 200, description = "所有类目", body = [Category])),
+    security(("bearer" = []))
+)]
+pub async fn categories(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+) -> ApiResult<Vec<Category>> {
+    let handler_name = "api_medicinal_categories";
+    current.require(Permission::View)?;
+    let client = get_client(&state, handler_name).await?;
+    let categories = accessible_categories(&state, &client, &current)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    Ok(Json(categories))
+}
@@ -0,0 +1,72 @@
+//! 对外提供的 JSON API, 供病房平板、报表脚本等非浏览器的系统调用
+//!
+//! 所有接口都挂在 `/api/v1` 下, 错误统一以 [`ApiError`] 的 json 形式返回
+
+pub mod admin;
+pub mod medicinal;
+pub mod openapi;
+
+use crate::error::ApiError;
+use crate::middleware::admin_auth::Auth;
+use axum::async_trait;
+use axum::body::{Bytes, HttpBody};
+use axum::extract::{extractor_middleware, FromRequest, RequestParts};
+use axum::routing::{get, post};
+use axum::{BoxError, Json, Router};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// API 处理结果
+type ApiResult<T> = std::result::Result<Json<T>, ApiError>;
+
+/// 删除/恢复等操作的结果
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ActionResult {
+    pub id: i32,
+    pub success: bool,
+}
+
+pub fn routers() -> Router {
+    // API 文档不需要认证
+    Router::new()
+        .route("/openapi.json", get(openapi::spec))
+        .merge(protected_routers())
+}
+
+fn protected_routers() -> Router {
+    Router::new()
+        .route(
+            "/medicinal",
+            get(medicinal::index).post(medicinal::create),
+        )
+        .route("/medicinal/export", get(medicinal::export))
+        .route(
+            "/medicinal/:id",
+            get(medicinal::find)
+                .put(medicinal::update)
+                .delete(medicinal::del),
+        )
+        .route("/medicinal/:id/recover", post(medicinal::recover))
+        .route("/categories", get(medicinal::categories))
+        .route("/admin", get(admin::index))
+        .route("/admin/:id", get(admin::find))
+        .layer(extractor_middleware::<ApiAuth>())
+}
+
+/// API 的鉴权, 与后台共用 [`Auth`] 的逻辑, 只是失败时返回 json
+pub struct ApiAuth {}
+
+#[async_trait]
+impl<B> FromRequest<B> for ApiAuth
+where
+    B: HttpBody + From<Bytes> + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        Auth::from_request(req).await.map_err(ApiError::from)?;
+        Ok(ApiAuth {})
+    }
+}
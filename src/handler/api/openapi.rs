@@ -0,0 +1,103 @@
+//! OpenAPI 3 文档, 由 handler 上的 `#[utoipa::path]` 及类型上的 `ToSchema` 生成,
+//! 所以文档不会和实际的接口脱节. 离线的调试页面见 `/static/api/index.html`
+
+use crate::db::pagination::{AdminPage, MedicinalPage};
+use crate::error::ApiErrorBody;
+use crate::form::{CreateMedicinal, UpdateMedicinal};
+use crate::handler::api::{admin, medicinal, ActionResult};
+use crate::model::{AdminInfo, Category, MedicinalID, MedicinalList};
+use axum::Json;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        medicinal::index,
+        medicinal::create,
+        medicinal::find,
+        medicinal::update,
+        medicinal::del,
+        medicinal::recover,
+        medicinal::export,
+        medicinal::categories,
+        admin::index,
+        admin::find,
+    ),
+    components(schemas(
+        CreateMedicinal,
+        UpdateMedicinal,
+        MedicinalList,
+        MedicinalID,
+        MedicinalPage,
+        Category,
+        AdminInfo,
+        AdminPage,
+        ActionResult,
+        ApiErrorBody,
+    )),
+    modifiers(&SecurityAddon),
+    tags(
+        (name = "medicinal", description = "药品"),
+        (name = "admin", description = "管理员")
+    )
+)]
+pub struct ApiDoc;
+
+/// 注册 bearer token 认证方式
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            )
+        }
+    }
+}
+
+/// 输出 OpenAPI 文档
+pub async fn spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_should_cover_all_routes() {
+        let doc = ApiDoc::openapi();
+        let mut paths: Vec<&str> = doc.paths.paths.keys().map(|k| k.as_str()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                "/api/v1/admin",
+                "/api/v1/admin/{id}",
+                "/api/v1/categories",
+                "/api/v1/medicinal",
+                "/api/v1/medicinal/export",
+                "/api/v1/medicinal/{id}",
+                "/api/v1/medicinal/{id}/recover",
+            ]
+        );
+    }
+
+    #[test]
+    fn spec_should_contain_schemas() {
+        let doc = ApiDoc::openapi();
+        let schemas = &doc.components.as_ref().unwrap().schemas;
+        for name in [
+            "CreateMedicinal",
+            "UpdateMedicinal",
+            "MedicinalList",
+            "MedicinalPage",
+            "ApiErrorBody",
+        ] {
+            assert!(schemas.contains_key(name), "missing schema {}", name);
+        }
+    }
+}
@@ -1,33 +1,40 @@
-use crate::db::admin;
-use crate::error::AppError;
-use crate::handler::backend::get_login_admin_by_cookie;
+use crate::db::{admin, login_event, role_setting};
+use crate::error::{AppError, AppErrorType};
+use crate::handler::backend::{get_login_admin_by_cookie, two_factor};
 use crate::handler::helper::{get_client, log_error, render};
-use crate::handler::redirect::{redirect, redirect_with_cookie};
-use crate::html::auth::LoginTemplate;
-use crate::model::{AdminSession, AppState};
+use crate::handler::redirect::{redirect, redirect_with_cookie, redirect_with_cookies};
+use crate::html::auth::{LoginTemplate, TwoFactorTemplate};
+use crate::model::{Admin, AdminSession, AppState};
 use crate::session::gen_redis_key;
-use crate::{form, hcaptcha, password, rdb, session, Result};
-use axum::extract::{Extension, Form};
+use crate::{captcha, csrf, form, login_guard, password, session, Result};
+use axum::extract::{ConnectInfo, Extension, Form};
+use axum::http::header::USER_AGENT;
 use axum::http::{HeaderMap, StatusCode};
-use axum::response::Html;
-use serde_json::json;
+use axum::response::{Html, IntoResponse};
+use std::net::SocketAddr;
 use std::ops::Add;
 use std::sync::Arc;
 use tower_cookies::{Cookie, Cookies};
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
 pub async fn admin_login_ui(
     Extension(state): Extension<Arc<AppState>>,
-    Extension(ck): Extension<Cookies>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> Result<Html<String>> {
     let handler_name = "admin_login_ui";
-    let site_key = state.hcap_cfg.site_key.clone();
-    let tmpl = LoginTemplate { site_key };
+    let ip = login_guard::client_ip(&state.login_cfg, &headers, &addr);
+    let captcha = captcha::widget(&state, &ip)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let tmpl = LoginTemplate { captcha };
     render(tmpl, handler_name)
 }
 
 pub async fn admin_login(
     Extension(state): Extension<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Form(login): Form<form::AdminLogin>,
     Extension(ck): Extension<Cookies>,
 ) -> Result<(StatusCode, HeaderMap, ())> {
@@ -40,58 +47,226 @@ pub async fn admin_login(
     }
 
     let handler_name = "auth_login";
-    // 不用去验证图片验证码
-    // let is_valid = hcaptcha::verify(
-    //     login.hcaptcha_response.clone(),
-    //     state.hcap_cfg.secret_key.clone(),
-    // )
-    // .await?;
-    // if !is_valid {
-    //     debug!("hcaptcha verification failed");
-    //     // return Err(AppError::auth_error("人机验证失败"));
-    // }
+    let ip = login_guard::client_ip(&state.login_cfg, &headers, &addr);
+    login_guard::check(state.cache.as_ref(), &state.login_cfg, &login.username, &ip).await?;
+    captcha::verify(&state, &ip, &login).await?;
     let client = get_client(&state, handler_name).await?;
-    let login_admin = admin::find(&client, &login.username)
+    // 用户名不存在和密码错误返回相同的提示, 避免被用来探测用户名
+    let login_admin = match admin::find(&client, &login.username).await {
+        Ok(login_admin) => login_admin,
+        Err(AppError {
+            error_type: AppErrorType::NotFound,
+            ..
+        }) => {
+            let reason = "用户名不存在";
+            return Err(login_failed(&state, &client, &login.username, &ip, reason).await);
+        }
+        Err(err) => return Err(log_error(handler_name.to_string())(err)),
+    };
+    if !password::verify(&login.password, &login_admin.password)? {
+        let reason = "密码错误";
+        return Err(login_failed(&state, &client, &login.username, &ip, reason).await);
+    }
+    if login_admin.is_disabled {
+        return Err(AppError::auth_error("账号已被禁用, 请联系管理员"));
+    }
+    if login_admin.totp_enabled {
+        // 密码正确, 还需要输入动态码才能完成登录, 输入正确之前不清除失败次数
+        let id = session::id();
+        let key = pending_2fa_key(&state, &id);
+        let admin_id = login_admin.id.to_string();
+        state.cache.set_ex(&key, &admin_id, PENDING_2FA_TTL).await?;
+        let cookie = session::cookie(&state.sess_cfg, &pending_2fa_cookie(&state), &id, true);
+        return redirect_with_cookie("/login/2fa", Some(&cookie));
+    }
+    login_guard::record_success(state.cache.as_ref(), &state.login_cfg, &login.username).await?;
+    start_session(&state, &client, login_admin, &ip, &headers).await
+}
+
+/// 等待输入动态码的登录的有效期, 单位秒
+const PENDING_2FA_TTL: usize = 300;
+
+fn pending_2fa_key(state: &AppState, id: &str) -> String {
+    format!("{}2fa:{}", &state.login_cfg.prefix, id)
+}
+
+fn pending_2fa_cookie(state: &AppState) -> String {
+    format!("{}_2fa", &state.sess_cfg.id_name)
+}
+
+/// 取出等待输入动态码的管理员 ID 和对应的 redis 键
+async fn pending_2fa(state: &AppState, ck: &Cookies) -> Result<Option<(i32, String)>> {
+    let id = match ck.get(&pending_2fa_cookie(state)) {
+        Some(c) => c.value().to_owned(),
+        None => return Ok(None),
+    };
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    let key = pending_2fa_key(state, &id);
+    let admin_id = state.cache.get_str(&key).await?;
+    Ok(admin_id.and_then(|s| s.parse().ok()).map(|admin_id| (admin_id, key)))
+}
+
+pub async fn admin_login_2fa_ui(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(ck): Extension<Cookies>,
+) -> Result<axum::response::Response> {
+    let handler_name = "admin_login_2fa_ui";
+    if pending_2fa(&state, &ck).await?.is_none() {
+        return Ok(redirect("/login")?.into_response());
+    }
+    let tmpl = TwoFactorTemplate {};
+    Ok(render(tmpl, handler_name)?.into_response())
+}
+
+/// 登录的第二步, 校验动态码或恢复码
+pub async fn admin_login_2fa(
+    Extension(state): Extension<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Form(tf): Form<form::TwoFactorCode>,
+    Extension(ck): Extension<Cookies>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "auth_login_2fa";
+    let (admin_id, key) = pending_2fa(&state, &ck)
+        .await?
+        .ok_or_else(|| AppError::auth_error("登录已过期, 请重新登录"))?;
+    let client = get_client(&state, handler_name).await?;
+    let login_admin = admin::find_by_id(&client, admin_id)
         .await
         .map_err(log_error(handler_name.to_string()))?;
-    debug!("passwd: {:?}", password::hash(&login.password));
-    if !password::verify(&login.password, &login_admin.password)? {
-        return Err(AppError::auth_error("用户名或密码错误"));
+    if login_admin.is_disabled {
+        return Err(AppError::auth_error("账号已被禁用, 请联系管理员"));
     }
-    let cfg = state.sess_cfg.clone();
-    let dateline = chrono::Local::now().add(chrono::Duration::seconds(cfg.expired as i64));
+    let ip = login_guard::client_ip(&state.login_cfg, &headers, &addr);
+    login_guard::check(state.cache.as_ref(), &state.login_cfg, &login_admin.username, &ip).await?;
+    if !two_factor::verify_second_factor(&client, &login_admin, &tf.code).await? {
+        record_failure(&state, &client, &login_admin.username, &ip, "动态码错误").await;
+        return Err(AppError::auth_error("动态码错误"));
+    }
+    state.cache.del(&key).await?;
+    login_guard::record_success(state.cache.as_ref(), &state.login_cfg, &login_admin.username).await?;
+    start_session(&state, &client, login_admin, &ip, &headers).await
+}
+
+/// 浏览器标识的最大长度, 超出的部分截掉
+const MAX_USER_AGENT_LEN: usize = 200;
+
+fn user_agent(headers: &HeaderMap) -> String {
+    headers
+        .get(USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .chars()
+        .take(MAX_USER_AGENT_LEN)
+        .collect()
+}
+
+/// 创建登录 session 并写入 cookie
+async fn start_session(
+    state: &AppState,
+    client: &deadpool_postgres::Client,
+    login_admin: Admin,
+    ip: &str,
+    headers: &HeaderMap,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "auth_start_session";
+    let categories = admin::categories(client, login_admin.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let must_enroll_2fa = !login_admin.totp_enabled
+        && role_setting::requires_2fa(client, &login_admin.role)
+            .await
+            .map_err(log_error(handler_name.to_string()))?;
+    let cfg = &state.sess_cfg;
+    let now = chrono::Local::now();
+    let dateline = now.add(chrono::Duration::seconds(cfg.expired as i64));
     debug!("dateline: {:?}", dateline);
-    let data = json!(AdminSession {
+    let must_change_password = login_admin.must_change_password;
+    let admin_session = AdminSession {
         id: login_admin.id,
         username: login_admin.username,
         dateline: dateline.timestamp() as i32,
-        password: login_admin.password,
         is_sys: login_admin.is_sys,
-    });
-    let data = data.to_string();
-    debug!("data: {:?}", data);
-    // data: "{\"dateline\":1647955504,\"id\":1,\"is_sys\":true,\"password\":\"$2b$12$QW8Lmf0gvsb1xtRJLxJxzea2M2p5Pxx1LrmPuVzria5obcY8u890C\",\"username\":\"wgr\"}"
-    let session::GeneratedKey {
-        id,
-        cookie_key,
-        redis_key,
-    } = session::gen_key(&cfg);
-    debug!(
-        "id: {:?}, cookie_key: {:?}, redis_key: {:?}",
-        id, cookie_key, redis_key
+        role: login_admin.role,
+        categories,
+        must_change_password,
+        must_enroll_2fa,
+        ip: ip.to_string(),
+        user_agent: user_agent(headers),
+        created_at: now.timestamp(),
+        last_seen: now.timestamp(),
+        csrf_token: csrf::generate(),
+    };
+    let id = session::create(state.cache.as_ref(), cfg, &admin_session)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let cookies = [
+        session::cookie(cfg, &cfg.id_name, &id, true),
+        // 页面脚本需要读取 csrf token, 不能设置 HttpOnly
+        session::cookie(cfg, &csrf::cookie_name(cfg), &admin_session.csrf_token, false),
+        // 登录完成, 不再需要等待输入动态码的 cookie
+        session::removal_cookie(cfg, &pending_2fa_cookie(state)),
+    ];
+    let cookies = [
+        cookies[0].as_str(),
+        cookies[1].as_str(),
+        cookies[2].as_str(),
+    ];
+    if must_change_password {
+        return redirect_with_cookies("/admin/password?msg=请先修改临时密码", &cookies);
+    }
+    if must_enroll_2fa {
+        return redirect_with_cookies("/admin/2fa?msg=你的角色要求启用二步验证", &cookies);
+    }
+    redirect_with_cookies("/admin", &cookies)
+}
+
+/// 用户名或密码错误, 记录后返回统一的提示
+async fn login_failed(
+    state: &AppState,
+    client: &deadpool_postgres::Client,
+    username: &str,
+    ip: &str,
+    reason: &str,
+) -> AppError {
+    record_failure(state, client, username, ip, reason).await;
+    AppError::auth_error("用户名或密码错误")
+}
+
+/// 记录登录失败, 并按连续失败的次数延迟响应
+async fn record_failure(
+    state: &AppState,
+    client: &deadpool_postgres::Client,
+    username: &str,
+    ip: &str,
+    reason: &str,
+) {
+    warn!(
+        "login failed: username={}, ip={}, reason={}",
+        username, ip, reason
     );
-    // id: "4d8e927e7dc44ef696351ec59438f5ee", cookie_key: "axumrs_session", redis_key: "axumrs:session:4d8e927e7dc44ef696351ec59438f5ee"
-    rdb::set(&state.rdc, &redis_key, &data, cfg.expired).await?;
-    // .map_err(AppError::form)?; // TODO:
-    let cookie = format!("{}={}", cookie_key, id);
-    debug!("to set cookie: {:?}", cookie);
-    // let cookie_item = Cookie::new(cookie_key, id);
-    redirect_with_cookie("/admin", Some(&cookie))
+    if let Err(err) = login_event::create(client, username, ip, reason).await {
+        error!("save login event failed: {:?}", err);
+    }
+    match login_guard::record_failure(state.cache.as_ref(), &state.login_cfg, username, ip).await {
+        Ok((_, true)) => {
+            warn!("login locked: username={}, ip={}", username, ip);
+            let _ = login_event::create(client, username, ip, "失败次数过多, 已锁定").await;
+        }
+        Ok((failures, false)) => {
+            tokio::time::sleep(login_guard::delay_for(&state.login_cfg, failures)).await;
+        }
+        Err(err) => error!("record login failure failed: {:?}", err),
+    }
 }
 
+/// 退出登录, 只接受带 csrf token 的 POST 请求
 pub async fn admin_logout(
     Extension(state): Extension<Arc<AppState>>,
     Extension(ck): Extension<Cookies>,
+    Form(logout): Form<form::Logout>,
 ) -> Result<(StatusCode, HeaderMap, ())> {
     let cfg = state.sess_cfg.clone();
     let cookie: Option<String> = ck
@@ -99,12 +274,21 @@ pub async fn admin_logout(
         .and_then(|c| Some(c.value().to_owned()));
 
     if let Some(val) = cookie {
+        if let Ok(Some(admin_session)) = get_login_admin_by_cookie(&state, &ck).await {
+            if !csrf::token_matches(&admin_session.csrf_token, &logout.csrf_token) {
+                return Err(AppError::forbidden(
+                    "页面已过期或请求来源不正确, 请刷新页面后重试",
+                ));
+            }
+            let _ = session::untrack(state.cache.as_ref(), &cfg, admin_session.id, &val).await;
+        }
         let redis_key = gen_redis_key(&cfg, &val);
         debug!("logout delete redis_key: {:?}", redis_key);
-        rdb::del(&state.rdc, &redis_key).await.map_err(|e| {
+        state.cache.del(&redis_key).await.map_err(|e| {
             error!("logout delete redis_key: {:?} failed: {:?}", redis_key, e);
         });
     }
-    let cookie_logout = format!("{}=", &cfg.id_name);
-    redirect_with_cookie("/login", Some(&cookie_logout))
+    let session_cookie = session::removal_cookie(&cfg, &cfg.id_name);
+    let csrf_cookie = session::removal_cookie(&cfg, &csrf::cookie_name(&cfg));
+    redirect_with_cookies("/login", &[&session_cookie, &csrf_cookie])
 }
@@ -1,152 +1,415 @@
 use crate::db::admin;
+use crate::db::query::Filter;
 use crate::error::{AppError, AppErrorType};
-use crate::form::{CreateAdmin, UpdateAdmin};
-use crate::handler::backend::get_login_admin_by_cookie;
-use crate::handler::helper::{get_client, log_error, render};
-use crate::handler::redirect::redirect;
-use crate::html::backend::admin::{AddTemplate, EditTemplate, IndexTemplate};
+use crate::form::{ChangeAdminPassword, CreateAdmin, UpdateAdminRole};
+use crate::handler::helper::{get_client, log_error, page_size, render};
+use crate::handler::redirect::{redirect, redirect_with_cookies};
+use crate::html::backend::admin::{
+    AddTemplate, EditTemplate, IndexTemplate, PasswordTemplate, ResetTemplate,
+};
+use crate::html::backend::index::ConfirmTemplate;
 use crate::model::AppState;
-use crate::{arg, password, Result};
+use crate::rbac::{self, CurrentAdmin, Permission, Role};
+use crate::{arg, csrf, login_guard, password, session, Result};
 use axum::extract::{Extension, Form, Path, Query};
 use axum::http::{HeaderMap, StatusCode};
 use axum::response::Html;
 use std::sync::Arc;
-use tower_cookies::Cookies;
 
 pub async fn index(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     args: Option<Query<arg::BackendQueryArg>>,
 ) -> Result<Html<String>> {
     let handler_name = "backend_admin_index";
+    current.require(Permission::ManageUsers)?;
     let args = args.unwrap();
     let q_keyword = format!("%{}%", args.keyword());
     let client = get_client(&state, handler_name).await?;
-    let admin_list = admin::select(
-        &client,
-        Some("is_del=$1 AND username ILIKE $2"),
-        &[&args.is_del(), &q_keyword],
-        args.page(),
-    )
-    .await
-    .map_err(log_error(handler_name.to_string()))?;
+    let filter = Filter::eq("is_del", args.is_del()).and(Filter::ilike("username", q_keyword));
+    let page_size = page_size(&state, &client, current.id, args.page_size)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let admin_list = admin::select(&client, filter, args.page(), page_size)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let mut locked = vec![];
+    for item in admin_list.data.iter() {
+        if login_guard::is_locked(state.cache.as_ref(), &state.login_cfg, &item.username).await? {
+            locked.push(item.id);
+        }
+    }
     let tmpl = IndexTemplate {
         list: admin_list,
         arg: args.0,
+        locked,
     };
     render(tmpl, handler_name)
 }
 
-pub async fn add() -> Result<Html<String>> {
+pub async fn add(Extension(current): Extension<CurrentAdmin>) -> Result<Html<String>> {
     let handler_name = "backend_admin_add";
-    let tmpl = AddTemplate {};
+    current.require(Permission::ManageUsers)?;
+    let tmpl = AddTemplate { roles: Role::all() };
     render(tmpl, handler_name)
 }
 
 pub async fn add_action(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     Form(ca): Form<CreateAdmin>,
 ) -> Result<(StatusCode, HeaderMap, ())> {
     let handler_name = "backend_admin_add_action";
-    if ca.password.is_empty() {
+    current.require(Permission::ManageUsers)?;
+    check_new_password(&ca.username, &ca.password, &ca.re_password)?;
+    let role: Role = ca.role.parse()?;
+    let client = get_client(&state, handler_name).await?;
+    let mut ca = CreateAdmin { ..ca };
+    ca.password = password::hash(&ca.password)?;
+    ca.role = role.as_str().to_string();
+    admin::create(&client, ca)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    redirect("/admin/admin?msg=账号添加成功")
+}
+
+/// 检查新密码是否符合要求
+fn check_new_password(username: &str, password: &str, re_password: &str) -> Result<()> {
+    if password.is_empty() {
         return Err(AppError::from_str("请输入密码", AppErrorType::Common));
     }
 
-    if ca.password.len() < 6 {
+    if password.len() < 6 {
         return Err(AppError::from_str(
             "密码长度不能小于6位",
             AppErrorType::Common,
         ));
     }
 
-    if ca.username.contains(ca.password.as_str()) {
+    if username.contains(password) {
         return Err(AppError::from_str(
             "密码设置不安全,请重新设置",
             AppErrorType::Common,
         ));
     }
 
-    if &ca.password != &ca.re_password {
+    if password != re_password {
         return Err(AppError::from_str(
             "两次输入的密码不一致",
             AppErrorType::Common,
         ));
     }
-    let client = get_client(&state, handler_name).await?;
-    let mut ca = CreateAdmin { ..ca };
-    ca.password = password::hash(&ca.password)?;
-    admin::create(&client, ca)
-        .await
-        .map_err(log_error(handler_name.to_string()))?;
-    redirect("/admin/admin?msg=账号添加成功")
+    Ok(())
 }
 
 pub async fn edit(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     Path(id): Path<i32>,
 ) -> Result<Html<String>> {
     let handler_name = "backend_admin_edit";
+    current.require(Permission::ManageUsers)?;
     let client = get_client(&state, handler_name).await?;
     let item = admin::find_by_id(&client, id)
         .await
         .map_err(log_error(handler_name.to_string()))?;
-    let tmpl = EditTemplate { admin: item };
+    let categories = admin::categories(&client, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let sessions = session::list_admin(state.cache.as_ref(), &state.sess_cfg, id, "")
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let tmpl = EditTemplate {
+        admin: item,
+        roles: Role::all(),
+        categories: categories.join(","),
+        sessions,
+    };
     render(tmpl, handler_name)
 }
 
-pub async fn edit_action(
+/// 修改自己的密码
+pub async fn password(
+    Extension(current): Extension<CurrentAdmin>,
+    args: Option<Query<arg::BackendQueryArg>>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_admin_password";
+    let tmpl = PasswordTemplate {
+        username: current.username,
+        arg: args.unwrap().0,
+    };
+    render(tmpl, handler_name)
+}
+
+pub async fn password_action(
     Extension(state): Extension<Arc<AppState>>,
-    Form(ua): Form<UpdateAdmin>,
-    Extension(ck): Extension<Cookies>,
+    Extension(current): Extension<CurrentAdmin>,
+    Form(cp): Form<ChangeAdminPassword>,
 ) -> Result<(StatusCode, HeaderMap, ())> {
-    let handler_name = "backend_admin_edit_action";
-    if ua.new_password.is_empty() {
-        return Err(AppError::from_str("请输入密码", AppErrorType::Common));
+    let handler_name = "backend_admin_password_action";
+    check_new_password(&current.username, &cp.new_password, &cp.re_password)?;
+    let client = get_client(&state, handler_name).await?;
+    // 原密码以数据库中的为准
+    let item = admin::find_by_id(&client, current.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    if !password::verify(&cp.password, &item.password)? {
+        return Err(AppError::auth_error("你输入的原密码错误."));
     }
-    if &ua.new_password != &ua.re_password {
+    if cp.password == cp.new_password {
         return Err(AppError::from_str(
-            "两次输入的密码不一致",
+            "新密码不能与原密码相同",
             AppErrorType::Common,
         ));
     }
+    let hashed = password::hash(&cp.new_password)?;
+    admin::update_password(&client, current.id, &hashed, false)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    // 所有设备上的登录都失效, 包括当前的
+    session::revoke_admin(state.cache.as_ref(), &state.sess_cfg, current.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let cfg = &state.sess_cfg;
+    let session_cookie = session::removal_cookie(cfg, &cfg.id_name);
+    let csrf_cookie = session::removal_cookie(cfg, &csrf::cookie_name(cfg));
+    redirect_with_cookies("/login", &[&session_cookie, &csrf_cookie])
+}
 
-    let admin_session = get_login_admin_by_cookie(&state, &ck).await?;
-    if admin_session.is_none() {
-        return Err(AppError::auth_error("UNAUTHENTICATED"));
+/// 管理员重置别人的密码, 生成临时密码, 对方下次登录必须修改
+pub async fn reset_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_admin_reset_action";
+    current.require(Permission::ManageUsers)?;
+    if id == current.id {
+        return Err(AppError::from_str(
+            "请通过修改密码页面修改自己的密码",
+            AppErrorType::Common,
+        ));
     }
+    let client = get_client(&state, handler_name).await?;
+    let item = admin::find_by_id(&client, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let temporary = password::temporary();
+    let hashed = password::hash(&temporary)?;
+    admin::update_password(&client, id, &hashed, true)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    session::revoke_admin(state.cache.as_ref(), &state.sess_cfg, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let tmpl = ResetTemplate {
+        username: item.username,
+        password: temporary,
+    };
+    render(tmpl, handler_name)
+}
 
-    let admin_session = admin_session.unwrap();
-    if !password::verify(&ua.password, &admin_session.password)? {
-        return Err(AppError::auth_error("你输入的密码错误."));
+/// 修改角色及限定的类目
+pub async fn role_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+    Form(ur): Form<UpdateAdminRole>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_admin_role_action";
+    current.require(Permission::ManageUsers)?;
+    if id == current.id {
+        return Err(AppError::from_str(
+            "不能修改自己的角色",
+            AppErrorType::Common,
+        ));
     }
-    let mut ua = UpdateAdmin { ..ua };
-    ua.new_password = password::hash(&ua.new_password)?;
+    let role: Role = ur.role.parse()?;
+    let categories = rbac::parse_categories(&ur.categories);
     let client = get_client(&state, handler_name).await?;
-    admin::update(&client, ua)
+    admin::update_role(&client, id, role.as_str(), &categories)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    session::revoke_admin(state.cache.as_ref(), &state.sess_cfg, id)
         .await
         .map_err(log_error(handler_name.to_string()))?;
-    redirect("/admin/admin?msg=账号修改成功")
+    redirect("/admin/admin?msg=角色修改成功")
 }
 
 pub async fn del(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     Path(id): Path<i32>,
-) -> Result<(StatusCode, HeaderMap, ())> {
+) -> Result<Html<String>> {
     let handler_name = "backend_admin_del";
+    confirm(&state, &current, id, handler_name, true).await
+}
+
+/// 删除、恢复账号的确认页面
+async fn confirm(
+    state: &AppState,
+    current: &CurrentAdmin,
+    id: i32,
+    handler_name: &str,
+    is_del: bool,
+) -> Result<Html<String>> {
+    current.require(Permission::ManageUsers)?;
+    let client = get_client(state, handler_name).await?;
+    let item = admin::find_by_id(&client, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let (op, path) = if is_del {
+        ("删除", "del")
+    } else {
+        ("恢复", "recover")
+    };
+    let tmpl = ConfirmTemplate {
+        parent_title: "账号管理".to_string(),
+        parent_url: "admin".to_string(),
+        title: format!("{}账号", op),
+        message: format!("确定{}账号 {}?", op, item.username),
+        action: format!("/admin/admin/{}/{}", path, id),
+        button: op.to_string(),
+        danger: is_del,
+    };
+    render(tmpl, handler_name)
+}
+
+pub async fn del_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_admin_del_action";
+    current.require(Permission::ManageUsers)?;
     let client = get_client(&state, handler_name).await?;
     admin::del_or_recover(&client, id, true)
         .await
         .map_err(log_error(handler_name.to_string()))?;
+    session::revoke_admin(state.cache.as_ref(), &state.sess_cfg, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
     redirect("/admin/admin?msg=账号删除成功")
 }
 
 pub async fn recover(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     Path(id): Path<i32>,
-) -> Result<(StatusCode, HeaderMap, ())> {
+) -> Result<Html<String>> {
     let handler_name = "backend_admin_recover";
+    confirm(&state, &current, id, handler_name, false).await
+}
+
+pub async fn recover_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_admin_recover_action";
+    current.require(Permission::ManageUsers)?;
     let client = get_client(&state, handler_name).await?;
     admin::del_or_recover(&client, id, false)
         .await
         .map_err(log_error(handler_name.to_string()))?;
     redirect("/admin/admin?msg=账号恢复成功")
 }
+
+pub async fn disable_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_admin_disable_action";
+    current.require(Permission::ManageUsers)?;
+    if id == current.id {
+        return Err(AppError::from_str(
+            "不能禁用自己的账号",
+            AppErrorType::Common,
+        ));
+    }
+    let client = get_client(&state, handler_name).await?;
+    admin::disable_or_enable(&client, id, true)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    session::revoke_admin(state.cache.as_ref(), &state.sess_cfg, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    redirect("/admin/admin?msg=账号已禁用")
+}
+
+pub async fn enable_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_admin_enable_action";
+    current.require(Permission::ManageUsers)?;
+    let client = get_client(&state, handler_name).await?;
+    admin::disable_or_enable(&client, id, false)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    redirect("/admin/admin?msg=账号已启用")
+}
+
+/// 解除因登录失败次数过多而导致的锁定
+pub async fn unlock_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_admin_unlock_action";
+    current.require(Permission::ManageUsers)?;
+    let client = get_client(&state, handler_name).await?;
+    let item = admin::find_by_id(&client, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    login_guard::unlock(state.cache.as_ref(), &state.login_cfg, &item.username)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    redirect("/admin/admin?msg=账号已解锁")
+}
+
+/// 重置别人的二步验证, 用于对方丢失手机且没有恢复码的情况
+pub async fn reset_2fa_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_admin_reset_2fa_action";
+    current.require(Permission::ManageUsers)?;
+    if id == current.id {
+        return Err(AppError::from_str(
+            "请在二步验证页面管理自己的设置",
+            AppErrorType::Common,
+        ));
+    }
+    let client = get_client(&state, handler_name).await?;
+    admin::disable_totp(&client, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    session::revoke_admin(state.cache.as_ref(), &state.sess_cfg, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    redirect("/admin/admin?msg=二步验证已重置")
+}
+
+/// 强制管理员下线, 注销对方所有的登录
+pub async fn logout_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_admin_logout_action";
+    current.require(Permission::ManageUsers)?;
+    if id == current.id {
+        return Err(AppError::from_str(
+            "请在我的登录页面管理自己的登录",
+            AppErrorType::Common,
+        ));
+    }
+    session::revoke_admin(state.cache.as_ref(), &state.sess_cfg, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    redirect("/admin/admin?msg=已强制下线")
+}
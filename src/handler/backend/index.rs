@@ -1,10 +1,20 @@
 use crate::handler::helper::render;
 use crate::html::backend::index::IndexTemplate;
+use crate::model::AppState;
 use crate::Result;
+use axum::extract::Extension;
 use axum::response::Html;
+use std::sync::Arc;
 
-pub async fn index() -> Result<Html<String>> {
+pub async fn index(Extension(state): Extension<Arc<AppState>>) -> Result<Html<String>> {
     let handler_name = "backend_index";
-    let tmpl = IndexTemplate {};
+    let next_run = match state.next_run.read() {
+        Ok(next_run) => next_run.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string()),
+        Err(_) => None,
+    };
+    let tmpl = IndexTemplate {
+        plan: state.plan.to_string(),
+        next_run,
+    };
     render(tmpl, handler_name)
 }
@@ -0,0 +1,32 @@
+use crate::db::login_event;
+use crate::db::query::Filter;
+use crate::handler::helper::{get_client, log_error, page_size, render};
+use crate::html::backend::login_event::IndexTemplate;
+use crate::model::AppState;
+use crate::rbac::{CurrentAdmin, Permission};
+use crate::{arg, Result};
+use axum::extract::{Extension, Query};
+use axum::response::Html;
+use std::sync::Arc;
+
+/// 登录失败记录, 按用户名或 IP 搜索
+pub async fn index(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    args: Option<Query<arg::BackendQueryArg>>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_login_event_index";
+    current.require(Permission::ManageUsers)?;
+    let args = args.unwrap();
+    let q_keyword = format!("%{}%", args.keyword());
+    let client = get_client(&state, handler_name).await?;
+    let filter = Filter::ilike("username", q_keyword.clone()).or(Filter::like("ip", q_keyword));
+    let page_size = page_size(&state, &client, current.id, args.page_size)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let list = login_event::select(&client, filter, args.page(), page_size)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let tmpl = IndexTemplate { list, arg: args.0 };
+    render(tmpl, handler_name)
+}
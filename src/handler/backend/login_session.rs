@@ -0,0 +1,81 @@
+use crate::error::AppError;
+use crate::handler::helper::{log_error, render};
+use crate::handler::redirect::redirect;
+use crate::html::backend::login_session::IndexTemplate;
+use crate::model::AppState;
+use crate::rbac::CurrentAdmin;
+use crate::{arg, session, Result};
+use axum::extract::{Extension, Path, Query};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Html;
+use std::sync::Arc;
+use tower_cookies::Cookies;
+
+/// 当前浏览器的 session id, 使用 API token 访问时为空
+fn current_id(state: &AppState, ck: &Cookies) -> String {
+    ck.get(&state.sess_cfg.id_name)
+        .map(|c| c.value().to_owned())
+        .unwrap_or_default()
+}
+
+/// 我的登录
+pub async fn index(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Extension(ck): Extension<Cookies>,
+    args: Option<Query<arg::BackendQueryArg>>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_login_session_index";
+    let list = session::list_admin(
+        state.cache.as_ref(),
+        &state.sess_cfg,
+        current.id,
+        &current_id(&state, &ck),
+    )
+    .await
+    .map_err(log_error(handler_name.to_string()))?;
+    let tmpl = IndexTemplate {
+        list,
+        arg: args.unwrap().0,
+    };
+    render(tmpl, handler_name)
+}
+
+/// 注销自己的某一个登录
+pub async fn revoke_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Extension(ck): Extension<Cookies>,
+    Path(id): Path<String>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_login_session_revoke_action";
+    if id == current_id(&state, &ck) {
+        return Err(AppError::invalid_argument("请使用退出登录注销当前的登录"));
+    }
+    let revoked = session::revoke(state.cache.as_ref(), &state.sess_cfg, current.id, &id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    if !revoked {
+        return Err(AppError::not_found("登录不存在或已失效"));
+    }
+    redirect("/admin/session?msg=已注销")
+}
+
+/// 注销除当前浏览器之外的所有登录
+pub async fn revoke_others_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Extension(ck): Extension<Cookies>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_login_session_revoke_others_action";
+    let current_id = current_id(&state, &ck);
+    let list = session::list_admin(state.cache.as_ref(), &state.sess_cfg, current.id, &current_id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    for item in list.iter().filter(|s| !s.is_current) {
+        session::revoke(state.cache.as_ref(), &state.sess_cfg, current.id, &item.id)
+            .await
+            .map_err(log_error(handler_name.to_string()))?;
+    }
+    redirect("/admin/session?msg=其它登录已全部注销")
+}
@@ -1,10 +1,12 @@
 use crate::db::medicinal;
 use crate::error::{AppError, AppErrorType};
 use crate::form::CreateMedicinal;
-use crate::handler::helper::{get_client, log_error, render};
+use crate::handler::helper::{get_client, log_error, page_size, render};
 use crate::handler::redirect::redirect;
+use crate::html::backend::index::ConfirmTemplate;
 use crate::html::backend::medicinal::{AddTemplate, EditTemplate, IndexTemplate, UploadTemplate};
-use crate::model::{get_expired_str, AppState, MedicinalList};
+use crate::model::{get_expired_str, AppState, Category, MedicinalList};
+use crate::rbac::{CurrentAdmin, Permission};
 use crate::{arg, form, Result};
 use axum::body::StreamBody;
 use axum::extract::{ContentLengthLimit, Extension, Form, Multipart, Path, Query};
@@ -33,16 +35,30 @@ const DEFAULT_VALIDITY_DATE: &str = "2099-12-31";
 
 pub async fn download(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     args: Option<Query<arg::MedicinalBackendQueryArg>>,
 ) -> Result<(HeaderMap, StreamBody<ReaderStream<File>>)> {
     let handler_name = "download";
+    current.require(Permission::Export)?;
+    let args = args
+        .ok_or_else(|| AppError::invalid_argument("导出参数错误"))?
+        .0;
     let client = get_client(&state, handler_name).await?;
 
-    let result = medicinal::all(&client, &format!("is_del=false"), &[])
+    // 与列表页使用相同的过滤条件和排序
+    let result = medicinal::all_by_arg(&client, &args, &current.categories)
         .await
         .map_err(log_error(handler_name.to_string()))?;
+    xlsx_attachment(&result, handler_name).await
+}
+
+/// 将药品列表生成 xlsx 文件, 并以附件的形式返回
+pub(crate) async fn xlsx_attachment(
+    result: &Vec<MedicinalList>,
+    handler_name: &str,
+) -> Result<(HeaderMap, StreamBody<ReaderStream<File>>)> {
     let file_name =
-        excel::create_xlsx_for_medicinal(&result).map_err(log_error(handler_name.to_string()))?;
+        excel::create_xlsx_for_medicinal(result).map_err(log_error(handler_name.to_string()))?;
 
     let file = tokio::fs::File::open(&file_name)
         .await
@@ -89,67 +105,22 @@ pub async fn download(
 
 pub async fn index(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     args: Option<Query<arg::MedicinalBackendQueryArg>>,
 ) -> Result<Html<String>> {
     debug!("args: {:#?}", args);
     let handler_name = "backend_medicinal_index";
+    current.require(Permission::View)?;
     let client = get_client(&state, handler_name).await?;
     let args = args.unwrap().0;
-    let q_keyword = format!("%{}%", args.keyword());
-
-    // 处理category 查询
-    let q_category = format!("%{}%", args.category());
-
-    let q_expired = args.expired();
-    // 处理查询日期(月份)
-    let q_expired_result = if q_expired > 0 {
-        if q_expired == 1 {
-            // 表示已经过期的数据
-            format!(
-                " AND validity <= '{}'",
-                chrono::Local::now().format("%Y-%m-%d").to_string(),
-            )
-        } else {
-            // 表示between 当前时间到 (q_expired-1)*30 天之间的数据
-            format!(
-                " AND validity BETWEEN '{}' AND '{}'",
-                chrono::Local::now().format("%Y-%m-%d").to_string(),
-                chrono::Local::now()
-                    .checked_add_signed(chrono::Duration::days((q_expired - 1) as i64 * 30))
-                    .unwrap()
-                    .format("%Y-%m-%d")
-                    .to_string(),
-            )
-        }
-    } else {
-        "".to_string()
-    };
-
-    // let q_order = args.order();
-    // let q_order_result = if !&q_order.is_empty() {
-    //     format!(" ORDER BY {}", q_order)
-    // } else {
-    //     "".to_string()
-    // };
-
-    debug!("q_expired_result: {}", q_expired_result);
-    // debug!("q_order_result: {}", q_order_result);
-    let condition = format!(
-        "is_del=$1 AND name LIKE $2 AND category LIKE $3 {}",
-        q_expired_result
-    );
+    let page_size = page_size(&state, &client, current.id, args.page_size)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let medicinal_list = medicinal::select_by_arg(&client, &args, &current.categories, page_size)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
 
-    let medicinal_list = medicinal::select(
-        &client,
-        // "is_del=$1 AND name LIKE $2",
-        &condition,
-        &[&args.is_del(), &q_keyword, &q_category],
-        args.page.unwrap_or(0),
-    )
-    .await
-    .map_err(log_error(handler_name.to_string()))?;
-
-    let categories = medicinal::categories(&client)
+    let categories = accessible_categories(&state, &client, &current)
         .await
         .map_err(log_error(handler_name.to_string()))?;
 
@@ -158,26 +129,58 @@ pub async fn index(
         list: medicinal_list,
         categories,
         expired_items: get_expired_str(),
+        page_sizes: state.page_cfg.choices(),
+        current,
     };
     render(tmpl, handler_name)
 }
 
+/// 当前管理员可以查看的类目
+pub(crate) async fn accessible_categories(
+    state: &AppState,
+    client: &deadpool_postgres::Client,
+    current: &CurrentAdmin,
+) -> Result<Vec<Category>> {
+    let categories = medicinal::categories(client, state.cache.as_ref()).await?;
+    Ok(categories
+        .into_iter()
+        .filter(|c| current.can_access_category(&c.category))
+        .collect())
+}
+
+/// 查找药品, 并检查当前管理员是否有权限对其执行指定的操作
+pub(crate) async fn find_for(
+    client: &deadpool_postgres::Client,
+    current: &CurrentAdmin,
+    permission: Permission,
+    id: i32,
+) -> Result<MedicinalList> {
+    current.require(permission)?;
+    let med = medicinal::find(client, id).await?;
+    current.require_category(permission, &med.category)?;
+    Ok(med)
+}
+
 // 添加药品页
-pub async fn add() -> Result<Html<String>> {
+pub async fn add(Extension(current): Extension<CurrentAdmin>) -> Result<Html<String>> {
+    current.require(Permission::Edit)?;
     let tmpl = AddTemplate {};
     render(tmpl, "backend_medicinal_add")
 }
 
 pub async fn add_action(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     form: Form<form::CreateMedicinal>,
 ) -> Result<(StatusCode, HeaderMap, ())> {
     let handler_name = "backend_medicinal_add_action";
+    current.require_category(Permission::Edit, &form.category)?;
     let client = get_client(&state, handler_name).await?;
     debug!("add_action and form: {:#?}", form);
     medicinal::create(&client, &form)
         .await
         .map_err(log_error(handler_name.to_string()))?;
+    medicinal::invalidate_categories(state.cache.as_ref()).await;
 
     redirect("/admin/medicinal?msg=药品添加成功")
 }
@@ -185,13 +188,14 @@ pub async fn add_action(
 // 编辑药品
 pub async fn edit(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     Path(id): Path<i32>,
 ) -> Result<Html<String>> {
     debug!("edit id: {}", id);
     let handler_name = "backend_medicinal_edit";
     let client = get_client(&state, handler_name).await?;
     debug!("start to find id.");
-    let med = medicinal::find(&client, Some("id=$1"), &[&id])
+    let med = find_for(&client, &current, Permission::Edit, id)
         .await
         .map_err(log_error(handler_name.to_string()))?;
     let tmpl = EditTemplate { medicinal: med };
@@ -201,14 +205,21 @@ pub async fn edit(
 // 编辑药品操作
 pub async fn edit_action(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     form: Form<form::UpdateMedicinal>,
 ) -> Result<(StatusCode, HeaderMap, ())> {
     let handler_name = "backend_medicinal_edit_action";
     let client = get_client(&state, handler_name).await?;
     debug!("edit_action and form: {:#?}", form);
+    // 原类目和新类目都需要有权限
+    find_for(&client, &current, Permission::Edit, form.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    current.require_category(Permission::Edit, &form.category)?;
     medicinal::update(&client, &form)
         .await
         .map_err(log_error(handler_name.to_string()))?;
+    medicinal::invalidate_categories(state.cache.as_ref()).await;
 
     redirect("/admin/medicinal?msg=药品编辑成功")
 }
@@ -216,10 +227,36 @@ pub async fn edit_action(
 // 删除药品
 pub async fn del(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     Path(id): Path<i32>,
-) -> Result<(StatusCode, HeaderMap, ())> {
+) -> Result<Html<String>> {
     let handler_name = "backend_medicinal_del";
     let client = get_client(&state, handler_name).await?;
+    let med = find_for(&client, &current, Permission::Delete, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let tmpl = ConfirmTemplate {
+        parent_title: "药品管理".to_string(),
+        parent_url: "medicinal".to_string(),
+        title: "删除药品".to_string(),
+        message: format!("确定删除药品 {}(批号 {})?", med.name, med.batch_number),
+        action: format!("/admin/medicinal/del/{}", id),
+        button: "删除".to_string(),
+        danger: true,
+    };
+    render(tmpl, handler_name)
+}
+
+pub async fn del_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_medicinal_del_action";
+    let client = get_client(&state, handler_name).await?;
+    find_for(&client, &current, Permission::Delete, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
     medicinal::delete(&client, id)
         .await
         .map_err(log_error(handler_name.to_string()))?;
@@ -229,26 +266,55 @@ pub async fn del(
 // 恢复某个药品
 pub async fn recover(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     Path(id): Path<i32>,
-) -> Result<(StatusCode, HeaderMap, ())> {
+) -> Result<Html<String>> {
     let handler_name = "backend_medicinal_recover";
     let client = get_client(&state, handler_name).await?;
+    let med = find_for(&client, &current, Permission::Delete, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let tmpl = ConfirmTemplate {
+        parent_title: "药品管理".to_string(),
+        parent_url: "medicinal".to_string(),
+        title: "恢复药品".to_string(),
+        message: format!("确定恢复药品 {}(批号 {})?", med.name, med.batch_number),
+        action: format!("/admin/medicinal/recover/{}", id),
+        button: "恢复".to_string(),
+        danger: false,
+    };
+    render(tmpl, handler_name)
+}
+
+pub async fn recover_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_medicinal_recover_action";
+    let client = get_client(&state, handler_name).await?;
+    find_for(&client, &current, Permission::Delete, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
     medicinal::recover(&client, id)
         .await
         .map_err(log_error(handler_name.to_string()))?;
     redirect("/admin/medicinal?msg=药品恢复成功")
 }
 
-pub async fn upload() -> Result<Html<String>> {
+pub async fn upload(Extension(current): Extension<CurrentAdmin>) -> Result<Html<String>> {
+    current.require(Permission::Import)?;
     let tmpl = UploadTemplate {};
     render(tmpl, "backend_medicinal_upload")
 }
 
 pub async fn upload_action(
     Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
     ContentLengthLimit(mut multipart): ContentLengthLimit<Multipart, { MAX_UPLOAD_SIZE }>,
 ) -> Result<(StatusCode, HeaderMap, ())> {
     debug!("upload_action and multipart: {:#?}", multipart);
+    current.require(Permission::Import)?;
     if let Some(file) = multipart.next_field().await.map_err(|err| {
         error!("upload_action error: {:?}", err);
         AppError::from_str("获取文件失败", AppErrorType::UploadError)
@@ -271,23 +337,7 @@ pub async fn upload_action(
         debug!("upload_action and filename: {:#?}", filename);
         debug!("upload_action and data size: {}", data.len());
 
-        // 文件创建以时间为前缀
-        let to_path = format!(
-            "{}/{}-{}",
-            &state.upload_dir,
-            format!(
-                "upload_{}",
-                chrono::Local::now().format("%Y-%m-%d_%H:%M:%S")
-            ),
-            filename
-        );
-
-        debug!("upload_action and to_path: {}", to_path);
-
-        // 保存上传的文件
-        tokio::fs::write(&to_path, &data)
-            .await
-            .map_err(|err| AppError::from_err(err, AppErrorType::UploadError))?;
+        let to_path = save_upload(&state.upload_dir, &filename, &data).await?;
 
         let (result, total_count, _success_count) = match sc.extension().and_then(|s| s.to_str()) {
             // 如果是csv文件,则解析csv文件
@@ -314,15 +364,10 @@ pub async fn upload_action(
         let mut insert_count = 0;
         if result.len() > 0 {
             let client = get_client(&state, "backend_medicinal_upload_action").await?;
-            for value in &result {
-                debug!("upload_action and row: {:#?}", value);
-                medicinal::create(&client, value).await.map_or_else(
-                    |e| {
-                        error!("upload_action error: {:?}", e);
-                    },
-                    |_| insert_count += 1,
-                )
-            }
+            insert_count = insert_all(&state, &client, &result, |category| {
+                current.can_access_category(category)
+            })
+            .await;
         }
 
         debug!(
@@ -342,15 +387,68 @@ pub async fn upload_action(
     }
 }
 
+/// 保存上传的文件, 文件名以时间为前缀, 返回保存的路径
+///
+/// 读取 csv 时会就地转换文件的编码, 所以总是先保存一份再读取
+pub(crate) async fn save_upload(upload_dir: &str, filename: &str, data: &[u8]) -> Result<String> {
+    let to_path = format!(
+        "{}/upload_{}-{}",
+        upload_dir,
+        chrono::Local::now().format("%Y-%m-%d_%H:%M:%S"),
+        filename
+    );
+    debug!("save upload to_path: {}", to_path);
+    tokio::fs::write(&to_path, data)
+        .await
+        .map_err(|err| AppError::from_err(err, AppErrorType::UploadError))?;
+    Ok(to_path)
+}
+
+/// 将读取到的药品逐条写入数据库, 返回成功的条数
+///
+/// # 参数
+///
+/// * `state` - 应用状态, 导入之后让类目列表的缓存失效
+/// * `client` - 数据库连接对象
+/// * `list` - 读取到的药品
+/// * `can_access` - 是否允许写入该类目, 没有权限的类目直接跳过, 计入失败条目
+pub(crate) async fn insert_all(
+    state: &AppState,
+    client: &deadpool_postgres::Client,
+    list: &[CreateMedicinal],
+    can_access: impl Fn(&str) -> bool,
+) -> u32 {
+    let mut insert_count = 0;
+    for value in list {
+        debug!("insert row: {:#?}", value);
+        if !can_access(&value.category) {
+            warn!("insert skip category: {}", value.category);
+            continue;
+        }
+        medicinal::create(client, value).await.map_or_else(
+            |e| {
+                error!("insert error: {:?}", e);
+            },
+            |_| insert_count += 1,
+        )
+    }
+    if insert_count > 0 {
+        medicinal::invalidate_categories(state.cache.as_ref()).await;
+    }
+    insert_count
+}
+
 fn remove_whitespace(s: &str) -> String {
     s.chars().filter(|c| !c.is_whitespace()).collect()
 }
 
-async fn load_csv_file(file: &str) -> Result<(Vec<CreateMedicinal>, u32, u32)> {
+pub(crate) async fn load_csv_file(file: &str) -> Result<(Vec<CreateMedicinal>, u32, u32)> {
     info!("load csv file: {}", file);
     let encoding_from = Encoding::for_label("gbk".as_bytes()).unwrap_or(GBK);
     let encoding_to = Encoding::for_label("utf8".as_bytes()).unwrap_or(UTF_8);
-    convert_encoding(file, encoding_from, encoding_to);
+    let bytes =
+        std::fs::read(file).map_err(|err| AppError::from_err(err, AppErrorType::UploadError))?;
+    let bytes = convert_encoding(bytes, encoding_from, encoding_to);
     info!("convert_encoding done");
     // 读取规则
     // 第一行是类目
@@ -359,7 +457,7 @@ async fn load_csv_file(file: &str) -> Result<(Vec<CreateMedicinal>, u32, u32)> {
     let mut rdr = csv::ReaderBuilder::new()
         .has_headers(false)
         .delimiter(b',')
-        .from_path(file)?;
+        .from_reader(bytes.as_slice());
 
     let mut category: Option<String> = None;
     let name_keys = vec!["药品", "名称", "药名", "项目", "型号"];
@@ -592,33 +690,28 @@ async fn load_csv_file(file: &str) -> Result<(Vec<CreateMedicinal>, u32, u32)> {
     Ok((result_content, total_count, success_count))
 }
 
-fn convert_encoding(file: &str, encoding_from: &'static Encoding, encoding_to: &'static Encoding) {
-    debug!("convert_encoding file: {}", file);
-    match std::fs::read(file) {
-        Ok(bytes) => {
-            let (string, encoding, has_malformed) = encoding_from.decode(&bytes);
-            if encoding != encoding_from {
-                println!("^^^^Detected encoding is {}", encoding.name());
-            }
-            if has_malformed {
-                println!("^^^^There are malformed characters");
-            } else {
-                let (bytes, encoding, has_unmappable) = encoding_to.encode(&string);
-                if encoding != encoding_to {
-                    println!("^^^^Saved encoding is {}", encoding.name());
-                }
-                if has_unmappable {
-                    println!("^^^^There are unmappable characters");
-                }
-                std::fs::write(file, bytes).unwrap_or_else(|err| {
-                    println!("^^^^write error: {}", err);
-                });
-            }
-        }
-        Err(err) => {
-            println!("^^^^read error: {}", err);
-        }
+/// 把 csv 的内容从 `encoding_from` 转换为 `encoding_to`, 只在内存中转换, 不修改原文件; 无法解码时原样返回
+fn convert_encoding(
+    bytes: Vec<u8>,
+    encoding_from: &'static Encoding,
+    encoding_to: &'static Encoding,
+) -> Vec<u8> {
+    let (string, encoding, has_malformed) = encoding_from.decode(&bytes);
+    if encoding != encoding_from {
+        println!("^^^^Detected encoding is {}", encoding.name());
+    }
+    if has_malformed {
+        println!("^^^^There are malformed characters");
+        return bytes;
+    }
+    let (converted, encoding, has_unmappable) = encoding_to.encode(&string);
+    if encoding != encoding_to {
+        println!("^^^^Saved encoding is {}", encoding.name());
+    }
+    if has_unmappable {
+        println!("^^^^There are unmappable characters");
     }
+    converted.into_owned()
 }
 
 mod tests {
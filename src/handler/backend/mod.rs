@@ -1,14 +1,19 @@
 pub mod admin;
 pub mod excel;
 pub mod index;
+pub mod login_event;
+pub mod login_session;
 pub mod medicinal;
+pub mod outbox;
+pub mod sms;
+pub mod token;
+pub mod two_factor;
 
 use crate::error::AppError;
 use crate::model::{AdminSession, AppState};
-use crate::session::gen_redis_key;
-use crate::{rdb, Result};
+use crate::{session, Result};
 use axum::http::HeaderMap;
-use axum::routing::get;
+use axum::routing::{get, post};
 use axum::Router;
 use tower_cookies::Cookies;
 use tracing::{debug, error};
@@ -30,13 +35,49 @@ pub fn routers() -> Router {
             get(medicinal::upload).post(medicinal::upload_action),
         ) // 批量上传药品
         .route("/medicinal/download", get(medicinal::download))
-        .route("/medicinal/del/:id", get(medicinal::del)) // 删除药品
-        .route("/medicinal/recover/:id", get(medicinal::recover)) // 恢复药品
+        .route(
+            "/medicinal/del/:id",
+            get(medicinal::del).post(medicinal::del_action),
+        ) // 删除药品
+        .route(
+            "/medicinal/recover/:id",
+            get(medicinal::recover).post(medicinal::recover_action),
+        ) // 恢复药品
         .route("/admin", get(admin::index))
         .route("/admin/add", get(admin::add).post(admin::add_action)) // 添加管理员
-        .route("/admin/edit/:id", get(admin::edit).post(admin::edit_action)) // 编辑管理员
-        .route("/admin/del/:id", get(admin::del)) // 删除管理员
-        .route("/admin/recover/:id", get(admin::recover)) // 恢复管理员
+        .route("/admin/edit/:id", get(admin::edit)) // 编辑管理员
+        .route("/admin/reset/:id", post(admin::reset_action)) // 重置管理员密码
+        .route("/admin/disable/:id", post(admin::disable_action)) // 禁用管理员
+        .route("/admin/enable/:id", post(admin::enable_action)) // 启用管理员
+        .route("/admin/unlock/:id", post(admin::unlock_action)) // 解除登录锁定
+        .route("/login_event", get(login_event::index)) // 登录失败记录
+        .route("/password", get(admin::password).post(admin::password_action)) // 修改自己的密码
+        .route("/admin/reset_2fa/:id", post(admin::reset_2fa_action)) // 重置管理员的二步验证
+        .route("/admin/logout/:id", post(admin::logout_action)) // 强制管理员下线
+        .route("/session", get(login_session::index)) // 我的登录
+        .route("/session/revoke/:id", post(login_session::revoke_action)) // 注销自己的某一个登录
+        .route(
+            "/session/revoke_others",
+            post(login_session::revoke_others_action),
+        ) // 注销自己的其它登录
+        .route("/2fa", get(two_factor::index)) // 二步验证
+        .route("/2fa/enroll", get(two_factor::enroll).post(two_factor::enroll_action)) // 启用二步验证
+        .route("/2fa/recovery", post(two_factor::recovery_action)) // 重新生成恢复码
+        .route("/2fa/disable", post(two_factor::disable_action)) // 关闭二步验证
+        .route("/2fa/roles", get(two_factor::roles).post(two_factor::roles_action)) // 角色的二步验证要求
+        .route("/admin/role/:id", post(admin::role_action)) // 修改管理员角色
+        .route("/admin/del/:id", get(admin::del).post(admin::del_action)) // 删除管理员
+        .route(
+            "/admin/recover/:id",
+            get(admin::recover).post(admin::recover_action),
+        ) // 恢复管理员
+        .route("/token", get(token::index))
+        .route("/token/add", get(token::add).post(token::add_action)) // 创建 API token
+        .route("/token/revoke/:id", post(token::revoke_action)) // 撤销 API token
+        .route("/sms", get(sms::index).post(sms::index_action)) // 短信设置
+        .route("/sms/test", post(sms::test_action)) // 发送测试短信
+        .route("/outbox", get(outbox::index)) // 通知发送记录
+        .route("/outbox/resend/:id", post(outbox::resend_action)) // 重新发送失败的通知
 }
 
 // pub async fn get_logined_admin(
@@ -51,7 +92,7 @@ pub fn routers() -> Router {
 //         if !session_id.is_empty() {
 //             let redis_key = gen_redis_key(&sess_cfg, &session_id);
 //             debug!("redis_key: {}", redis_key);
-//             let admin_session = rdb::get(&state.rdc, &redis_key).await.map_err(|err| {
+//             let admin_session = state.cache.get_str(&redis_key).await.map_err(|err| {
 //                 error!("get session failed: {:?}", err);
 //                 AppError::auth_error("UNAUTHENTICATED")
 //             })?;
@@ -84,24 +125,16 @@ pub async fn get_login_admin_by_cookie(
         .and_then(|c| Some(c.value().to_owned()));
     debug!("result cookie: {:#?}", cookie);
     if let Some(cookie) = cookie {
-        let redis_key = gen_redis_key(&sess_cfg, &cookie);
-        debug!("redis_key: {}", redis_key);
-        let admin_session = rdb::get(&state.rdc, &redis_key).await.map_err(|err| {
-            error!("get session failed: {:?}", err);
-            AppError::auth_error("UNAUTHENTICATED")
-        })?;
+        // 读取时会检查最长有效期并延长空闲超时
+        let admin_session = session::load(state.cache.as_ref(), &sess_cfg, &cookie)
+            .await
+            .map_err(|err| {
+                error!("get session failed: {:?}", err);
+                AppError::auth_error("UNAUTHENTICATED")
+            })?;
         debug!("admin_session: {:#?}", admin_session);
-        if let Some(admin_session) = admin_session {
-            let admin_session: AdminSession =
-                serde_json::from_str(&admin_session).map_err(|err| {
-                    error!("des parse session failed: {:?}", err);
-                    AppError::auth_error("UNAUTHENTICATED")
-                })?;
-            debug!(
-                "deserialize success and admin_session: {:#?}",
-                admin_session
-            );
-            return Ok(Some(admin_session));
+        if admin_session.is_some() {
+            return Ok(admin_session);
         }
     }
 
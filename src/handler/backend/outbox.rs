@@ -0,0 +1,58 @@
+use crate::db::notify_outbox;
+use crate::db::query::Filter;
+use crate::error::AppError;
+use crate::handler::helper::{get_client, log_error, page_size, render};
+use crate::handler::redirect::redirect;
+use crate::html::backend::outbox::IndexTemplate;
+use crate::model::AppState;
+use crate::rbac::{CurrentAdmin, Permission};
+use crate::{arg, Result};
+use axum::extract::{Extension, Path, Query};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Html;
+use std::sync::Arc;
+
+/// 通知的发送记录, 按接收人或标题搜索, 按状态筛选
+pub async fn index(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    args: Option<Query<arg::OutboxQueryArg>>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_outbox_index";
+    current.require(Permission::ManageUsers)?;
+    let args = args.unwrap();
+    let q_keyword = format!("%{}%", args.keyword());
+    let client = get_client(&state, handler_name).await?;
+    let mut filter =
+        Filter::ilike("recipients", q_keyword.clone()).or(Filter::ilike("title", q_keyword));
+    if !args.status().is_empty() {
+        filter = filter.and(Filter::eq("status", args.status().to_string()));
+    }
+    let page_size = page_size(&state, &client, current.id, args.page_size)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let list = notify_outbox::select(&client, filter, args.page(), page_size)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let tmpl = IndexTemplate { list, arg: args.0 };
+    render(tmpl, handler_name)
+}
+
+/// 重新发送失败的消息, 唤醒发件箱立即发送
+pub async fn resend_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_outbox_resend_action";
+    current.require(Permission::ManageUsers)?;
+    let client = get_client(&state, handler_name).await?;
+    let updated = notify_outbox::resend(&client, id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    if updated == 0 {
+        return Err(AppError::not_found("消息不存在或者不是发送失败的消息"));
+    }
+    state.outbox_wake.notify_one();
+    redirect("/admin/outbox?status=pending&msg=已重新加入发送队列")
+}
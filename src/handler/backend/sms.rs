@@ -0,0 +1,126 @@
+use crate::db::sms_setting;
+use crate::form::{SaveSmsSetting, SmsTest};
+use crate::handler::helper::{get_client, log_error, render};
+use crate::handler::redirect::redirect;
+use crate::html::backend::sms::IndexTemplate;
+use crate::model::{AppState, SmsSetting};
+use crate::notify::sms::{check_phones, effective, AliyunSms};
+use crate::rbac::{CurrentAdmin, Permission};
+use crate::{arg, Result};
+use axum::extract::{Extension, Form, Query};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Html;
+use std::sync::Arc;
+
+fn index_template(
+    state: &AppState,
+    saved: SmsSetting,
+    test_result: Option<String>,
+    arg: arg::BackendQueryArg,
+) -> IndexTemplate {
+    IndexTemplate {
+        setting: effective(state.sms_cfg.as_ref(), Some(saved.clone())),
+        saved,
+        enabled: state
+            .sms_cfg
+            .as_ref()
+            .and_then(|c| c.send_sms_toggle)
+            .unwrap_or(false),
+        test_result,
+        arg,
+    }
+}
+
+/// 短信设置
+pub async fn index(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    args: Option<Query<arg::BackendQueryArg>>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_sms_index";
+    current.require(Permission::ManageUsers)?;
+    let client = get_client(&state, handler_name).await?;
+    let saved = sms_setting::find(&client)
+        .await
+        .map_err(log_error(handler_name.to_string()))?
+        .unwrap_or_default();
+    let tmpl = index_template(&state, saved, None, args.unwrap().0);
+    render(tmpl, handler_name)
+}
+
+/// 保存短信设置, 密钥留空表示不修改
+pub async fn index_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Form(form): Form<SaveSmsSetting>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_sms_index_action";
+    current.require(Permission::ManageUsers)?;
+    let phones: Vec<String> = form
+        .phones
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '，')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect();
+    check_phones(&phones)?;
+    let client = get_client(&state, handler_name).await?;
+    let saved = sms_setting::find(&client)
+        .await
+        .map_err(log_error(handler_name.to_string()))?
+        .unwrap_or_default();
+    let access_key_secret = match form.access_key_secret.trim() {
+        "" => saved.access_key_secret,
+        secret => secret.to_string(),
+    };
+    let setting = SmsSetting {
+        access_key_id: form.access_key_id.trim().to_string(),
+        access_key_secret,
+        sign_name: form.sign_name.trim().to_string(),
+        template_code: form.template_code.trim().to_string(),
+        phones,
+    };
+    sms_setting::save(&client, &setting)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    redirect("/admin/sms?msg=保存成功")
+}
+
+/// 按生效的设置发送一条测试短信, 在页面上显示服务商的响应
+pub async fn test_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Form(form): Form<SmsTest>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_sms_test_action";
+    current.require(Permission::ManageUsers)?;
+    let client = get_client(&state, handler_name).await?;
+    let saved = sms_setting::find(&client)
+        .await
+        .map_err(log_error(handler_name.to_string()))?
+        .unwrap_or_default();
+    let setting = effective(state.sms_cfg.as_ref(), Some(saved.clone()));
+    let phones = match form.phone.trim() {
+        "" => vec![],
+        phone => vec![phone.to_string()],
+    };
+    let param = aliyun_sdk::SmsParam {
+        name: "测试短信".to_string(),
+        code: chrono::Local::now().format("%Y-%m-%d %H:%M").to_string(),
+    };
+    let test_result = match AliyunSms::send_param(&setting, phones, param).await {
+        Ok(response) => response,
+        Err(err) => format!(
+            "发送失败: {}",
+            err.message.or(err.cause).unwrap_or_default()
+        ),
+    };
+    let arg = arg::BackendQueryArg {
+        page: None,
+        keyword: None,
+        msg: None,
+        is_del: None,
+        page_size: None,
+    };
+    let tmpl = index_template(&state, saved, Some(test_result), arg);
+    render(tmpl, handler_name)
+}
@@ -0,0 +1,113 @@
+use crate::db::api_token;
+use crate::error::{AppError, AppErrorType};
+use crate::form::CreateApiToken;
+use crate::handler::backend::get_login_admin_by_cookie;
+use crate::handler::helper::{get_client, log_error, render};
+use crate::handler::redirect::redirect;
+use crate::html::backend::token::{AddTemplate, CreatedTemplate, IndexTemplate};
+use crate::model::{AdminSession, AppState};
+use crate::token::{self, TokenScope};
+use crate::{arg, Result};
+use axum::extract::{Extension, Form, Path, Query};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Html;
+use std::sync::Arc;
+use tower_cookies::Cookies;
+
+/// token 名称的最大长度
+const MAX_NAME_LEN: usize = 100;
+
+/// 获取当前登录的管理员
+async fn login_admin(state: &AppState, ck: &Cookies) -> Result<AdminSession> {
+    get_login_admin_by_cookie(state, ck)
+        .await?
+        .ok_or_else(|| AppError::auth_error("UNAUTHENTICATED"))
+}
+
+pub async fn index(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(ck): Extension<Cookies>,
+    args: Option<Query<arg::BackendQueryArg>>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_token_index";
+    let admin_session = login_admin(&state, &ck).await?;
+    let client = get_client(&state, handler_name).await?;
+    let list = api_token::select_by_admin(&client, admin_session.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let tmpl = IndexTemplate {
+        list,
+        arg: args.unwrap().0,
+    };
+    render(tmpl, handler_name)
+}
+
+pub async fn add() -> Result<Html<String>> {
+    let handler_name = "backend_token_add";
+    let tmpl = AddTemplate {};
+    render(tmpl, handler_name)
+}
+
+pub async fn add_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(ck): Extension<Cookies>,
+    Form(ct): Form<CreateApiToken>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_token_add_action";
+    let name = ct.name.trim();
+    if name.is_empty() {
+        return Err(AppError::from_str("请输入 token 名称", AppErrorType::Common));
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(AppError::from_str(
+            "token 名称不能超过100个字符",
+            AppErrorType::Common,
+        ));
+    }
+    let admin_session = login_admin(&state, &ck).await?;
+    let scope = TokenScope::from(ct.scope.as_str());
+    let expires_at = match ct.expires_days {
+        Some(days) if days > 0 => {
+            Some(chrono::Local::now() + chrono::Duration::days(days as i64))
+        }
+        _ => None,
+    };
+
+    let plain = token::generate();
+    let client = get_client(&state, handler_name).await?;
+    api_token::create(
+        &client,
+        admin_session.id,
+        name,
+        &token::hash(&plain),
+        scope.as_str(),
+        expires_at,
+    )
+    .await
+    .map_err(log_error(handler_name.to_string()))?;
+
+    // 明文 token 只在这里展示一次
+    let tmpl = CreatedTemplate {
+        name: name.to_string(),
+        scope: scope.as_str().to_string(),
+        token: plain,
+    };
+    render(tmpl, handler_name)
+}
+
+pub async fn revoke_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(ck): Extension<Cookies>,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_token_revoke_action";
+    let admin_session = login_admin(&state, &ck).await?;
+    let client = get_client(&state, handler_name).await?;
+    if !api_token::revoke(&client, id, admin_session.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?
+    {
+        return Err(AppError::not_found("不存在的 token"));
+    }
+    redirect("/admin/token?msg=token已撤销")
+}
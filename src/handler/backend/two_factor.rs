@@ -0,0 +1,231 @@
+use crate::db::{admin, role_setting};
+use crate::error::{AppError, AppErrorType};
+use crate::form::{DisableTwoFactor, TwoFactorCode};
+use crate::handler::helper::{get_client, log_error, render};
+use crate::handler::redirect::redirect;
+use crate::html::backend::two_factor::{
+    EnrollTemplate, IndexTemplate, RecoveryCodesTemplate, RolesTemplate,
+};
+use crate::model::{Admin, AppState, RoleSetting};
+use crate::rbac::{CurrentAdmin, Permission, Role};
+use crate::{arg, password, session, token, totp, Result};
+use axum::extract::{Extension, Form, Query};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Html;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// 认证应用中显示的名称
+const ISSUER: &str = "药品管理";
+/// 扫码后等待确认的密钥的有效期, 单位秒
+const ENROLL_TTL: usize = 600;
+
+fn enroll_key(state: &AppState, admin_id: i32) -> String {
+    format!("{}2fa:enroll:{}", &state.login_cfg.prefix, admin_id)
+}
+
+/// 校验动态码或恢复码, 动态码和恢复码都只能使用一次
+pub(crate) async fn verify_second_factor(
+    client: &deadpool_postgres::Client,
+    item: &Admin,
+    code: &str,
+) -> Result<bool> {
+    let secret = match (&item.totp_secret, item.totp_enabled) {
+        (Some(secret), true) => secret,
+        _ => return Ok(false),
+    };
+    let now = chrono::Local::now().timestamp() as u64;
+    if let Some(step) = totp::verify(secret, code, now) {
+        return admin::use_totp_step(client, item.id, step as i64).await;
+    }
+    let code = totp::normalize_recovery_code(code);
+    if code.is_empty() {
+        return Ok(false);
+    }
+    admin::use_recovery_code(client, item.id, &token::hash(&code)).await
+}
+
+/// 生成一组新的恢复码, 数据库中只保存摘要
+async fn new_recovery_codes(
+    client: &deadpool_postgres::Client,
+    admin_id: i32,
+) -> Result<Vec<String>> {
+    let codes = totp::generate_recovery_codes();
+    let hashes = codes.iter().map(|c| token::hash(c)).collect();
+    admin::replace_recovery_codes(client, admin_id, &hashes).await?;
+    Ok(codes)
+}
+
+/// 二步验证的状态
+pub async fn index(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    args: Option<Query<arg::BackendQueryArg>>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_two_factor_index";
+    let client = get_client(&state, handler_name).await?;
+    let item = admin::find_by_id(&client, current.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let unused_codes = admin::unused_recovery_codes(&client, current.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let required = role_setting::requires_2fa(&client, &item.role)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let tmpl = IndexTemplate {
+        enabled: item.totp_enabled,
+        unused_codes,
+        required,
+        arg: args.unwrap().0,
+    };
+    render(tmpl, handler_name)
+}
+
+/// 生成密钥并展示二维码
+pub async fn enroll(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_two_factor_enroll";
+    let secret = totp::generate_secret();
+    // 输入动态码确认之前不写入数据库
+    let key = enroll_key(&state, current.id);
+    state.cache.set_ex(&key, &secret, ENROLL_TTL).await?;
+    let url = totp::otpauth_url(ISSUER, &current.username, &secret);
+    let tmpl = EnrollTemplate {
+        qr_svg: totp::qr_svg(&url),
+        secret,
+    };
+    render(tmpl, handler_name)
+}
+
+/// 输入动态码确认启用, 生成恢复码, 并注销所有登录
+pub async fn enroll_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Form(tf): Form<TwoFactorCode>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_two_factor_enroll_action";
+    let key = enroll_key(&state, current.id);
+    let secret = state.cache.get_str(&key).await?.ok_or_else(|| {
+        AppError::from_str("二维码已过期, 请重新扫码", AppErrorType::Common)
+    })?;
+    let now = chrono::Local::now().timestamp() as u64;
+    let step = totp::verify(&secret, &tf.code, now).ok_or_else(|| {
+        AppError::from_str(
+            "动态码错误, 请检查手机时间是否准确",
+            AppErrorType::Common,
+        )
+    })?;
+    let client = get_client(&state, handler_name).await?;
+    admin::enable_totp(&client, current.id, &secret, step as i64)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let codes = new_recovery_codes(&client, current.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    state.cache.del(&key).await?;
+    session::revoke_admin(state.cache.as_ref(), &state.sess_cfg, current.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let tmpl = RecoveryCodesTemplate {
+        codes,
+        relogin: true,
+    };
+    render(tmpl, handler_name)
+}
+
+/// 重新生成恢复码, 旧的恢复码全部失效
+pub async fn recovery_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Form(tf): Form<TwoFactorCode>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_two_factor_recovery_action";
+    let client = get_client(&state, handler_name).await?;
+    let item = admin::find_by_id(&client, current.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    if !verify_second_factor(&client, &item, &tf.code).await? {
+        return Err(AppError::auth_error("动态码错误"));
+    }
+    let codes = new_recovery_codes(&client, current.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let tmpl = RecoveryCodesTemplate {
+        codes,
+        relogin: false,
+    };
+    render(tmpl, handler_name)
+}
+
+/// 关闭自己的二步验证, 角色要求二步验证时不允许关闭
+pub async fn disable_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Form(dt): Form<DisableTwoFactor>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_two_factor_disable_action";
+    let client = get_client(&state, handler_name).await?;
+    let item = admin::find_by_id(&client, current.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    if role_setting::requires_2fa(&client, &item.role).await? {
+        return Err(AppError::forbidden("你的角色要求启用二步验证, 不能关闭"));
+    }
+    if !password::verify(&dt.password, &item.password)? {
+        return Err(AppError::auth_error("你输入的密码错误."));
+    }
+    if !verify_second_factor(&client, &item, &dt.code).await? {
+        return Err(AppError::auth_error("动态码错误"));
+    }
+    admin::disable_totp(&client, current.id)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    redirect("/admin/2fa?msg=二步验证已关闭")
+}
+
+/// 各角色是否要求二步验证
+pub async fn roles(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    args: Option<Query<arg::BackendQueryArg>>,
+) -> Result<Html<String>> {
+    let handler_name = "backend_two_factor_roles";
+    current.require(Permission::ManageUsers)?;
+    let client = get_client(&state, handler_name).await?;
+    let saved = role_setting::all(&client)
+        .await
+        .map_err(log_error(handler_name.to_string()))?;
+    let list = Role::all()
+        .into_iter()
+        .map(|role| RoleSetting {
+            role: role.as_str().to_string(),
+            require_2fa: saved.iter().any(|s| s.role == role.as_str() && s.require_2fa),
+        })
+        .collect();
+    let tmpl = RolesTemplate {
+        list,
+        arg: args.unwrap().0,
+    };
+    render(tmpl, handler_name)
+}
+
+/// 保存各角色的二步验证要求, 表单中勾选的角色以角色名作为字段名
+pub async fn roles_action(
+    Extension(state): Extension<Arc<AppState>>,
+    Extension(current): Extension<CurrentAdmin>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<(StatusCode, HeaderMap, ())> {
+    let handler_name = "backend_two_factor_roles_action";
+    current.require(Permission::ManageUsers)?;
+    let client = get_client(&state, handler_name).await?;
+    for role in Role::all() {
+        let require_2fa = form.contains_key(role.as_str());
+        role_setting::save_require_2fa(&client, role.as_str(), require_2fa)
+            .await
+            .map_err(log_error(handler_name.to_string()))?;
+    }
+    redirect("/admin/2fa/roles?msg=保存成功, 相关账号下次登录时需要启用二步验证")
+}
@@ -1,3 +1,4 @@
+use crate::db::admin;
 use crate::error::AppError;
 use crate::model::AppState;
 use crate::Result;
@@ -57,6 +58,33 @@ pub async fn get_client(state: &AppState, handler_name: &str) -> Result<Client>
     })
 }
 
+/// 列表的每页记录数: 指定了就记住作为该管理员的默认值, 否则使用上次记住的值, 都限制在配置的范围内
+///
+/// # 参数
+///
+/// * `state` - 应用状态
+/// * `client` - 数据库连接对象
+/// * `admin_id` - 当前管理员的 ID
+/// * `requested` - 请求中指定的每页记录数
+pub async fn page_size(
+    state: &AppState,
+    client: &Client,
+    admin_id: i32,
+    requested: Option<u32>,
+) -> Result<u32> {
+    let saved = admin::page_size(client, admin_id).await?;
+    match requested {
+        Some(size) => {
+            let size = state.page_cfg.clamp(Some(size));
+            if saved != Some(size as i32) {
+                admin::update_page_size(client, admin_id, size as i32).await?;
+            }
+            Ok(size)
+        }
+        None => Ok(state.page_cfg.clamp(saved.map(|s| s.max(0) as u32))),
+    }
+}
+
 // TODO:
 pub fn log_error(handler_name: String) -> Box<dyn Fn(AppError) -> AppError> {
     Box::new(move |err| {
@@ -10,11 +10,22 @@ pub fn redirect(url: &str) -> crate::Result<(StatusCode, HeaderMap, ())> {
 pub fn redirect_with_cookie(
     url: &str,
     cookie: Option<&str>,
+) -> crate::Result<(StatusCode, HeaderMap, ())> {
+    match cookie {
+        Some(cookie) => redirect_with_cookies(url, &[cookie]),
+        None => redirect_with_cookies(url, &[]),
+    }
+}
+
+/// 重定向, 同时设置多个 cookie
+pub fn redirect_with_cookies(
+    url: &str,
+    cookies: &[&str],
 ) -> crate::Result<(StatusCode, HeaderMap, ())> {
     let mut headers = HeaderMap::new();
     headers.insert(LOCATION, url.parse().unwrap());
-    if let Some(cookie) = cookie {
-        headers.insert(SET_COOKIE, cookie.parse().unwrap());
+    for cookie in cookies {
+        headers.append(SET_COOKIE, cookie.parse().unwrap());
     }
     Ok((StatusCode::FOUND, headers, ()))
 }
@@ -0,0 +1,81 @@
+//! 汉字转拼音, 用于按拼音全拼或首字母搜索药品
+//!
+//! 多音字取最常用的读音, 非汉字的字母和数字转为小写后原样保留, 其它字符忽略.
+
+use crate::db::medicinal;
+use crate::handler::helper::get_client;
+use crate::model::AppState;
+use pinyin::{Pinyin, ToPinyin};
+use std::sync::Arc;
+use tracing::{error, info};
+
+fn convert(s: &str, f: fn(Pinyin) -> &'static str) -> String {
+    s.chars()
+        .zip(s.to_pinyin())
+        .filter_map(|(c, p)| match p {
+            Some(p) => Some(f(p).to_string()),
+            None if c.is_alphanumeric() => Some(c.to_lowercase().to_string()),
+            None => None,
+        })
+        .collect()
+}
+
+/// 拼音全拼, 如 `多巴胺` 转为 `duobaan`
+pub fn full(s: &str) -> String {
+    convert(s, |p| p.plain())
+}
+
+/// 拼音首字母, 如 `多巴胺` 转为 `dba`
+pub fn initials(s: &str) -> String {
+    convert(s, |p| p.first_letter())
+}
+
+/// 把搜索的关键字转为与拼音比较的形式, 只有字母和数字的关键字才按拼音搜索
+pub fn keyword(s: &str) -> Option<String> {
+    let s: String = s
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '\'')
+        .collect();
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some(s.to_lowercase())
+}
+
+/// 为还没有拼音的药品补全拼音, 启动时在后台执行
+pub async fn backfill(state: Arc<AppState>) {
+    let client = match get_client(&state, "后台任务:补全拼音").await {
+        Ok(client) => client,
+        Err(err) => {
+            error!("补全拼音失败: {:?}", err);
+            return;
+        }
+    };
+    match medicinal::backfill_pinyin(&client).await {
+        Ok(0) => {}
+        Ok(count) => info!("补全拼音: {} 条药品", count),
+        Err(err) => error!("补全拼音失败: {:?}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_and_initials() {
+        assert_eq!(full("多巴胺"), "duobaan");
+        assert_eq!(initials("多巴胺"), "dba");
+        assert_eq!(full("维生素C 片"), "weishengsucpian");
+        assert_eq!(initials("N95口罩"), "n95kz");
+    }
+
+    #[test]
+    fn test_keyword() {
+        assert_eq!(keyword("DBF"), Some("dbf".to_string()));
+        assert_eq!(keyword("duo ba'an"), Some("duobaan".to_string()));
+        assert_eq!(keyword("多巴"), None);
+        assert_eq!(keyword("  "), None);
+        assert_eq!(keyword("a%b"), None);
+    }
+}
@@ -1,7 +1,13 @@
+use crate::captcha::Widget;
 use askama::Template;
 
 #[derive(Template)]
 #[template(path = "auth/login.html")]
 pub struct LoginTemplate {
-    pub site_key: String,
+    pub captcha: Widget,
 }
+
+/// 登录的第二步, 输入动态码
+#[derive(Template)]
+#[template(path = "auth/login_2fa.html")]
+pub struct TwoFactorTemplate {}
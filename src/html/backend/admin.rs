@@ -1,6 +1,7 @@
 use crate::arg;
 use crate::db::pagination::Pagination;
-use crate::model::Admin;
+use crate::model::{ActiveSession, Admin};
+use crate::rbac::Role;
 use askama::Template;
 
 #[derive(Template)]
@@ -8,13 +9,43 @@ use askama::Template;
 pub struct IndexTemplate {
     pub list: Pagination<Vec<Admin>>,
     pub arg: arg::BackendQueryArg,
+    /// 因登录失败次数过多而被锁定的账号
+    pub locked: Vec<i32>,
+}
+
+impl IndexTemplate {
+    pub fn is_locked(&self, id: i32) -> bool {
+        self.locked.contains(&id)
+    }
 }
 
 #[derive(Template)]
 #[template(path = "backend/admin/add.html")]
-pub struct AddTemplate {}
+pub struct AddTemplate {
+    pub roles: Vec<Role>,
+}
 #[derive(Template)]
 #[template(path = "backend/admin/edit.html")]
 pub struct EditTemplate {
     pub admin: Admin,
+    pub roles: Vec<Role>,
+    /// 以逗号分隔的限定类目
+    pub categories: String,
+    /// 该账号当前的登录
+    pub sessions: Vec<ActiveSession>,
+}
+
+#[derive(Template)]
+#[template(path = "backend/admin/password.html")]
+pub struct PasswordTemplate {
+    pub username: String,
+    pub arg: arg::BackendQueryArg,
+}
+
+/// 重置密码后展示一次临时密码
+#[derive(Template)]
+#[template(path = "backend/admin/reset.html")]
+pub struct ResetTemplate {
+    pub username: String,
+    pub password: String,
 }
@@ -2,4 +2,25 @@ use askama::Template;
 
 #[derive(Template)]
 #[template(path = "backend/index/index.html")]
-pub struct IndexTemplate {}
+pub struct IndexTemplate {
+    /// 定时检查通知的计划
+    pub plan: String,
+    /// 下一次检查通知的时间, 定时任务没有启动时为空
+    pub next_run: Option<String>,
+}
+
+/// 删除、恢复等操作的确认页面, 确认后以 POST 提交到 `action`
+#[derive(Template)]
+#[template(path = "backend/index/confirm.html")]
+pub struct ConfirmTemplate {
+    pub parent_title: String,
+    pub parent_url: String,
+    pub title: String,
+    /// 提示内容
+    pub message: String,
+    pub action: String,
+    /// 确认按钮的文字
+    pub button: String,
+    /// 是否是删除之类的危险操作, 按钮显示为红色
+    pub danger: bool,
+}
@@ -0,0 +1,11 @@
+use crate::arg;
+use crate::db::pagination::Pagination;
+use crate::model::LoginEvent;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "backend/login_event/index.html")]
+pub struct IndexTemplate {
+    pub list: Pagination<Vec<LoginEvent>>,
+    pub arg: arg::BackendQueryArg,
+}
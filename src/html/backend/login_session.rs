@@ -0,0 +1,10 @@
+use crate::arg;
+use crate::model::ActiveSession;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "backend/login_session/index.html")]
+pub struct IndexTemplate {
+    pub list: Vec<ActiveSession>,
+    pub arg: arg::BackendQueryArg,
+}
@@ -1,5 +1,6 @@
 use crate::db::pagination::Pagination;
 use crate::model::{Category, ExpiredItem};
+use crate::rbac::CurrentAdmin;
 use crate::{arg, model};
 use askama::Template;
 
@@ -10,6 +11,8 @@ pub struct IndexTemplate {
     pub list: Pagination<Vec<model::MedicinalList>>,
     pub categories: Vec<Category>,       // 分类信息
     pub expired_items: Vec<ExpiredItem>, // 查询过期条件信息
+    pub page_sizes: Vec<u32>,            // 可以选择的每页记录数
+    pub current: CurrentAdmin,           // 当前管理员, 用于隐藏没有权限的操作
 }
 
 #[derive(Template)]
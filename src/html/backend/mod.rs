@@ -1,3 +1,9 @@
 pub mod admin;
 pub mod index;
+pub mod login_event;
+pub mod login_session;
 pub mod medicinal;
+pub mod outbox;
+pub mod sms;
+pub mod token;
+pub mod two_factor;
@@ -0,0 +1,11 @@
+use crate::arg;
+use crate::db::pagination::Pagination;
+use crate::model::NotifyOutbox;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "backend/outbox/index.html")]
+pub struct IndexTemplate {
+    pub list: Pagination<Vec<NotifyOutbox>>,
+    pub arg: arg::OutboxQueryArg,
+}
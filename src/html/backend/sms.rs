@@ -0,0 +1,28 @@
+use crate::arg;
+use crate::model::SmsSetting;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "backend/sms/index.html")]
+pub struct IndexTemplate {
+    /// 后台保存的设置
+    pub saved: SmsSetting,
+    /// 生效的设置
+    pub setting: SmsSetting,
+    /// 是否打开了短信提醒 `SMS.SEND_SMS_TOGGLE`
+    pub enabled: bool,
+    /// 测试短信的结果
+    pub test_result: Option<String>,
+    pub arg: arg::BackendQueryArg,
+}
+
+impl IndexTemplate {
+    /// 后台保存的接收号码, 每行一个
+    pub fn saved_phones(&self) -> String {
+        self.saved.phones.join("\n")
+    }
+
+    pub fn setting_phones(&self) -> String {
+        self.setting.phones.join(", ")
+    }
+}
@@ -0,0 +1,23 @@
+use crate::arg;
+use crate::model::ApiToken;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "backend/token/index.html")]
+pub struct IndexTemplate {
+    pub list: Vec<ApiToken>,
+    pub arg: arg::BackendQueryArg,
+}
+
+#[derive(Template)]
+#[template(path = "backend/token/add.html")]
+pub struct AddTemplate {}
+
+/// 创建成功后展示明文 token, 只展示这一次
+#[derive(Template)]
+#[template(path = "backend/token/created.html")]
+pub struct CreatedTemplate {
+    pub name: String,
+    pub scope: String,
+    pub token: String,
+}
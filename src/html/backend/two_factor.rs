@@ -0,0 +1,37 @@
+use crate::arg;
+use crate::model::RoleSetting;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "backend/two_factor/index.html")]
+pub struct IndexTemplate {
+    pub enabled: bool,
+    /// 剩余可用的恢复码数量
+    pub unused_codes: i64,
+    /// 角色是否要求二步验证
+    pub required: bool,
+    pub arg: arg::BackendQueryArg,
+}
+
+#[derive(Template)]
+#[template(path = "backend/two_factor/enroll.html")]
+pub struct EnrollTemplate {
+    pub secret: String,
+    pub qr_svg: String,
+}
+
+/// 只展示一次的恢复码
+#[derive(Template)]
+#[template(path = "backend/two_factor/recovery_codes.html")]
+pub struct RecoveryCodesTemplate {
+    pub codes: Vec<String>,
+    /// 是否已经注销了登录, 需要重新登录
+    pub relogin: bool,
+}
+
+#[derive(Template)]
+#[template(path = "backend/two_factor/roles.html")]
+pub struct RolesTemplate {
+    pub list: Vec<RoleSetting>,
+    pub arg: arg::BackendQueryArg,
+}
@@ -1,19 +1,27 @@
 pub mod arg;
 pub mod cache;
+pub mod captcha;
+pub mod cli;
 pub mod config;
+pub mod csrf;
 pub mod db;
 pub mod error;
 pub mod form;
 pub mod handler;
+pub mod hanzi;
 pub mod hcaptcha;
 pub mod html;
+pub mod login_guard;
 pub mod middleware;
 pub mod model;
+pub mod notify;
 pub mod password;
-pub mod rdb;
+pub mod rbac;
 pub mod session;
 pub mod sms;
 pub mod time;
+pub mod token;
+pub mod totp;
 
 /// 结果
 type Result<T> = std::result::Result<T, self::error::AppError>;
@@ -0,0 +1,213 @@
+//! 登录防暴力破解
+//!
+//! 按用户名和客户端 IP 分别统计失败次数, 每次失败后的延迟逐渐增加,
+//! 超过阈值后在一段时间内拒绝登录, 管理员可以手动解锁.
+
+use crate::cache::Cache;
+use crate::config::LoginConfig;
+use crate::error::AppError;
+use crate::Result;
+use axum::http::HeaderMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+fn user_key(cfg: &LoginConfig, username: &str) -> String {
+    format!("{}fail:user:{}", cfg.prefix, username)
+}
+fn ip_key(cfg: &LoginConfig, ip: &str) -> String {
+    format!("{}fail:ip:{}", cfg.prefix, ip)
+}
+fn user_lock_key(cfg: &LoginConfig, username: &str) -> String {
+    format!("{}lock:user:{}", cfg.prefix, username)
+}
+fn ip_lock_key(cfg: &LoginConfig, ip: &str) -> String {
+    format!("{}lock:ip:{}", cfg.prefix, ip)
+}
+/// 用户名登录时遇到过的被锁定的 IP, 管理员解锁时一起解除
+fn user_ips_key(cfg: &LoginConfig, username: &str) -> String {
+    format!("{}lock:ips:{}", cfg.prefix, username)
+}
+
+/// 获取客户端 IP, 只有配置了 `trust_proxy` 才信任代理传过来的头
+pub fn client_ip(cfg: &LoginConfig, headers: &HeaderMap, addr: &SocketAddr) -> String {
+    if cfg.trust_proxy {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .or_else(|| headers.get("x-real-ip"))
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|v| v.trim().parse::<IpAddr>().ok());
+        if let Some(ip) = forwarded {
+            return ip.to_string();
+        }
+    }
+    addr.ip().to_string()
+}
+
+/// 第 `failures` 次失败之后的延迟, 每次翻倍, 不超过上限
+pub fn delay_for(cfg: &LoginConfig, failures: u32) -> Duration {
+    if failures == 0 {
+        return Duration::from_millis(0);
+    }
+    let factor = 1u64.checked_shl(failures - 1).unwrap_or(u64::MAX);
+    let ms = cfg.delay_ms.saturating_mul(factor).min(cfg.max_delay_ms);
+    Duration::from_millis(ms)
+}
+
+fn locked_error(seconds: i64) -> AppError {
+    let minutes = (seconds + 59) / 60;
+    AppError::auth_error(&format!(
+        "登录失败次数过多, 已被临时锁定, 请 {} 分钟后再试",
+        minutes.max(1)
+    ))
+}
+
+/// 登录前检查用户名和 IP 是否被锁定
+pub async fn check(cache: &dyn Cache, cfg: &LoginConfig, username: &str, ip: &str) -> Result<()> {
+    let ttl = cache.ttl(&user_lock_key(cfg, username)).await?;
+    if ttl > 0 {
+        return Err(locked_error(ttl));
+    }
+    let ttl = cache.ttl(&ip_lock_key(cfg, ip)).await?;
+    if ttl > 0 {
+        // IP 可能是因为其他用户名被锁定的, 记下来让管理员能看到并解锁这个用户
+        cache
+            .sadd(&user_ips_key(cfg, username), ip, cfg.lock_seconds)
+            .await?;
+        return Err(locked_error(ttl));
+    }
+    Ok(())
+}
+
+/// 记录一次失败, 返回该用户名在时间窗口内的失败次数, 以及这次失败是否触发了锁定
+pub async fn record_failure(
+    cache: &dyn Cache,
+    cfg: &LoginConfig,
+    username: &str,
+    ip: &str,
+) -> Result<(u32, bool)> {
+    let user_failures = cache.incr(&user_key(cfg, username), cfg.window).await?;
+    let ip_failures = cache.incr(&ip_key(cfg, ip), cfg.window).await?;
+    let mut locked = false;
+    if user_failures >= cfg.max_attempts as i64 {
+        cache
+            .set_ex(&user_lock_key(cfg, username), ip, cfg.lock_seconds)
+            .await?;
+        cache.del(&user_key(cfg, username)).await?;
+        locked = true;
+    }
+    if ip_failures >= cfg.max_ip_attempts as i64 {
+        cache
+            .set_ex(&ip_lock_key(cfg, ip), username, cfg.lock_seconds)
+            .await?;
+        cache.del(&ip_key(cfg, ip)).await?;
+        cache
+            .sadd(&user_ips_key(cfg, username), ip, cfg.lock_seconds)
+            .await?;
+        locked = true;
+    }
+    Ok((user_failures as u32, locked))
+}
+
+/// 登录成功后清除该用户名的失败次数, IP 的计数保留到时间窗口结束
+pub async fn record_success(cache: &dyn Cache, cfg: &LoginConfig, username: &str) -> Result<()> {
+    cache.del(&user_key(cfg, username)).await?;
+    Ok(())
+}
+
+/// 某个 IP 在时间窗口内的失败次数
+pub async fn ip_failures(cache: &dyn Cache, cfg: &LoginConfig, ip: &str) -> Result<u32> {
+    let failures = cache.get_str(&ip_key(cfg, ip)).await?;
+    Ok(failures.and_then(|n| n.parse().ok()).unwrap_or(0))
+}
+
+/// 用户名是否被锁定, 包括登录时使用的 IP 被锁定
+pub async fn is_locked(cache: &dyn Cache, cfg: &LoginConfig, username: &str) -> Result<bool> {
+    if cache.is_exists(&user_lock_key(cfg, username)).await? {
+        return Ok(true);
+    }
+    for ip in cache.smembers(&user_ips_key(cfg, username)).await? {
+        if cache.is_exists(&ip_lock_key(cfg, &ip)).await? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// 管理员手动解锁, 同时解除锁定时使用的 IP 以及该用户名登录时遇到的被锁定的 IP
+pub async fn unlock(cache: &dyn Cache, cfg: &LoginConfig, username: &str) -> Result<()> {
+    let mut ips = cache.smembers(&user_ips_key(cfg, username)).await?;
+    if let Some(ip) = cache.get_str(&user_lock_key(cfg, username)).await? {
+        ips.push(ip);
+    }
+    for ip in ips {
+        cache.del(&ip_lock_key(cfg, &ip)).await?;
+        cache.del(&ip_key(cfg, &ip)).await?;
+    }
+    cache.del(&user_ips_key(cfg, username)).await?;
+    cache.del(&user_lock_key(cfg, username)).await?;
+    cache.del(&user_key(cfg, username)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::sleddb::SledDb;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_delay_for() {
+        let cfg = LoginConfig::default();
+        assert_eq!(delay_for(&cfg, 0), Duration::from_millis(0));
+        assert_eq!(delay_for(&cfg, 1), Duration::from_millis(500));
+        assert_eq!(delay_for(&cfg, 3), Duration::from_millis(2000));
+        assert_eq!(delay_for(&cfg, 10), Duration::from_millis(8000));
+        assert_eq!(delay_for(&cfg, 100), Duration::from_millis(8000));
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let addr: SocketAddr = "10.0.0.2:34567".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("203.0.113.9, 10.0.0.1"),
+        );
+
+        let mut cfg = LoginConfig::default();
+        assert_eq!(client_ip(&cfg, &headers, &addr), "10.0.0.2");
+
+        cfg.trust_proxy = true;
+        assert_eq!(client_ip(&cfg, &headers, &addr), "203.0.113.9");
+
+        headers.insert("x-forwarded-for", HeaderValue::from_static("bogus"));
+        assert_eq!(client_ip(&cfg, &headers, &addr), "10.0.0.2");
+    }
+
+    #[tokio::test]
+    async fn unlock_should_clear_ip_lock() {
+        let cache = SledDb::new("./test_sleddb_login_guard");
+        let cfg = LoginConfig {
+            max_attempts: 100,
+            max_ip_attempts: 2,
+            ..LoginConfig::default()
+        };
+        let ip = "203.0.113.9";
+        unlock(&cache, &cfg, "alice").await.unwrap();
+        unlock(&cache, &cfg, "bob").await.unwrap();
+
+        // 其他用户名的失败锁定了 IP, alice 从这个 IP 登录时也被锁定
+        record_failure(&cache, &cfg, "bob", ip).await.unwrap();
+        let (_, locked) = record_failure(&cache, &cfg, "bob", ip).await.unwrap();
+        assert!(locked);
+        assert!(check(&cache, &cfg, "alice", ip).await.is_err());
+        assert!(is_locked(&cache, &cfg, "alice").await.unwrap());
+
+        unlock(&cache, &cfg, "alice").await.unwrap();
+        assert!(!is_locked(&cache, &cfg, "alice").await.unwrap());
+        assert!(check(&cache, &cfg, "alice", ip).await.is_ok());
+        assert_eq!(ip_failures(&cache, &cfg, ip).await.unwrap(), 0);
+        unlock(&cache, &cfg, "bob").await.unwrap();
+    }
+}
@@ -1,14 +1,18 @@
 use axum::extract::{extractor_middleware, Extension};
 use axum::http::StatusCode;
-use axum::routing::{get, get_service};
+use axum::routing::{get, get_service, post};
 use axum::Router;
 use axum_server::tls_rustls::RustlsConfig;
+use clap::Parser;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use strangers::handler::{auth, backend, home};
+use strangers::cli::{self, Cli, Command, ConfigCommand};
+use strangers::config::{Config, CERT_FILE, KEY_FILE};
+use strangers::db::migration;
+use strangers::handler::{api, auth, backend, home};
 use strangers::middleware::admin_auth::Auth;
 use strangers::model::AppState;
-use strangers::sms::send::NotifySms;
+use strangers::notify::{outbox, Notifier};
 use strangers::sms::sms;
 use tower::ServiceBuilder;
 use tower_cookies::CookieManagerLayer;
@@ -19,35 +23,93 @@ use tracing_subscriber::util::SubscriberInitExt;
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
     // dotenv 放前面，因为配置文件里面有可能配置 `RUST_LOG` 信息，但是有可能会受全局变量配置的影响, whatever.
     dotenv::dotenv().ok();
+    // 日志输出到标准错误, 命令行输出的结果(如导出的 json)不会混入日志
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG").unwrap_or_else(|_| "strangers=debug".to_string()),
         ))
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
 
-    let cfg = strangers::config::Config::from_env().unwrap();
-    let pool = cfg.pg.create_pool(None, tokio_postgres::NoTls).unwrap();
-    let rdc = redis::Client::open(&*cfg.redis.dsn).unwrap(); // TODO: 为什么是 &*
+    let cfg = match Config::load(cli.config.as_deref()) {
+        Ok(cfg) => cfg,
+        Err(err) => exit(format!("读取配置失败: {}", err)),
+    };
+    let command = cli.command.unwrap_or(Command::Serve);
+    if let Command::Config(ConfigCommand::Check) = command {
+        if !cli::check_config(&cfg) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    let problems = cfg.validate();
+    if !problems.is_empty() {
+        exit(format!(
+            "配置有 {} 个问题:\n  - {}",
+            problems.len(),
+            problems.join("\n  - ")
+        ));
+    }
+    debug!("cfg: {}", cfg.masked());
+    if cli::only_needs_db(&command) {
+        let pool = match AppState::pool_from_config(&cfg) {
+            Ok(pool) => pool,
+            Err(err) => exit(format!("初始化失败: {}", err)),
+        };
+        if let Err(err) = cli::run_db(&pool, command).await {
+            exit(err.to_string());
+        }
+        return;
+    }
+    let state = match AppState::from_config(&cfg).await {
+        Ok(state) => Arc::new(state),
+        Err(err) => exit(format!("初始化失败: {}", err)),
+    };
 
-    debug!("cfg: {:#?}", cfg);
+    match command {
+        Command::Serve => serve(cfg, state).await,
+        command => {
+            if let Err(err) = cli::run(state, command).await {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
 
-    let state = Arc::new(AppState {
-        pool,
-        rdc,
-        sess_cfg: cfg.session,
-        hcap_cfg: cfg.hcaptcha,
-        sms_cfg: cfg.sms,
-        upload_dir: cfg.upload_dir.unwrap_or("upload".to_string()),
-    });
+/// 输出错误并退出
+fn exit(msg: String) -> ! {
+    eprintln!("{}", msg);
+    std::process::exit(1);
+}
+
+/// 启动 web 服务
+async fn serve(cfg: Config, state: Arc<AppState>) {
+    if cfg.migrate.on_start {
+        let mut client = match state.pool.get().await {
+            Ok(client) => client,
+            Err(err) => exit(format!("连接数据库失败: {}", err)),
+        };
+        if let Err(err) = migration::up(&mut client, false).await {
+            exit(format!("数据库迁移失败: {}", err));
+        }
+    }
 
-    // 初始化短信发送任务
-    let notify_state = Arc::new(NotifySms::new());
+    // 补全旧数据的拼音, 用于按拼音搜索
+    tokio::spawn(strangers::hanzi::backfill(state.clone()));
 
-    // 启动短信告警后台服务
-    tokio::spawn(sms::sms_schedule(state.clone(), notify_state.clone()));
+    // 初始化所有的通知渠道
+    let notifier = match Notifier::from_state(&state) {
+        Ok(notifier) => Arc::new(notifier),
+        Err(err) => exit(format!("初始化通知渠道失败: {}", err)),
+    };
+
+    // 启动告警后台服务, 发件箱负责发送和重试
+    tokio::spawn(outbox::run(state.clone(), notifier.clone()));
+    tokio::spawn(sms::sms_schedule(state.clone(), notifier));
 
     let backend_router = backend::routers().layer(extractor_middleware::<Auth>());
     let static_serve = get_service(ServeDir::new("static")).handle_error(|err| async move {
@@ -61,28 +123,44 @@ async fn main() {
     let app = Router::new()
         .nest("/static", static_serve)
         .nest("/admin", backend_router)
+        .nest("/api/v1", api::routers())
         .route("/login", get(auth::admin_login_ui).post(auth::admin_login))
-        .route("/logout", get(auth::admin_logout))
+        .route(
+            "/login/2fa",
+            get(auth::admin_login_2fa_ui).post(auth::admin_login_2fa),
+        )
+        .route("/logout", post(auth::admin_logout))
         .route("/", get(home::admin_index))
         .layer(ServiceBuilder::new().layer(Extension(state)))
         .layer(CookieManagerLayer::new());
 
     // 启动服务
+    let addr = match cfg.web.addr.parse::<SocketAddr>() {
+        Ok(addr) => addr,
+        Err(err) => exit(format!("WEB.ADDR 不是合法的地址 {}: {}", cfg.web.addr, err)),
+    };
+    let service = app.into_make_service_with_connect_info::<SocketAddr, _>();
     if !&cfg.ssl_enable {
-        info!("web server listening on http://{}", &cfg.web.addr);
-        axum::Server::bind(&cfg.web.addr.parse::<SocketAddr>().unwrap())
-            .serve(app.into_make_service())
-            .await
-            .unwrap();
+        info!("web server listening on http://{}", addr);
+        let server = match axum::Server::try_bind(&addr) {
+            Ok(server) => server,
+            Err(err) => exit(format!("监听 {} 失败: {}", addr, err)),
+        };
+        if let Err(err) = server.serve(service).await {
+            exit(format!("web 服务退出: {}", err));
+        }
     } else {
         // 启动 https 服务
-        info!("web server listening on https://{}", &cfg.web.addr);
-        let ssl_cfg = RustlsConfig::from_pem_file("certs/emacsvi.com.cer", "certs/emacsvi.com.key")
-            .await
-            .unwrap();
-        axum_server::bind_rustls(cfg.web.addr.parse::<SocketAddr>().unwrap(), ssl_cfg)
-            .serve(app.into_make_service())
-            .await
-            .unwrap();
+        info!("web server listening on https://{}", addr);
+        let ssl_cfg = match RustlsConfig::from_pem_file(CERT_FILE, KEY_FILE).await {
+            Ok(ssl_cfg) => ssl_cfg,
+            Err(err) => exit(format!(
+                "读取证书 {} 和私钥 {} 失败: {}",
+                CERT_FILE, KEY_FILE, err
+            )),
+        };
+        if let Err(err) = axum_server::bind_rustls(addr, ssl_cfg).serve(service).await {
+            exit(format!("web 服务退出: {}", err));
+        }
     }
 }
@@ -1,27 +1,100 @@
+use crate::csrf;
+use crate::db::admin;
 use crate::error::AppError;
 use crate::handler::backend::get_login_admin_by_cookie;
+use crate::handler::helper::get_client;
 use crate::model::AppState;
+use crate::rbac::CurrentAdmin;
+use crate::token::{self, TokenScope};
 use axum::async_trait;
-use axum::extract::{FromRequest, RequestParts};
+use axum::body::{Bytes, HttpBody};
+use axum::extract::{FromRequest, OriginalUri, RequestParts};
+use axum::http::header::AUTHORIZATION;
+use axum::BoxError;
 use std::sync::Arc;
 use tower_cookies::Cookies;
 use tracing::debug;
 
+/// 修改自己密码的页面
+const CHANGE_PASSWORD_PATH: &str = "/admin/password";
+/// 二步验证的页面
+const TWO_FACTOR_PATH: &str = "/admin/2fa";
+
 pub struct Auth {}
 
 #[async_trait]
 impl<B> FromRequest<B> for Auth
 where
-    B: Send,
+    B: HttpBody + From<Bytes> + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
 {
     type Rejection = AppError;
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
-        let state = req.extensions().unwrap().get::<Arc<AppState>>().unwrap();
+        let state = req
+            .extensions()
+            .unwrap()
+            .get::<Arc<AppState>>()
+            .unwrap()
+            .clone();
+
+        // 带有 `Authorization` 头的请求使用 API token 认证, 不再检查 cookie
+        let authorization = req
+            .headers()
+            .and_then(|h| h.get(AUTHORIZATION))
+            .and_then(|v| v.to_str().ok());
+        if let Some(authorization) = authorization {
+            let bearer = token::from_authorization(authorization)
+                .ok_or_else(|| AppError::auth_error("无效的 token"))?;
+            let identity = token::authenticate(&state, bearer).await?;
+            debug!("token identity: {:?}", identity);
+            if !TokenScope::from(identity.scope.as_str()).allows(req.method()) {
+                return Err(AppError::auth_error("token 为只读权限, 不允许该操作"));
+            }
+            let client = get_client(&state, "auth_token").await?;
+            let categories = admin::categories(&client, identity.admin_id).await?;
+            let current = CurrentAdmin::from_token(identity, categories);
+            req.extensions_mut().unwrap().insert(current);
+            return Ok(Auth {});
+        }
+
         let ck = req.extensions().unwrap().get::<Cookies>().unwrap();
         // let headers = req.headers().unwrap();
         let admin_session = get_login_admin_by_cookie(&state, ck).await?;
-        if let Some(_) = admin_session {
+        if let Some(admin_session) = admin_session {
+            // 临时密码登录或者角色要求二步验证但还没有启用的账号, 只能访问对应的页面
+            let restricted = if admin_session.must_change_password {
+                Some((
+                    CHANGE_PASSWORD_PATH,
+                    "你正在使用临时密码, 请先访问 /admin/password 修改密码",
+                ))
+            } else if admin_session.must_enroll_2fa {
+                Some((
+                    TWO_FACTOR_PATH,
+                    "你的角色要求启用二步验证, 请先访问 /admin/2fa 完成设置",
+                ))
+            } else {
+                None
+            };
+            if let Some((allowed, msg)) = restricted {
+                let path = req
+                    .extensions()
+                    .and_then(|e| e.get::<OriginalUri>())
+                    .map(|uri| uri.0.path().to_string())
+                    .unwrap_or_else(|| req.uri().path().to_string());
+                if path != allowed && !path.starts_with(&format!("{}/", allowed)) {
+                    return Err(AppError::forbidden(msg));
+                }
+            }
+            // 使用 cookie 登录时, 修改数据的请求需要校验 csrf token
+            if !csrf::is_safe_method(req.method()) {
+                csrf::verify(req, &admin_session.csrf_token).await?;
+            }
+            // 放入 extensions, 供 handler 通过 `Extension<CurrentAdmin>` 判断权限
+            req.extensions_mut()
+                .unwrap()
+                .insert(CurrentAdmin::from(admin_session));
             Ok(Auth {})
         } else {
             Err(AppError::auth_error(
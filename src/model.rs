@@ -1,27 +1,149 @@
-use crate::config::{HCaptchaConfig, SessionConfig, SmsConfig};
+use crate::cache::Cache;
+use crate::config::{
+    CaptchaConfig, Config, HCaptchaConfig, LoginConfig, NotifyConfig, PageConfig, SessionConfig,
+    SmsConfig,
+};
+use crate::error::{AppError, AppErrorType};
+use crate::notify::schedule::Plan;
+use crate::rbac::Role;
 use crate::sms::sms::EXPIRED_DAYS;
 use chrono::Datelike;
-use redis::Client;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
+use std::sync::RwLock;
 use tokio_pg_mapper_derive::PostgresMapper;
+use utoipa::ToSchema;
 
 pub struct AppState {
     pub pool: deadpool_postgres::Pool,
-    pub rdc: Client,
+    /// redis 或 sled 缓存
+    pub cache: Box<dyn Cache>,
     pub sess_cfg: SessionConfig,
     pub hcap_cfg: HCaptchaConfig,
+    pub captcha_cfg: CaptchaConfig,
+    pub login_cfg: LoginConfig,
+    pub page_cfg: PageConfig,
     pub upload_dir: String,
     pub sms_cfg: Option<SmsConfig>,
+    pub notify_cfg: NotifyConfig,
+    /// 定时检查通知的计划
+    pub plan: Plan,
+    /// 定时任务的下一次检查, 定时任务没有启动时为空
+    pub next_run: RwLock<Option<chrono::DateTime<chrono_tz::Tz>>>,
+    /// 唤醒发件箱立即发送, 如后台重新发送失败的通知
+    pub outbox_wake: tokio::sync::Notify,
 }
 
+impl AppState {
+    /// 根据配置创建数据库连接池和缓存, web 服务和命令行共用
+    pub async fn from_config(cfg: &Config) -> crate::Result<Self> {
+        let pool = Self::pool_from_config(cfg)?;
+        let cache = crate::cache::from_config(&cfg.cache, cfg.redis.as_ref()).await?;
+        let plan = Plan::new(
+            &cfg.schedule,
+            cfg.sms.as_ref().and_then(|c| c.check_interval),
+        )
+        .map_err(|err| AppError::from_str(&err, AppErrorType::Common))?;
+        Ok(Self {
+            pool,
+            cache,
+            sess_cfg: SessionConfig {
+                secure: cfg.session.secure || cfg.ssl_enable,
+                ..cfg.session.clone()
+            },
+            hcap_cfg: cfg.hcaptcha.clone(),
+            captcha_cfg: cfg.captcha.clone(),
+            login_cfg: cfg.login.clone(),
+            page_cfg: cfg.page.clone(),
+            sms_cfg: cfg.sms.clone(),
+            notify_cfg: cfg.notify.clone(),
+            plan,
+            next_run: RwLock::new(None),
+            outbox_wake: tokio::sync::Notify::new(),
+            upload_dir: cfg
+                .upload_dir
+                .clone()
+                .unwrap_or_else(|| "upload".to_string()),
+        })
+    }
+
+    /// 只创建数据库连接池, 用于不需要缓存的命令行
+    pub fn pool_from_config(cfg: &Config) -> crate::Result<deadpool_postgres::Pool> {
+        cfg.pg
+            .create_pool(None, tokio_postgres::NoTls)
+            .map_err(|err| AppError::from_err(err, AppErrorType::DbError))
+    }
+}
+
+/// 登录 session, 只保存身份和登录信息, 不保存密码
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AdminSession {
     pub id: i32,
     pub username: String,
-    pub password: String,
     pub is_sys: bool,
+    /// 最长有效期的截止时间
     pub dateline: i32,
+    /// 角色, 旧的 session 没有这个字段
+    #[serde(default)]
+    pub role: String,
+    /// 允许操作的类目, 为空表示不限制
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// 使用临时密码登录, 修改密码之前只能访问修改密码页面
+    #[serde(default)]
+    pub must_change_password: bool,
+    /// 角色要求二步验证但还没有启用, 启用之前只能访问二步验证页面
+    #[serde(default)]
+    pub must_enroll_2fa: bool,
+    /// 登录时的 IP
+    #[serde(default)]
+    pub ip: String,
+    /// 登录时的浏览器
+    #[serde(default)]
+    pub user_agent: String,
+    /// 登录时间
+    #[serde(default)]
+    pub created_at: i64,
+    /// 最后访问时间
+    #[serde(default)]
+    pub last_seen: i64,
+    /// 防跨站请求伪造的 token, 修改数据的请求必须带上
+    #[serde(default)]
+    pub csrf_token: String,
+}
+
+impl AdminSession {
+    pub fn created_at_str(&self) -> String {
+        timestamp_str(self.created_at)
+    }
+
+    pub fn last_seen_str(&self) -> String {
+        timestamp_str(self.last_seen)
+    }
+
+    pub fn dateline_str(&self) -> String {
+        timestamp_str(self.dateline as i64)
+    }
+}
+
+/// 格式化 unix 时间戳, 为 0 时表示未知
+fn timestamp_str(ts: i64) -> String {
+    use chrono::TimeZone;
+    if ts <= 0 {
+        return "-".to_string();
+    }
+    chrono::Local
+        .timestamp(ts, 0)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// 管理员的一个登录
+pub struct ActiveSession {
+    pub id: String,
+    pub data: AdminSession,
+    /// 是否是当前浏览器的登录
+    pub is_current: bool,
 }
 
 #[derive(PostgresMapper)]
@@ -32,6 +154,47 @@ pub struct Admin {
     pub password: String,
     pub is_sys: bool,
     pub is_del: bool,
+    pub role: String,
+    /// 管理员重置后的临时密码, 下次登录必须修改
+    pub must_change_password: bool,
+    /// 被禁用的账号不能登录, 也不能使用 API token
+    pub is_disabled: bool,
+    /// 二步验证的密钥, base32 编码
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    /// 最后一次使用的动态码所在的周期, 防止动态码被重复使用
+    pub totp_last_step: i64,
+}
+
+impl Admin {
+    pub fn role_label(&self) -> &'static str {
+        Role::from(self.role.as_str()).label()
+    }
+}
+
+/// 管理员的公开信息, 不包含密码, 用于 API 输出
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AdminInfo {
+    pub id: i32,
+    pub username: String,
+    pub is_sys: bool,
+    pub is_del: bool,
+    /// 角色: viewer, operator, pharmacist, administrator, auditor
+    pub role: String,
+    pub is_disabled: bool,
+}
+
+impl From<Admin> for AdminInfo {
+    fn from(admin: Admin) -> Self {
+        Self {
+            id: admin.id,
+            username: admin.username,
+            is_sys: admin.is_sys,
+            is_del: admin.is_del,
+            role: admin.role,
+            is_disabled: admin.is_disabled,
+        }
+    }
 }
 
 #[derive(PostgresMapper)]
@@ -40,7 +203,7 @@ pub struct AdminID {
     pub id: i32,
 }
 
-#[derive(PostgresMapper)]
+#[derive(PostgresMapper, Serialize, Deserialize, ToSchema)]
 #[pg_mapper(table = "medicinal")]
 pub struct MedicinalList {
     pub id: i32,
@@ -51,9 +214,29 @@ pub struct MedicinalList {
     pub count: String,
     pub validity: chrono::NaiveDate,
     pub is_del: bool,
+    /// 入库日期
+    pub created_at: Option<chrono::NaiveDate>,
+    /// 搜索时与关键字匹配的字段: `name`, `batch_number`, `spec` 或 `category`, 不搜索时为空
+    pub matched: Option<String>,
 }
 
 impl MedicinalList {
+    /// 入库日期, 没有时为空
+    pub fn created_at_str(&self) -> String {
+        self.created_at.map(|d| d.to_string()).unwrap_or_default()
+    }
+
+    /// 匹配字段的名称, 不搜索时为空
+    pub fn matched_label(&self) -> &'static str {
+        match self.matched.as_deref() {
+            Some("name") => "名称",
+            Some("batch_number") => "批号",
+            Some("spec") => "规格",
+            Some("category") => "类目",
+            _ => "",
+        }
+    }
+
     pub fn is_expired(&self) -> bool {
         let now = chrono::Local::now();
         let validity = chrono::NaiveDate::from_ymd(now.year(), now.month(), now.day());
@@ -83,6 +266,15 @@ impl MedicinalList {
     }
 }
 
+/// 补全拼音时使用的药品名称和类目
+#[derive(PostgresMapper)]
+#[pg_mapper(table = "medicinal")]
+pub struct MedicinalName {
+    pub id: i32,
+    pub name: String,
+    pub category: String,
+}
+
 pub struct ExpiredItem {
     pub name: String,
     pub id: u8,
@@ -127,14 +319,230 @@ impl Display for MedicinalList {
     }
 }
 
-#[derive(PostgresMapper)]
+#[derive(PostgresMapper, Serialize, ToSchema)]
 #[pg_mapper(table = "medicinal")]
 pub struct MedicinalID {
     pub id: i32,
 }
 
-#[derive(PostgresMapper)]
+#[derive(PostgresMapper, Serialize, Deserialize, ToSchema, Clone)]
 #[pg_mapper(table = "medicinal")]
 pub struct Category {
     pub category: String,
 }
+
+/// 管理员记住的每页记录数
+#[derive(PostgresMapper)]
+#[pg_mapper(table = "admin")]
+pub struct AdminPageSize {
+    pub page_size: Option<i32>,
+}
+
+/// 管理员被限定的类目
+#[derive(PostgresMapper)]
+#[pg_mapper(table = "admin_scope")]
+pub struct AdminScope {
+    pub category: String,
+}
+
+/// 个人 API token, 不包含 token 的摘要
+#[derive(PostgresMapper)]
+#[pg_mapper(table = "api_token")]
+pub struct ApiToken {
+    pub id: i32,
+    pub admin_id: i32,
+    pub name: String,
+    /// 权限范围: read 或者 write
+    pub scope: String,
+    pub expires_at: Option<chrono::DateTime<chrono::Local>>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Local>>,
+    pub is_revoked: bool,
+    pub created_at: chrono::DateTime<chrono::Local>,
+}
+
+impl ApiToken {
+    pub fn is_expired(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= chrono::Local::now(),
+            None => false,
+        }
+    }
+
+    pub fn expires_at_str(&self) -> String {
+        match self.expires_at {
+            Some(t) => t.format("%Y-%m-%d %H:%M").to_string(),
+            None => "永不过期".to_string(),
+        }
+    }
+
+    pub fn last_used_at_str(&self) -> String {
+        match self.last_used_at {
+            Some(t) => t.format("%Y-%m-%d %H:%M").to_string(),
+            None => "从未使用".to_string(),
+        }
+    }
+}
+
+#[derive(PostgresMapper)]
+#[pg_mapper(table = "api_token")]
+pub struct ApiTokenID {
+    pub id: i32,
+}
+
+/// 通过 token 认证得到的身份
+#[derive(PostgresMapper, Debug)]
+#[pg_mapper(table = "api_token")]
+pub struct TokenIdentity {
+    /// token ID
+    pub id: i32,
+    pub admin_id: i32,
+    pub username: String,
+    pub role: String,
+    pub scope: String,
+}
+
+/// 角色的设置
+#[derive(PostgresMapper)]
+#[pg_mapper(table = "role_setting")]
+pub struct RoleSetting {
+    pub role: String,
+    /// 是否要求该角色的管理员启用二步验证
+    pub require_2fa: bool,
+}
+
+impl RoleSetting {
+    pub fn role_label(&self) -> &'static str {
+        Role::from(self.role.as_str()).label()
+    }
+}
+
+/// 登录事件, 目前只记录失败的登录
+#[derive(PostgresMapper)]
+#[pg_mapper(table = "login_event")]
+pub struct LoginEvent {
+    pub id: i32,
+    pub username: String,
+    pub ip: String,
+    /// 失败原因
+    pub reason: String,
+    pub created_at: chrono::DateTime<chrono::Local>,
+}
+
+impl LoginEvent {
+    pub fn created_at_str(&self) -> String {
+        self.created_at.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+}
+
+/// 已经执行的数据库迁移
+#[derive(PostgresMapper)]
+#[pg_mapper(table = "schema_version")]
+pub struct SchemaVersion {
+    pub version: i32,
+    pub name: String,
+    /// 执行时迁移文件内容的 sha256
+    pub checksum: String,
+    pub applied_at: chrono::DateTime<chrono::Local>,
+}
+
+/// 后台保存的短信设置, 为空的字段使用配置中的值
+#[derive(PostgresMapper, Default, Clone, Debug, PartialEq)]
+#[pg_mapper(table = "sms_setting")]
+pub struct SmsSetting {
+    pub access_key_id: String,
+    pub access_key_secret: String,
+    pub sign_name: String,
+    pub template_code: String,
+    /// 接收短信的手机号码
+    pub phones: Vec<String>,
+}
+
+/// 通知发件箱中的一条消息, 一个事件发送到每个渠道各一条
+#[derive(PostgresMapper)]
+#[pg_mapper(table = "notify_outbox")]
+pub struct NotifyOutbox {
+    pub id: i32,
+    /// 渠道的标识, 如 `sms`, `email`, `webhook.0`
+    pub channel: String,
+    /// 渠道的名称
+    pub channel_name: String,
+    /// 接收人, 如手机号码、邮件地址或者 webhook 的主机名
+    pub recipients: String,
+    /// 事件类型
+    pub kind: String,
+    pub title: String,
+    pub summary: String,
+    /// 事件的 JSON, 重试和重新发送时使用
+    pub payload: String,
+    /// 状态: pending, sending, sent, failed
+    pub status: String,
+    /// 已经尝试发送的次数
+    pub attempts: i32,
+    pub next_attempt_at: chrono::DateTime<chrono::Local>,
+    /// 服务商的响应或者失败原因
+    pub response: String,
+    pub created_at: chrono::DateTime<chrono::Local>,
+    pub sent_at: Option<chrono::DateTime<chrono::Local>>,
+}
+
+impl NotifyOutbox {
+    /// 等待发送, 包括等待重试
+    pub const PENDING: &'static str = "pending";
+    /// 已经取出, 正在发送
+    pub const SENDING: &'static str = "sending";
+    pub const SENT: &'static str = "sent";
+    /// 重试之后仍然失败, 只能在后台重新发送
+    pub const FAILED: &'static str = "failed";
+
+    pub fn created_at_str(&self) -> String {
+        self.created_at.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+
+    /// 发送成功的时间, 没有发送成功时为空
+    pub fn sent_at_str(&self) -> String {
+        self.sent_at
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn next_attempt_at_str(&self) -> String {
+        self.next_attempt_at.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+
+    pub fn status_label(&self) -> &'static str {
+        match self.status.as_str() {
+            Self::PENDING if self.attempts > 0 => "等待重试",
+            Self::PENDING => "等待发送",
+            Self::SENDING => "正在发送",
+            Self::SENT => "已发送",
+            _ => "失败",
+        }
+    }
+
+    /// 状态标签的颜色
+    pub fn status_class(&self) -> &'static str {
+        match self.status.as_str() {
+            Self::SENT => "badge-success",
+            Self::FAILED => "badge-danger",
+            Self::PENDING if self.attempts > 0 => "badge-warning",
+            _ => "badge-secondary",
+        }
+    }
+
+    /// 失败的消息可以重新发送
+    pub fn can_resend(&self) -> bool {
+        self.status == Self::FAILED
+    }
+}
+
+/// 写入发件箱的消息
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboxMessage {
+    pub channel: String,
+    pub channel_name: String,
+    pub recipients: String,
+    pub kind: String,
+    pub title: String,
+    pub summary: String,
+    pub payload: String,
+}
@@ -0,0 +1,169 @@
+//! 汇总通知: 一次检查中需要提醒的药品按类目统计, 只发送一条消息
+
+use crate::notify::Event;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// 一个类目的统计
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct DigestGroup {
+    pub category: String,
+    pub expired: usize,
+    pub expiring: usize,
+    pub low_stock: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Digest {
+    /// 多少天内过期算即将过期
+    pub expiring_days: i64,
+    /// 按类目统计, 按类目名称排序
+    pub groups: Vec<DigestGroup>,
+    /// 后台列表页的地址, 没有配置 `NOTIFY.BASE_URL` 时为空
+    pub link: Option<String>,
+    /// 汇总的药品
+    pub ids: Vec<i32>,
+}
+
+/// 不为 0 的数量, 如 `已过期 1, 30天内过期 5`
+fn counts(expired: usize, expiring: usize, low_stock: usize, days: i64) -> String {
+    let mut list = vec![];
+    if expired > 0 {
+        list.push(format!("已过期 {}", expired));
+    }
+    if expiring > 0 {
+        list.push(format!("{}天内过期 {}", days, expiring));
+    }
+    if low_stock > 0 {
+        list.push(format!("库存不足 {}", low_stock));
+    }
+    list.join(", ")
+}
+
+impl Digest {
+    /// 汇总逐个药品的通知
+    ///
+    /// # 参数
+    ///
+    /// * `events` - 逐个药品的通知
+    /// * `expiring_days` - 多少天内过期算即将过期
+    /// * `base_url` - 后台的访问地址, 用于生成列表页的链接
+    pub fn new(events: &[Event], expiring_days: i64, base_url: Option<&str>) -> Self {
+        let mut groups: BTreeMap<String, DigestGroup> = BTreeMap::new();
+        let mut ids = vec![];
+        for event in events {
+            let item = match event.item() {
+                Some(item) => item,
+                None => continue,
+            };
+            let group = groups
+                .entry(item.category.clone())
+                .or_insert_with(|| DigestGroup {
+                    category: item.category.clone(),
+                    ..DigestGroup::default()
+                });
+            match event {
+                Event::Expired(_) => group.expired += 1,
+                Event::Expiring { .. } => group.expiring += 1,
+                Event::LowStock { .. } => group.low_stock += 1,
+                Event::Digest(_) => {}
+            }
+            ids.push(item.id);
+        }
+        let mut digest = Self {
+            expiring_days,
+            groups: groups.into_values().collect(),
+            link: None,
+            ids,
+        };
+        digest.link = base_url.map(|base| {
+            format!(
+                "{}/admin/medicinal?{}",
+                base.trim_end_matches('/'),
+                digest.query()
+            )
+        });
+        digest
+    }
+
+    /// 列表页的查询参数: 有即将过期的药品时查看即将过期的, 否则查看已经过期的, 只有库存不足时按数量排序
+    fn query(&self) -> String {
+        let (expired, expiring, _) = self.totals();
+        if expiring > 0 {
+            format!("expired={}", (self.expiring_days + 29) / 30 + 1)
+        } else if expired > 0 {
+            "expired=1".to_string()
+        } else {
+            "order=count".to_string()
+        }
+    }
+
+    /// 已经过期、即将过期和库存不足的总数
+    pub fn totals(&self) -> (usize, usize, usize) {
+        self.groups.iter().fold((0, 0, 0), |(a, b, c), g| {
+            (a + g.expired, b + g.expiring, c + g.low_stock)
+        })
+    }
+
+    /// 一行的摘要, 如 `已过期 3, 30天内过期 12`
+    pub fn summary(&self) -> String {
+        let (expired, expiring, low_stock) = self.totals();
+        counts(expired, expiring, low_stock, self.expiring_days)
+    }
+
+    /// 摘要、每个类目的统计和列表页的链接, 每项一行
+    pub fn text(&self) -> String {
+        let mut lines = vec![self.summary()];
+        for g in &self.groups {
+            lines.push(format!(
+                "{}: {}",
+                g.category,
+                counts(g.expired, g.expiring, g.low_stock, self.expiring_days)
+            ));
+        }
+        if let Some(link) = &self.link {
+            lines.push(format!("查看: {}", link));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::tests::item;
+
+    #[test]
+    fn test_digest() {
+        let mut other = item();
+        other.id = 2;
+        other.category = "手术室".to_string();
+        let events = vec![
+            Event::Expired(item()),
+            Event::Expiring {
+                item: other,
+                days: 30,
+            },
+            Event::LowStock {
+                item: item(),
+                threshold: 5,
+            },
+        ];
+        let digest = Digest::new(&events, 30, Some("https://emacsvi.com:9998/"));
+        assert_eq!(digest.totals(), (1, 1, 1));
+        assert_eq!(digest.ids, vec![1, 2, 1]);
+        assert_eq!(digest.groups[0].category, "急救");
+        assert_eq!(
+            digest.link.as_deref(),
+            Some("https://emacsvi.com:9998/admin/medicinal?expired=2")
+        );
+        assert_eq!(
+            digest.text(),
+            "已过期 1, 30天内过期 1, 库存不足 1\n急救: 已过期 1, 库存不足 1\n手术室: 30天内过期 1\n查看: https://emacsvi.com:9998/admin/medicinal?expired=2"
+        );
+
+        let digest = Digest::new(&events[..1], 30, None);
+        assert_eq!(digest.summary(), "已过期 1");
+        assert_eq!(digest.link, None);
+    }
+}
@@ -0,0 +1,93 @@
+//! 通过 SMTP 发送邮件
+
+use crate::config::EmailConfig;
+use crate::error::{AppError, AppErrorType};
+use crate::notify::{Channel, Event};
+use crate::Result;
+use axum::async_trait;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+pub struct Email {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+fn to_notify_error(err: impl ToString) -> AppError {
+    AppError::from_err(err, AppErrorType::NotifyError)
+}
+
+impl Email {
+    /// 解析发件人和收件人, 创建 SMTP 连接池, 这时还不会连接服务器
+    pub fn new(cfg: &EmailConfig) -> Result<Self> {
+        let mut builder = match cfg.tls.as_str() {
+            "tls" => {
+                AsyncSmtpTransport::<Tokio1Executor>::relay(&cfg.host).map_err(to_notify_error)?
+            }
+            "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&cfg.host),
+            _ => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&cfg.host)
+                .map_err(to_notify_error)?,
+        };
+        if let Some(port) = cfg.port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (&cfg.username, &cfg.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        let from = cfg.from.parse::<Mailbox>().map_err(to_notify_error)?;
+        let to = cfg
+            .to
+            .iter()
+            .map(|to| to.parse::<Mailbox>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(to_notify_error)?;
+        Ok(Self {
+            mailer: builder.build(),
+            from,
+            to,
+        })
+    }
+}
+
+#[async_trait]
+impl Channel for Email {
+    fn name(&self) -> &str {
+        "邮件"
+    }
+
+    async fn recipients(&self) -> String {
+        self.to
+            .iter()
+            .map(|to| to.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// 返回 SMTP 服务器的响应, 如 `250 Ok: queued as ...`
+    async fn send(&self, event: &Event) -> Result<String> {
+        let mut builder = Message::builder().from(self.from.clone()).subject(format!(
+            "[药品提醒] {}: {}",
+            event.title(),
+            event
+                .item()
+                .map(|item| item.name.clone())
+                .unwrap_or_else(|| event.summary())
+        ));
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        let message = builder
+            .header(ContentType::TEXT_PLAIN)
+            .body(event.text())
+            .map_err(to_notify_error)?;
+        let response = self.mailer.send(message).await.map_err(to_notify_error)?;
+        Ok(format!(
+            "{} {}",
+            response.code(),
+            response.message().collect::<Vec<_>>().join(" ")
+        ))
+    }
+}
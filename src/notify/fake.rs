@@ -0,0 +1,58 @@
+//! 测试用的渠道, 只把通知记录在内存中
+
+use crate::error::{AppError, AppErrorType};
+use crate::notify::{Channel, Event};
+use crate::Result;
+use axum::async_trait;
+use std::sync::{Arc, Mutex};
+
+/// 克隆出来的渠道共用同一份记录, 交给 [`super::Notifier`] 之后还可以查看发送了什么
+#[derive(Clone, Default)]
+pub struct FakeChannel {
+    sent: Arc<Mutex<Vec<String>>>,
+    /// 发送时返回的错误
+    fail: Option<String>,
+}
+
+impl FakeChannel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 每次发送都失败
+    pub fn failing() -> Self {
+        Self::rejecting("发送失败")
+    }
+
+    /// 模拟服务商拒绝发送, 每次都返回 `response` 作为错误
+    pub fn rejecting(response: &str) -> Self {
+        Self {
+            fail: Some(response.to_string()),
+            ..Self::default()
+        }
+    }
+
+    /// 已经发送的通知正文
+    pub fn sent(&self) -> Vec<String> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Channel for FakeChannel {
+    fn name(&self) -> &str {
+        "测试"
+    }
+
+    async fn recipients(&self) -> String {
+        "测试".to_string()
+    }
+
+    async fn send(&self, event: &Event) -> Result<String> {
+        if let Some(response) = &self.fail {
+            return Err(AppError::from_str(response, AppErrorType::NotifyError));
+        }
+        self.sent.lock().unwrap().push(event.text());
+        Ok("ok".to_string())
+    }
+}
@@ -0,0 +1,367 @@
+//! 通知渠道: 短信、邮件和 webhook(钉钉、企业微信、飞书群机器人)
+//!
+//! 药品到期和库存不足的提醒发送到所有配置的渠道, 每个渠道一条消息写入发件箱,
+//! 某个渠道发送失败不影响其它渠道, 失败的消息由发件箱重试.
+
+pub mod digest;
+pub mod email;
+#[cfg(test)]
+pub(crate) mod fake;
+pub mod outbox;
+pub mod schedule;
+pub mod sms;
+pub mod webhook;
+
+use crate::error::{AppError, AppErrorType};
+use crate::model::{AppState, MedicinalList, OutboxMessage};
+use crate::notify::digest::Digest;
+use crate::notify::schedule::QuietHours;
+use crate::Result;
+use axum::async_trait;
+use chrono::DateTime;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// 需要通知的事件, 以 JSON 保存在发件箱中
+#[derive(Serialize, Deserialize)]
+pub enum Event {
+    /// 已经过期
+    Expired(MedicinalList),
+    /// 将在 `days` 天内过期
+    Expiring { item: MedicinalList, days: i64 },
+    /// 数量低于 `threshold`
+    LowStock { item: MedicinalList, threshold: i32 },
+    /// 一次检查中多个药品的汇总
+    Digest(Digest),
+}
+
+impl Event {
+    /// 事件对应的药品, 汇总时为空
+    pub fn item(&self) -> Option<&MedicinalList> {
+        match self {
+            Event::Expired(item) | Event::Expiring { item, .. } | Event::LowStock { item, .. } => {
+                Some(item)
+            }
+            Event::Digest(_) => None,
+        }
+    }
+
+    /// 汇总的内容, 不是汇总时为空
+    pub fn digest(&self) -> Option<&Digest> {
+        match self {
+            Event::Digest(digest) => Some(digest),
+            _ => None,
+        }
+    }
+
+    /// 通知了的药品
+    pub fn ids(&self) -> Vec<i32> {
+        match self {
+            Event::Digest(digest) => digest.ids.clone(),
+            _ => self.item().map(|item| vec![item.id]).unwrap_or_default(),
+        }
+    }
+
+    /// 事件类型, 通用 webhook 中使用, 也用于配置 `NOTIFY.CRITICAL`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Expired(_) => "expired",
+            Event::Expiring { .. } => "expiring",
+            Event::LowStock { .. } => "low_stock",
+            Event::Digest(_) => "digest",
+        }
+    }
+
+    /// 标题, 如 `即将过期(30天)药品`
+    pub fn title(&self) -> String {
+        match self {
+            Event::Expired(_) => "已经过期药品".to_string(),
+            Event::Expiring { days, .. } => format!("即将过期({}天)药品", days),
+            Event::LowStock { threshold, .. } => format!("库存不足(少于{})药品", threshold),
+            Event::Digest(_) => "药品提醒汇总".to_string(),
+        }
+    }
+
+    /// 一行的摘要, 用于日志和邮件标题
+    pub fn summary(&self) -> String {
+        match self {
+            Event::Digest(digest) => digest.summary(),
+            _ => self.item().map(|item| item.to_string()).unwrap_or_default(),
+        }
+    }
+
+    /// 正文, 邮件和 webhook 使用
+    pub fn text(&self) -> String {
+        let item = match self {
+            Event::Digest(digest) => return format!("{}: {}", self.title(), digest.text()),
+            Event::Expired(item) | Event::Expiring { item, .. } | Event::LowStock { item, .. } => {
+                item
+            }
+        };
+        format!(
+            "{}: {}\n批号: {}\n类目: {}\n规格: {}\n数量: {}\n有效期: {}",
+            self.title(),
+            item.name,
+            item.batch_number,
+            item.category,
+            item.spec,
+            item.count,
+            item.validity.format("%Y-%m-%d")
+        )
+    }
+}
+
+/// 通知渠道
+#[async_trait]
+pub trait Channel: Send + Sync + 'static {
+    /// 渠道名称, 用于日志和发送记录
+    fn name(&self) -> &str;
+
+    /// 接收人, 记录在发送记录中
+    async fn recipients(&self) -> String;
+
+    /// 发送通知, 返回服务商的响应, 返回错误表示发送失败
+    async fn send(&self, event: &Event) -> Result<String>;
+}
+
+/// 渠道和它的安静时段
+struct Entry {
+    /// 渠道的标识, 保存在发件箱中, 重试时用来找到渠道
+    key: String,
+    channel: Box<dyn Channel>,
+    quiet_hours: Option<QuietHours>,
+}
+
+/// 发送一条消息的结果
+#[derive(Debug, PartialEq)]
+pub enum Delivery {
+    /// 发送成功, 服务商的响应
+    Sent(String),
+    /// 发送失败, 失败原因
+    Failed(String),
+    /// 在安静时段内, 到这个时间再发送
+    Quiet(DateTime<Tz>),
+    /// 渠道已经从配置中删除
+    Missing,
+}
+
+/// 通知的所有渠道
+pub struct Notifier {
+    channels: Vec<Entry>,
+    /// 安静时段按这个时区计算
+    tz: Tz,
+}
+
+impl Notifier {
+    pub fn new(tz: Tz) -> Self {
+        Self {
+            channels: vec![],
+            tz,
+        }
+    }
+
+    /// 添加渠道, `quiet_hours` 内不通过这个渠道发送
+    ///
+    /// # 参数
+    ///
+    /// * `key` - 渠道的标识, 配置修改之后同一个渠道的标识应该不变
+    /// * `channel` - 渠道
+    /// * `quiet_hours` - 安静时段
+    pub fn push(&mut self, key: &str, channel: Box<dyn Channel>, quiet_hours: Option<QuietHours>) {
+        self.channels.push(Entry {
+            key: key.to_string(),
+            channel,
+            quiet_hours,
+        });
+    }
+
+    /// 根据配置创建渠道: 配置了 `SMS` 并且打开 `SMS.SEND_SMS_TOGGLE` 时发送短信,
+    /// 配置了 `NOTIFY.EMAIL` 时发送邮件, 每个 `NOTIFY.WEBHOOKS` 是一个渠道
+    pub fn from_state(state: &AppState) -> Result<Self> {
+        let cfg = &state.notify_cfg;
+        let plan = &state.plan;
+        let quiet_hours = |channel: Option<&String>| {
+            plan.quiet_hours_for(channel)
+                .map_err(|err| AppError::from_str(&err, AppErrorType::NotifyError))
+        };
+        let mut notifier = Self::new(plan.tz);
+        let sms_cfg = state.sms_cfg.as_ref();
+        if sms_cfg.and_then(|c| c.send_sms_toggle).unwrap_or(false) {
+            notifier.push(
+                "sms",
+                Box::new(sms::AliyunSms::new(state.pool.clone(), sms_cfg.cloned())),
+                quiet_hours(sms_cfg.and_then(|c| c.quiet_hours.as_ref()))?,
+            );
+        }
+        if let Some(email_cfg) = &cfg.email {
+            notifier.push(
+                "email",
+                Box::new(email::Email::new(email_cfg)?),
+                quiet_hours(email_cfg.quiet_hours.as_ref())?,
+            );
+        }
+        for (i, webhook_cfg) in cfg.webhooks.iter().enumerate() {
+            notifier.push(
+                &format!("webhook.{}", i),
+                Box::new(webhook::Webhook::new(webhook_cfg)),
+                quiet_hours(webhook_cfg.quiet_hours.as_ref())?,
+            );
+        }
+        info!("通知渠道: {:?}", notifier.names());
+        Ok(notifier)
+    }
+
+    /// 没有任何渠道
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// 所有渠道的名称, 带上安静时段
+    pub fn names(&self) -> Vec<String> {
+        self.channels
+            .iter()
+            .map(|e| match &e.quiet_hours {
+                Some(quiet_hours) => format!("{}(安静时段 {})", e.channel.name(), quiet_hours),
+                None => e.channel.name().to_string(),
+            })
+            .collect()
+    }
+
+    /// 安静时段所在时区的当前时间
+    pub fn now(&self) -> DateTime<Tz> {
+        chrono::Utc::now().with_timezone(&self.tz)
+    }
+
+    /// 事件发送到每个渠道的消息, 写入发件箱
+    pub async fn messages(&self, event: &Event) -> Result<Vec<OutboxMessage>> {
+        let payload = serde_json::to_string(event)?;
+        let mut messages = vec![];
+        for Entry { key, channel, .. } in &self.channels {
+            messages.push(OutboxMessage {
+                channel: key.clone(),
+                channel_name: channel.name().to_string(),
+                recipients: channel.recipients().await,
+                kind: event.kind().to_string(),
+                title: event.title(),
+                summary: event.summary(),
+                payload: payload.clone(),
+            });
+        }
+        Ok(messages)
+    }
+
+    /// 通过 `key` 对应的渠道发送, `now` 在渠道的安静时段内时不发送
+    pub async fn deliver(&self, key: &str, event: &Event, now: DateTime<Tz>) -> Delivery {
+        let entry = match self.channels.iter().find(|e| e.key == key) {
+            Some(entry) => entry,
+            None => return Delivery::Missing,
+        };
+        if let Some(quiet_hours) = entry.quiet_hours.filter(|q| q.contains(now.time())) {
+            info!(
+                "{}在安静时段 {} 内, 不发送通知",
+                entry.channel.name(),
+                quiet_hours
+            );
+            return Delivery::Quiet(quiet_hours.end_after(now));
+        }
+        match entry.channel.send(event).await {
+            Ok(response) => Delivery::Sent(response),
+            Err(err) => Delivery::Failed(err.message.or(err.cause).unwrap_or_default()),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::fake::FakeChannel;
+    use super::*;
+
+    pub(crate) fn item() -> MedicinalList {
+        MedicinalList {
+            id: 1,
+            category: "急救".to_string(),
+            name: "多巴胺".to_string(),
+            batch_number: "B2022".to_string(),
+            spec: "2ml:20mg".to_string(),
+            count: "3支".to_string(),
+            validity: chrono::NaiveDate::from_ymd(2022, 6, 1),
+            is_del: false,
+            created_at: None,
+            matched: None,
+        }
+    }
+
+    #[test]
+    fn test_event_text() {
+        let event = Event::Expiring {
+            item: item(),
+            days: 30,
+        };
+        assert_eq!(event.title(), "即将过期(30天)药品");
+        assert!(event
+            .text()
+            .starts_with("即将过期(30天)药品: 多巴胺\n批号: B2022"));
+        assert!(event.text().ends_with("有效期: 2022-06-01"));
+    }
+
+    #[tokio::test]
+    async fn test_messages() {
+        let mut notifier = Notifier::new(chrono_tz::Asia::Shanghai);
+        notifier.push("first", Box::new(FakeChannel::new()), None);
+        notifier.push("second", Box::new(FakeChannel::failing()), None);
+        let event = Event::LowStock {
+            item: item(),
+            threshold: 5,
+        };
+        let messages = notifier.messages(&event).await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].channel, "second");
+        assert_eq!(messages[0].channel_name, "测试");
+        assert_eq!(messages[0].kind, "low_stock");
+        assert_eq!(messages[0].title, "库存不足(少于5)药品");
+
+        // 保存的 JSON 可以还原成事件, 重试时使用
+        let saved: Event = serde_json::from_str(&messages[0].payload).unwrap();
+        assert_eq!(saved.text(), event.text());
+    }
+
+    #[tokio::test]
+    async fn test_deliver() {
+        use chrono::TimeZone;
+        use chrono_tz::Asia::Shanghai;
+
+        let day = FakeChannel::new();
+        let mut notifier = Notifier::new(Shanghai);
+        notifier.push(
+            "day",
+            Box::new(day.clone()),
+            QuietHours::parse("18:00-08:00").unwrap(),
+        );
+        notifier.push("failing", Box::new(FakeChannel::failing()), None);
+        let event = Event::Expired(item());
+
+        let night = Shanghai.ymd(2022, 6, 1).and_hms(22, 0, 0);
+        assert_eq!(
+            notifier.deliver("day", &event, night).await,
+            Delivery::Quiet(Shanghai.ymd(2022, 6, 2).and_hms(8, 0, 0))
+        );
+        assert!(day.sent().is_empty());
+
+        let noon = Shanghai.ymd(2022, 6, 1).and_hms(12, 0, 0);
+        assert_eq!(
+            notifier.deliver("day", &event, noon).await,
+            Delivery::Sent("ok".to_string())
+        );
+        assert_eq!(day.sent(), vec![event.text()]);
+        assert_eq!(
+            notifier.deliver("failing", &event, noon).await,
+            Delivery::Failed("发送失败".to_string())
+        );
+        assert_eq!(
+            notifier.deliver("webhook.0", &event, noon).await,
+            Delivery::Missing
+        );
+        assert_eq!(notifier.names(), vec!["测试(安静时段 18:00-08:00)", "测试"]);
+    }
+}
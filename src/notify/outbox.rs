@@ -0,0 +1,246 @@
+//! 通知发件箱: 消息先写入 `notify_outbox` 表再发送, 记录服务商的响应, 失败时按退避时间重试,
+//! 重试 `NOTIFY.MAX_ATTEMPTS` 次之后仍然失败的消息可以在后台的发送记录中重新发送.
+
+use crate::config::NotifyConfig;
+use crate::db::notify_outbox;
+use crate::model::{AppState, NotifyOutbox};
+use crate::notify::{Delivery, Event, Notifier};
+use crate::Result;
+use chrono::{DateTime, Local};
+use deadpool_postgres::Client;
+use std::sync::Arc;
+use tokio::time::{self, Duration};
+use tracing::{error, info};
+
+/// 检查到期消息的间隔, 单位秒
+const DISPATCH_INTERVAL: u64 = 30;
+/// 每次最多取出的消息数
+const BATCH_SIZE: i64 = 50;
+/// 重试间隔的上限, 单位秒
+const MAX_RETRY_DELAY: u64 = 3600;
+
+/// 第 `attempts` 次失败之后等待的时间: 从 `retry_delay` 秒开始每次翻倍, 最多 1 小时
+pub fn backoff(retry_delay: u64, attempts: i32) -> chrono::Duration {
+    let exp = (attempts.max(1) - 1).min(16) as u32;
+    let seconds = retry_delay
+        .saturating_mul(2u64.pow(exp))
+        .min(MAX_RETRY_DELAY);
+    chrono::Duration::seconds(seconds as i64)
+}
+
+/// 事件写入发件箱, 每个渠道一条, 在同一个事务中写入, 返回写入的条数
+pub async fn enqueue(client: &mut Client, notifier: &Notifier, event: &Event) -> Result<usize> {
+    let messages = notifier.messages(event).await?;
+    let tx = client.transaction().await?;
+    for msg in &messages {
+        notify_outbox::create(&tx, msg).await?;
+    }
+    tx.commit().await?;
+    Ok(messages.len())
+}
+
+/// 发送之后怎样更新消息
+#[derive(Debug, PartialEq)]
+enum Step {
+    /// 发送成功, 记录服务商的响应
+    Sent(String),
+    /// 发送失败, 放回去等到指定的时间重试
+    Retry(String, DateTime<Local>),
+    /// 发送失败, 不再重试
+    Fail(String),
+    /// 安静时段, 推迟到指定的时间
+    Defer(DateTime<Local>),
+}
+
+/// 根据发送的结果决定下一步: 失败时没有超过 `NOTIFY.MAX_ATTEMPTS` 就按退避时间重试
+///
+/// # 参数
+///
+/// * `cfg` - 通知的配置
+/// * `msg` - 取出的消息
+/// * `delivery` - 发送的结果
+/// * `now` - 当前时间
+fn next_step(
+    cfg: &NotifyConfig,
+    msg: &NotifyOutbox,
+    delivery: Delivery,
+    now: DateTime<Local>,
+) -> Step {
+    match delivery {
+        Delivery::Sent(response) => Step::Sent(response),
+        Delivery::Failed(reason) => {
+            let attempts = msg.attempts + 1;
+            if attempts < cfg.max_attempts {
+                Step::Retry(reason, now + backoff(cfg.retry_delay, attempts))
+            } else {
+                Step::Fail(reason)
+            }
+        }
+        Delivery::Quiet(until) => Step::Defer(until.with_timezone(&Local)),
+        Delivery::Missing => {
+            Step::Fail(format!("渠道 {} 已经不存在, 请检查通知的配置", msg.channel))
+        }
+    }
+}
+
+/// 发送一条取出的消息, 返回是否发送成功
+async fn send(
+    client: &Client,
+    cfg: &NotifyConfig,
+    notifier: &Notifier,
+    msg: NotifyOutbox,
+) -> Result<bool> {
+    let event: Event = match serde_json::from_str(&msg.payload) {
+        Ok(event) => event,
+        Err(err) => {
+            let reason = format!("消息内容无法解析: {}", err);
+            notify_outbox::failed(client, msg.id, &reason, None).await?;
+            return Ok(false);
+        }
+    };
+    let delivery = notifier.deliver(&msg.channel, &event, notifier.now()).await;
+    match next_step(cfg, &msg, delivery, Local::now()) {
+        Step::Sent(response) => {
+            info!("通过{}发送通知: {}", msg.channel_name, msg.summary);
+            notify_outbox::sent(client, msg.id, &response).await?;
+            return Ok(true);
+        }
+        Step::Retry(reason, retry_at) => {
+            error!(
+                "通过{}发送通知失败(第 {} 次), {} 重试: {}",
+                msg.channel_name,
+                msg.attempts + 1,
+                retry_at.format("%H:%M:%S"),
+                reason
+            );
+            notify_outbox::failed(client, msg.id, &reason, Some(retry_at)).await?;
+        }
+        Step::Fail(reason) => {
+            error!(
+                "通过{}发送通知失败(第 {} 次), 不再重试: {}",
+                msg.channel_name,
+                msg.attempts + 1,
+                reason
+            );
+            notify_outbox::failed(client, msg.id, &reason, None).await?;
+        }
+        Step::Defer(until) => {
+            notify_outbox::defer(client, msg.id, until).await?;
+        }
+    }
+    Ok(false)
+}
+
+/// 发送所有到期的消息, 返回发送成功的条数
+pub async fn dispatch(state: &AppState, notifier: &Notifier) -> Result<usize> {
+    let client = state.pool.get().await?;
+    notify_outbox::release(&client).await?;
+    let mut sent = 0;
+    loop {
+        let list = notify_outbox::claim_due(&client, BATCH_SIZE).await?;
+        if list.is_empty() {
+            break;
+        }
+        for msg in list {
+            if send(&client, &state.notify_cfg, notifier, msg).await? {
+                sent += 1;
+            }
+        }
+    }
+    Ok(sent)
+}
+
+/// 定时发送发件箱中到期的消息, 后台重新发送时被唤醒立即发送
+pub async fn run(state: Arc<AppState>, notifier: Arc<Notifier>) {
+    if notifier.is_empty() {
+        return;
+    }
+    loop {
+        if let Err(err) = dispatch(&state, &notifier).await {
+            error!("发送发件箱中的通知失败: {}", err);
+        }
+        tokio::select! {
+            _ = time::sleep(Duration::from_secs(DISPATCH_INTERVAL)) => {}
+            _ = state.outbox_wake.notified() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::fake::FakeChannel;
+    use crate::notify::tests::item;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(60, 1), chrono::Duration::seconds(60));
+        assert_eq!(backoff(60, 2), chrono::Duration::seconds(120));
+        assert_eq!(backoff(60, 4), chrono::Duration::seconds(480));
+        assert_eq!(backoff(60, 10), chrono::Duration::seconds(3600));
+        assert_eq!(backoff(60, i32::MAX), chrono::Duration::seconds(3600));
+    }
+
+    fn message(channel: &str, attempts: i32) -> NotifyOutbox {
+        NotifyOutbox {
+            id: 1,
+            channel: channel.to_string(),
+            channel_name: "测试".to_string(),
+            recipients: "测试".to_string(),
+            kind: "expired".to_string(),
+            title: "已过期药品".to_string(),
+            summary: "多巴胺".to_string(),
+            payload: String::new(),
+            status: NotifyOutbox::SENDING.to_string(),
+            attempts,
+            next_attempt_at: Local::now(),
+            response: String::new(),
+            created_at: Local::now(),
+            sent_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provider_error_should_retry() {
+        let mut notifier = Notifier::new(chrono_tz::Asia::Shanghai);
+        notifier.push(
+            "sms",
+            Box::new(FakeChannel::rejecting("isv.BUSINESS_LIMIT_CONTROL")),
+            None,
+        );
+        let cfg = NotifyConfig::default();
+        let event = Event::Expired(item());
+        let now = Local::now();
+
+        // 服务商拒绝发送, 没有超过最多尝试次数时按退避时间放回去重试
+        let delivery = notifier.deliver("sms", &event, notifier.now()).await;
+        assert_eq!(
+            next_step(&cfg, &message("sms", 1), delivery, now),
+            Step::Retry(
+                "isv.BUSINESS_LIMIT_CONTROL".to_string(),
+                now + chrono::Duration::seconds(120)
+            )
+        );
+
+        // 最后一次仍然失败, 不再重试
+        let delivery = notifier.deliver("sms", &event, notifier.now()).await;
+        assert_eq!(
+            next_step(&cfg, &message("sms", cfg.max_attempts - 1), delivery, now),
+            Step::Fail("isv.BUSINESS_LIMIT_CONTROL".to_string())
+        );
+
+        assert_eq!(
+            next_step(&cfg, &message("webhook.0", 0), Delivery::Missing, now),
+            Step::Fail("渠道 webhook.0 已经不存在, 请检查通知的配置".to_string())
+        );
+        assert_eq!(
+            next_step(
+                &cfg,
+                &message("sms", 0),
+                Delivery::Sent("OK".to_string()),
+                now
+            ),
+            Step::Sent("OK".to_string())
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! 定时检查的计划: cron 表达式或固定间隔, 按配置的时区计算, 可以跳过周末和节假日
+//!
+//! 安静时段按渠道配置, 安静时段内该渠道不发送, 没有单独配置的渠道使用 `SCHEDULE.QUIET_HOURS`.
+
+use crate::config::ScheduleConfig;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use std::fmt;
+use std::str::FromStr;
+
+/// 没有配置 cron 表达式和 `SMS.CHECK_INTERVAL` 时的检查间隔, 单位秒
+pub const DEFAULT_INTERVAL: u64 = 1200;
+/// 查找下一次检查时最多尝试的次数, 节假日配置得太多时不会一直找下去
+const MAX_TRIES: usize = 100_000;
+
+/// 安静时段, 如 `18:00-08:00`, 结束早于开始表示跨过午夜
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl QuietHours {
+    /// 解析 `HH:MM-HH:MM`, 为空表示没有安静时段
+    pub fn parse(s: &str) -> Result<Option<Self>, String> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Ok(None);
+        }
+        let time = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").ok();
+        let (start, end) = match s.split_once('-') {
+            Some((start, end)) => (time(start), time(end)),
+            None => (None, None),
+        };
+        match (start, end) {
+            (Some(start), Some(end)) if start != end => Ok(Some(Self { start, end })),
+            (Some(_), Some(_)) => Err(format!("安静时段的开始和结束不能相同: {}", s)),
+            _ => Err(format!("安静时段的格式应该是 18:00-08:00: {}", s)),
+        }
+    }
+
+    /// `time` 是否在安静时段内, 包含开始不包含结束
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// `now` 之后安静时段结束的时间
+    pub fn end_after(&self, now: DateTime<Tz>) -> DateTime<Tz> {
+        let local = now.naive_local();
+        let mut end = local.date().and_time(self.end);
+        if end <= local {
+            end += chrono::Duration::days(1);
+        }
+        now.timezone()
+            .from_local_datetime(&end)
+            .earliest()
+            .unwrap_or_else(|| now + chrono::Duration::hours(1))
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+enum Trigger {
+    Cron(cron::Schedule),
+    Every(chrono::Duration),
+}
+
+/// 检查的计划
+pub struct Plan {
+    trigger: Trigger,
+    /// 时区, cron 表达式、安静时段和节假日都按这个时区计算
+    pub tz: Tz,
+    /// 渠道没有单独配置时的安静时段
+    pub quiet_hours: Option<QuietHours>,
+    skip_weekends: bool,
+    holidays: Vec<NaiveDate>,
+    workdays: Vec<NaiveDate>,
+    /// 用于显示的说明
+    description: String,
+}
+
+/// cron 表达式, 五个字段(分 时 日 月 周)时补上秒
+fn parse_cron(expr: &str) -> Result<cron::Schedule, String> {
+    let expr = expr.trim();
+    let full = if expr.split_whitespace().count() == 5 {
+        format!("0 {}", expr)
+    } else {
+        expr.to_string()
+    };
+    cron::Schedule::from_str(&full)
+        .map_err(|err| format!("SCHEDULE.CRON 不是合法的 cron 表达式 {}: {}", expr, err))
+}
+
+/// 解析 `YYYY-MM-DD` 格式的日期列表
+fn parse_dates(name: &str, dates: &[String]) -> Result<Vec<NaiveDate>, String> {
+    dates
+        .iter()
+        .map(|d| {
+            NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d")
+                .map_err(|_| format!("{} 中的日期格式应该是 2022-10-01: {}", name, d))
+        })
+        .collect()
+}
+
+impl Plan {
+    /// 根据配置创建计划, 返回配置的问题
+    ///
+    /// # 参数
+    ///
+    /// * `cfg` - `SCHEDULE` 的配置
+    /// * `interval` - 没有配置 cron 表达式时的检查间隔(`SMS.CHECK_INTERVAL`), 单位秒
+    pub fn new(cfg: &ScheduleConfig, interval: Option<u64>) -> Result<Self, String> {
+        let tz: Tz = cfg
+            .timezone
+            .parse()
+            .map_err(|_| format!("SCHEDULE.TIMEZONE 不是合法的时区: {}", cfg.timezone))?;
+        let (trigger, mut description) = match &cfg.cron {
+            Some(expr) => (Trigger::Cron(parse_cron(expr)?), format!("cron {}", expr)),
+            None => {
+                let seconds = interval.unwrap_or(DEFAULT_INTERVAL);
+                if seconds == 0 {
+                    return Err("SMS.CHECK_INTERVAL 必须大于 0".to_string());
+                }
+                (
+                    Trigger::Every(chrono::Duration::seconds(seconds as i64)),
+                    format!("每 {} 秒", seconds),
+                )
+            }
+        };
+        let quiet_hours = QuietHours::parse(&cfg.quiet_hours)
+            .map_err(|err| format!("SCHEDULE.QUIET_HOURS {}", err))?;
+        let holidays = parse_dates("SCHEDULE.HOLIDAYS", &cfg.holidays)?;
+        let workdays = parse_dates("SCHEDULE.WORKDAYS", &cfg.workdays)?;
+
+        description.push_str(&format!(", 时区 {}", tz.name()));
+        if cfg.skip_weekends {
+            description.push_str(", 跳过周末");
+        }
+        if !holidays.is_empty() {
+            description.push_str(&format!(", 节假日 {} 天", holidays.len()));
+        }
+        if let Some(quiet_hours) = &quiet_hours {
+            description.push_str(&format!(", 安静时段 {}", quiet_hours));
+        }
+        Ok(Self {
+            trigger,
+            tz,
+            quiet_hours,
+            skip_weekends: cfg.skip_weekends,
+            holidays,
+            workdays,
+            description,
+        })
+    }
+
+    /// 渠道的安静时段, 渠道没有单独配置时使用 `SCHEDULE.QUIET_HOURS`, 配置为空表示没有安静时段
+    pub fn quiet_hours_for(&self, channel: Option<&String>) -> Result<Option<QuietHours>, String> {
+        match channel {
+            Some(s) => QuietHours::parse(s),
+            None => Ok(self.quiet_hours),
+        }
+    }
+
+    /// 配置时区的当前时间
+    pub fn now(&self) -> DateTime<Tz> {
+        Utc::now().with_timezone(&self.tz)
+    }
+
+    /// 配置时区的今天
+    pub fn today(&self) -> NaiveDate {
+        self.now().date().naive_local()
+    }
+
+    /// 是否是不检查的日子: 节假日, 或者开启 `SKIP_WEEKENDS` 时不用调休上班的周末
+    pub fn is_day_off(&self, date: NaiveDate) -> bool {
+        if self.holidays.contains(&date) {
+            return true;
+        }
+        self.skip_weekends
+            && matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+            && !self.workdays.contains(&date)
+    }
+
+    /// `after` 之后的下一次检查, 跳过不检查的日子, 找不到时为空
+    pub fn next_after(&self, after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let workday = |t: &DateTime<Tz>| !self.is_day_off(t.date().naive_local());
+        match &self.trigger {
+            Trigger::Cron(schedule) => schedule.after(&after).take(MAX_TRIES).find(workday),
+            Trigger::Every(interval) => {
+                let interval = *interval;
+                std::iter::successors(Some(after + interval), |t| Some(*t + interval))
+                    .take(MAX_TRIES)
+                    .find(workday)
+            }
+        }
+    }
+
+    /// 下一次检查
+    pub fn next(&self) -> Option<DateTime<Tz>> {
+        self.next_after(self.now())
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Asia::Shanghai;
+
+    fn config(cron: Option<&str>) -> ScheduleConfig {
+        ScheduleConfig {
+            cron: cron.map(|s| s.to_string()),
+            skip_weekends: true,
+            holidays: vec!["2022-10-03".to_string()],
+            workdays: vec!["2022-10-08".to_string()],
+            ..ScheduleConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_quiet_hours() {
+        let quiet = QuietHours::parse("18:00-08:00").unwrap().unwrap();
+        assert!(quiet.contains(NaiveTime::from_hms(22, 0, 0)));
+        assert!(quiet.contains(NaiveTime::from_hms(7, 59, 0)));
+        assert!(!quiet.contains(NaiveTime::from_hms(8, 0, 0)));
+        assert!(!quiet.contains(NaiveTime::from_hms(12, 0, 0)));
+        assert_eq!(quiet.to_string(), "18:00-08:00");
+        assert_eq!(
+            quiet.end_after(Shanghai.ymd(2022, 6, 1).and_hms(22, 0, 0)),
+            Shanghai.ymd(2022, 6, 2).and_hms(8, 0, 0)
+        );
+        assert_eq!(
+            quiet.end_after(Shanghai.ymd(2022, 6, 1).and_hms(7, 0, 0)),
+            Shanghai.ymd(2022, 6, 1).and_hms(8, 0, 0)
+        );
+
+        let lunch = QuietHours::parse("12:00-13:30").unwrap().unwrap();
+        assert!(lunch.contains(NaiveTime::from_hms(12, 30, 0)));
+        assert!(!lunch.contains(NaiveTime::from_hms(18, 0, 0)));
+
+        assert_eq!(QuietHours::parse(" ").unwrap(), None);
+        assert!(QuietHours::parse("25:00-08:00").is_err());
+        assert!(QuietHours::parse("18:00").is_err());
+        assert!(QuietHours::parse("08:00-08:00").is_err());
+    }
+
+    #[test]
+    fn test_next_cron() {
+        // 2022-09-30 是周五, 10-03 是节假日
+        let plan = Plan::new(&config(Some("30 9 * * Mon-Fri")), None).unwrap();
+        assert_eq!(plan.tz, Shanghai);
+        let next = plan.next_after(Shanghai.ymd(2022, 9, 30).and_hms(10, 0, 0));
+        assert_eq!(next, Some(Shanghai.ymd(2022, 10, 4).and_hms(9, 30, 0)));
+
+        let plan = Plan::new(&config(Some("0 0 8-17 * * *")), None).unwrap();
+        let next = plan.next_after(Shanghai.ymd(2022, 10, 7).and_hms(17, 0, 0));
+        assert_eq!(next, Some(Shanghai.ymd(2022, 10, 8).and_hms(8, 0, 0)));
+    }
+
+    #[test]
+    fn test_next_interval() {
+        let plan = Plan::new(&config(None), Some(1200)).unwrap();
+        let next = plan.next_after(Shanghai.ymd(2022, 10, 15).and_hms(10, 0, 0));
+        assert_eq!(next, Some(Shanghai.ymd(2022, 10, 17).and_hms(0, 0, 0)));
+        assert_eq!(
+            plan.to_string(),
+            "每 1200 秒, 时区 Asia/Shanghai, 跳过周末, 节假日 1 天, 安静时段 18:00-08:00"
+        );
+        assert_eq!(plan.quiet_hours_for(Some(&"".to_string())).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let invalid = |cfg: ScheduleConfig, interval| Plan::new(&cfg, interval).err().unwrap();
+        assert!(invalid(config(Some("every day")), None).contains("SCHEDULE.CRON"));
+        assert!(invalid(config(None), Some(0)).contains("SMS.CHECK_INTERVAL"));
+        let cfg = ScheduleConfig {
+            timezone: "Asia/Beijing".to_string(),
+            ..ScheduleConfig::default()
+        };
+        assert!(invalid(cfg, None).contains("SCHEDULE.TIMEZONE"));
+        let cfg = ScheduleConfig {
+            holidays: vec!["2022/10/01".to_string()],
+            ..ScheduleConfig::default()
+        };
+        assert!(invalid(cfg, None).contains("SCHEDULE.HOLIDAYS"));
+    }
+}
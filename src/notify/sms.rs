@@ -0,0 +1,240 @@
+//! 阿里云短信
+//!
+//! 密钥、签名、模板和接收号码来自配置 `SMS` 或者后台的短信设置, 后台保存的值优先.
+//! 每次发送前重新读取设置, 后台修改(如更换密钥)之后不用重启.
+
+use crate::config::{self, SmsConfig};
+use crate::db::sms_setting;
+use crate::error::{AppError, AppErrorType};
+use crate::model::SmsSetting;
+use crate::notify::{Channel, Event};
+use crate::Result;
+use axum::async_trait;
+use tracing::info;
+
+/// 后台保存的值优先, 为空时使用配置中的值
+fn pick(saved: String, cfg: Option<&String>) -> String {
+    if saved.is_empty() {
+        cfg.cloned().unwrap_or_default()
+    } else {
+        saved
+    }
+}
+
+/// 生效的短信设置
+pub fn effective(cfg: Option<&SmsConfig>, saved: Option<SmsSetting>) -> SmsSetting {
+    let saved = saved.unwrap_or_default();
+    SmsSetting {
+        access_key_id: pick(
+            saved.access_key_id,
+            cfg.and_then(|c| c.access_key_id.as_ref()),
+        ),
+        access_key_secret: pick(
+            saved.access_key_secret,
+            cfg.and_then(|c| c.access_key_secret.as_ref()),
+        ),
+        sign_name: pick(saved.sign_name, cfg.and_then(|c| c.sign_name.as_ref())),
+        template_code: pick(
+            saved.template_code,
+            cfg.and_then(|c| c.template_code.as_ref()),
+        ),
+        phones: if saved.phones.is_empty() {
+            cfg.and_then(|c| c.phones.clone()).unwrap_or_default()
+        } else {
+            saved.phones
+        },
+    }
+}
+
+impl SmsSetting {
+    /// 检查设置是否完整, 手机号码格式是否正确
+    pub fn check(&self) -> Result<()> {
+        let missing: Vec<&str> = [
+            (self.access_key_id.is_empty(), "AccessKey ID"),
+            (self.access_key_secret.is_empty(), "AccessKey Secret"),
+            (self.sign_name.is_empty(), "短信签名"),
+            (self.template_code.is_empty(), "短信模板"),
+            (self.phones.is_empty(), "接收号码"),
+        ]
+        .iter()
+        .filter(|(missing, _)| *missing)
+        .map(|(_, name)| *name)
+        .collect();
+        if !missing.is_empty() {
+            return Err(AppError::from_str(
+                &format!("短信设置不完整, 缺少: {}", missing.join(", ")),
+                AppErrorType::NotifyError,
+            ));
+        }
+        check_phones(&self.phones)
+    }
+}
+
+/// 检查手机号码的格式
+pub fn check_phones(phones: &[String]) -> Result<()> {
+    match phones.iter().find(|p| !config::is_phone(p)) {
+        Some(phone) => Err(AppError::invalid_argument(&format!(
+            "手机号码格式错误: {}",
+            phone
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// 阿里云在正常的响应中用 `Code` 表示业务结果(签名、模板不对, 超过频率限制等), 只有 `OK` 表示发送成功
+///
+/// # 参数
+///
+/// * `code` - 响应中的 `Code`
+/// * `response` - 完整的响应, 成功时记录下来, 失败时作为失败原因
+fn check_response(code: &str, response: String) -> Result<String> {
+    if code == "OK" {
+        Ok(response)
+    } else {
+        Err(AppError::from_str(&response, AppErrorType::NotifyError))
+    }
+}
+
+pub struct AliyunSms {
+    pool: deadpool_postgres::Pool,
+    cfg: Option<SmsConfig>,
+}
+
+impl AliyunSms {
+    pub fn new(pool: deadpool_postgres::Pool, cfg: Option<SmsConfig>) -> Self {
+        Self { pool, cfg }
+    }
+
+    /// 当前生效的设置
+    pub async fn setting(&self) -> Result<SmsSetting> {
+        let client = self.pool.get().await?;
+        let saved = sms_setting::find(&client).await?;
+        Ok(effective(self.cfg.as_ref(), saved))
+    }
+
+    /// 按设置发送短信, 返回服务商的响应, 服务商返回错误时响应作为失败原因
+    ///
+    /// # 参数
+    ///
+    /// * `setting` - 生效的短信设置
+    /// * `phones` - 接收的号码, 为空时发送到设置中的号码
+    /// * `param` - 短信模板的变量
+    pub async fn send_param(
+        setting: &SmsSetting,
+        phones: Vec<String>,
+        param: aliyun_sdk::SmsParam,
+    ) -> Result<String> {
+        setting.check()?;
+        let phones = if phones.is_empty() {
+            setting.phones.clone()
+        } else {
+            check_phones(&phones)?;
+            phones
+        };
+        info!(
+            "send sms request name(code) {}({})",
+            &param.name, &param.code
+        );
+        let sms_request = aliyun_sdk::SmsRequest {
+            phones,
+            sign_name: setting.sign_name.clone(),
+            template_code: setting.template_code.clone(),
+            out_id: Some("123".to_string()),
+            param,
+        };
+        let sms_client = aliyun_sdk::Client::new(
+            setting.access_key_id.clone(),
+            setting.access_key_secret.clone(),
+        );
+        let sms_response = sms_client
+            .send_sms(sms_request)
+            .await
+            .map_err(|err| AppError::from_err(format!("{:?}", err), AppErrorType::NotifyError))?;
+        info!("send sms response {:?}", sms_response);
+        check_response(&sms_response.code, format!("{:?}", sms_response))
+    }
+}
+
+#[async_trait]
+impl Channel for AliyunSms {
+    fn name(&self) -> &str {
+        "短信"
+    }
+
+    /// 设置中的接收号码
+    async fn recipients(&self) -> String {
+        match self.setting().await {
+            Ok(setting) => setting.phones.join(", "),
+            Err(_) => String::new(),
+        }
+    }
+
+    async fn send(&self, event: &Event) -> Result<String> {
+        // 短信模板只有 name 和 code 两个变量
+        let param = match (event.item(), event.digest()) {
+            (Some(item), _) => aliyun_sdk::SmsParam {
+                name: format!(
+                    "{}:{}-{}-{}",
+                    event.title(),
+                    &item.name,
+                    item.batch_number,
+                    item.category
+                ),
+                code: format!("有效期:{}", item.validity.format("%Y-%m-%d")),
+            },
+            (None, digest) => aliyun_sdk::SmsParam {
+                name: format!("{}:{}", event.title(), event.summary()),
+                code: digest
+                    .and_then(|d| d.link.clone())
+                    .unwrap_or_else(|| "详见后台".to_string()),
+            },
+        };
+        let setting = self.setting().await?;
+        Self::send_param(&setting, vec![], param).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective() {
+        let cfg = SmsConfig {
+            phones: Some(vec!["18280835550".to_string()]),
+            access_key_id: Some("cfg-id".to_string()),
+            access_key_secret: Some("cfg-secret".to_string()),
+            sign_name: Some("恒乐淘".to_string()),
+            ..SmsConfig::default()
+        };
+        let saved = SmsSetting {
+            access_key_id: "db-id".to_string(),
+            template_code: "SMS_235793799".to_string(),
+            ..SmsSetting::default()
+        };
+        let setting = effective(Some(&cfg), Some(saved));
+        assert_eq!(setting.access_key_id, "db-id");
+        assert_eq!(setting.access_key_secret, "cfg-secret");
+        assert_eq!(setting.template_code, "SMS_235793799");
+        assert_eq!(setting.phones, vec!["18280835550".to_string()]);
+        assert!(setting.check().is_ok());
+
+        let setting = effective(None, None);
+        assert!(setting.check().is_err());
+    }
+
+    #[test]
+    fn test_check_response() {
+        assert_eq!(check_response("OK", "ok".to_string()).unwrap(), "ok");
+        let err = check_response("isv.BUSINESS_LIMIT_CONTROL", "限流".to_string()).unwrap_err();
+        assert_eq!(err.message.as_deref(), Some("限流"));
+        assert!(check_response("isv.SMS_SIGNATURE_ILLEGAL", String::new()).is_err());
+    }
+
+    #[test]
+    fn test_check_phones() {
+        assert!(check_phones(&["18180815129".to_string()]).is_ok());
+        assert!(check_phones(&["1818081512".to_string()]).is_err());
+        assert!(check_phones(&["28180815129".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+//! 通过 HTTP POST 发送通知, 内置钉钉、企业微信和飞书群机器人的消息格式
+
+use crate::config::WebhookConfig;
+use crate::error::{AppError, AppErrorType};
+use crate::notify::{Channel, Event};
+use crate::Result;
+use axum::async_trait;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tracing::debug;
+
+#[derive(Debug, PartialEq)]
+enum Kind {
+    DingTalk,
+    WeCom,
+    Feishu,
+    Generic,
+}
+
+pub struct Webhook {
+    kind: Kind,
+    url: String,
+    secret: Option<String>,
+    client: reqwest::Client,
+}
+
+impl Webhook {
+    pub fn new(cfg: &WebhookConfig) -> Self {
+        let kind = match cfg.kind.as_str() {
+            "dingtalk" => Kind::DingTalk,
+            "wecom" => Kind::WeCom,
+            "feishu" => Kind::Feishu,
+            _ => Kind::Generic,
+        };
+        Self {
+            kind,
+            url: cfg.url.clone(),
+            secret: cfg.secret.clone(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// 请求的地址和内容, `now` 为当前的毫秒时间戳, 用于加签
+    fn request(&self, event: &Event, now: i64) -> (String, Value) {
+        let text = event.text();
+        match (&self.kind, &self.secret) {
+            (Kind::DingTalk, secret) => {
+                let mut url = self.url.clone();
+                if let Some(secret) = secret {
+                    // https://open.dingtalk.com/document/robots/customize-robot-security-settings
+                    let sign = sign(secret, &format!("{}\n{}", now, secret));
+                    let query = serde_urlencoded::to_string(&[
+                        ("timestamp", now.to_string()),
+                        ("sign", sign),
+                    ])
+                    .unwrap_or_default();
+                    url.push(if url.contains('?') { '&' } else { '?' });
+                    url.push_str(&query);
+                }
+                (url, json!({"msgtype": "text", "text": {"content": text}}))
+            }
+            (Kind::WeCom, _) => (
+                self.url.clone(),
+                json!({"msgtype": "text", "text": {"content": text}}),
+            ),
+            (Kind::Feishu, secret) => {
+                let mut body = json!({"msg_type": "text", "content": {"text": text}});
+                if let Some(secret) = secret {
+                    // https://open.feishu.cn/document/ukTMukTMukTM/ucTM5YjL3ETO24yNxkjN
+                    let timestamp = (now / 1000).to_string();
+                    body["timestamp"] = json!(timestamp);
+                    body["sign"] = json!(sign(&format!("{}\n{}", timestamp, secret), ""));
+                }
+                (self.url.clone(), body)
+            }
+            (Kind::Generic, _) => (
+                self.url.clone(),
+                json!({
+                    "event": event.kind(),
+                    "title": event.title(),
+                    "text": text,
+                    "item": event.item(),
+                    "digest": event.digest(),
+                }),
+            ),
+        }
+    }
+
+    fn kind_name(&self) -> &str {
+        match self.kind {
+            Kind::DingTalk => "钉钉",
+            Kind::WeCom => "企业微信",
+            Kind::Feishu => "飞书",
+            Kind::Generic => "webhook",
+        }
+    }
+}
+
+/// HmacSHA256 之后 base64 编码
+fn sign(key: &str, message: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(message.as_bytes());
+    base64::encode(mac.finalize().into_bytes())
+}
+
+/// 群机器人的接口在 HTTP 200 中用错误码表示失败: 钉钉和企业微信是 `errcode`, 飞书是 `code`
+fn check_response(body: &str) -> Result<()> {
+    let res: Value = match serde_json::from_str(body) {
+        Ok(res) => res,
+        Err(_) => return Ok(()),
+    };
+    let code = res
+        .get("errcode")
+        .or_else(|| res.get("code"))
+        .or_else(|| res.get("StatusCode"))
+        .and_then(|v| v.as_i64())
+        .unwrap_or(0);
+    if code != 0 {
+        return Err(AppError::from_err(body, AppErrorType::NotifyError));
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Channel for Webhook {
+    fn name(&self) -> &str {
+        self.kind_name()
+    }
+
+    /// webhook 的主机名, 地址中的 token 不显示
+    async fn recipients(&self) -> String {
+        reqwest::Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .unwrap_or_default()
+    }
+
+    async fn send(&self, event: &Event) -> Result<String> {
+        let (url, body) = self.request(event, chrono::Local::now().timestamp_millis());
+        let res = self
+            .client
+            .post(url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|err| AppError::from_err(err, AppErrorType::NotifyError))?;
+        let status = res.status();
+        let text = res
+            .text()
+            .await
+            .map_err(|err| AppError::from_err(err, AppErrorType::NotifyError))?;
+        debug!("{} webhook response: {} {}", self.kind_name(), status, text);
+        if !status.is_success() {
+            return Err(AppError::from_err(
+                format!("{} {}", status, text),
+                AppErrorType::NotifyError,
+            ));
+        }
+        check_response(&text)?;
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::tests::item;
+
+    fn webhook(kind: &str, url: &str, secret: Option<&str>) -> Webhook {
+        Webhook::new(&WebhookConfig {
+            kind: kind.to_string(),
+            url: url.to_string(),
+            secret: secret.map(|s| s.to_string()),
+            quiet_hours: None,
+        })
+    }
+
+    #[test]
+    fn test_request() {
+        let event = Event::Expired(item());
+
+        let (url, body) = webhook(
+            "wecom",
+            "https://qyapi.weixin.qq.com/cgi-bin/webhook/send?key=k",
+            None,
+        )
+        .request(&event, 1654041600000);
+        assert_eq!(
+            url,
+            "https://qyapi.weixin.qq.com/cgi-bin/webhook/send?key=k"
+        );
+        assert_eq!(body["msgtype"], "text");
+        assert_eq!(body["text"]["content"], event.text());
+
+        let (url, _) = webhook(
+            "dingtalk",
+            "https://oapi.dingtalk.com/robot/send?access_token=t",
+            Some("SEC123"),
+        )
+        .request(&event, 1654041600000);
+        assert!(url.starts_with(
+            "https://oapi.dingtalk.com/robot/send?access_token=t&timestamp=1654041600000&sign="
+        ));
+
+        let (_, body) = webhook(
+            "feishu",
+            "https://open.feishu.cn/open-apis/bot/v2/hook/h",
+            Some("s"),
+        )
+        .request(&event, 1654041600000);
+        assert_eq!(body["timestamp"], "1654041600");
+        assert_eq!(body["content"]["text"], event.text());
+        assert!(body["sign"].as_str().unwrap().ends_with('='));
+
+        let (_, body) = webhook("generic", "http://127.0.0.1/alert", None).request(&event, 0);
+        assert_eq!(body["event"], "expired");
+        assert_eq!(body["item"]["name"], "多巴胺");
+    }
+
+    #[test]
+    fn test_check_response() {
+        assert!(check_response(r#"{"errcode":0,"errmsg":"ok"}"#).is_ok());
+        assert!(check_response(r#"{"errcode":310000,"errmsg":"sign not match"}"#).is_err());
+        assert!(check_response(r#"{"code":19021,"msg":"sign match fail"}"#).is_err());
+        assert!(check_response("ok").is_ok());
+    }
+}
@@ -1,5 +1,11 @@
 use crate::error::AppError;
 use crate::Result;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+/// 临时密码的长度
+const TEMPORARY_LEN: usize = 10;
+
 pub fn hash(pwd: &str) -> Result<String> {
     bcrypt::hash(pwd, bcrypt::DEFAULT_COST).map_err(AppError::from)
 }
@@ -8,6 +14,15 @@ pub fn verify(pwd: &str, hashed_pwd: &str) -> Result<bool> {
     bcrypt::verify(pwd, hashed_pwd).map_err(AppError::from)
 }
 
+/// 生成管理员重置账号时使用的临时密码
+pub fn temporary() -> String {
+    thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(TEMPORARY_LEN)
+        .map(char::from)
+        .collect()
+}
+
 mod tests {
     use super::*;
 
@@ -26,4 +41,12 @@ mod tests {
         let is_match = verify(pwd, hashed_pwd).unwrap();
         assert!(is_match);
     }
+
+    #[test]
+    fn test_temporary() {
+        let pwd = temporary();
+        assert_eq!(pwd.len(), TEMPORARY_LEN);
+        assert!(pwd.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(pwd, temporary());
+    }
 }
@@ -0,0 +1,322 @@
+//! 基于角色的权限控制
+//!
+//! 每个管理员有一个角色, 角色决定了能执行哪些操作; 另外可以把管理员限定在某些类目(药箱)上,
+//! 没有限定类目的管理员可以操作所有类目.
+
+use crate::error::AppError;
+use crate::model::{AdminSession, TokenIdentity};
+use crate::token::TokenScope;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// 角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// 只能查看
+    Viewer,
+    /// 日常录入: 查看、编辑、导入
+    Operator,
+    /// 药师: 除账号管理以外的所有操作
+    Pharmacist,
+    /// 管理员: 所有操作
+    Administrator,
+    /// 审计: 查看和导出
+    Auditor,
+}
+
+/// 操作权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    View,
+    Edit,
+    Delete,
+    Import,
+    Export,
+    ManageUsers,
+}
+
+impl Role {
+    /// 所有角色, 用于页面上的下拉选择
+    pub fn all() -> Vec<Role> {
+        vec![
+            Role::Viewer,
+            Role::Operator,
+            Role::Pharmacist,
+            Role::Administrator,
+            Role::Auditor,
+        ]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Pharmacist => "pharmacist",
+            Role::Administrator => "administrator",
+            Role::Auditor => "auditor",
+        }
+    }
+
+    /// 角色的中文名称
+    pub fn label(&self) -> &'static str {
+        match self {
+            Role::Viewer => "查看",
+            Role::Operator => "操作员",
+            Role::Pharmacist => "药师",
+            Role::Administrator => "管理员",
+            Role::Auditor => "审计",
+        }
+    }
+
+    /// 角色拥有的权限
+    pub fn permissions(&self) -> &'static [Permission] {
+        use Permission::*;
+        match self {
+            Role::Viewer => &[View],
+            Role::Operator => &[View, Edit, Import],
+            Role::Pharmacist => &[View, Edit, Delete, Import, Export],
+            Role::Administrator => &[View, Edit, Delete, Import, Export, ManageUsers],
+            Role::Auditor => &[View, Export],
+        }
+    }
+
+    pub fn has(&self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+impl Default for Role {
+    fn default() -> Self {
+        Role::Viewer
+    }
+}
+
+/// 解析表单、命令行等外部输入的角色, 不认识的角色返回错误
+impl FromStr for Role {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Role::all()
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or_else(|| AppError::invalid_argument(&format!("无效的角色: {}", s)))
+    }
+}
+
+/// 读取已保存的角色, 不认识的角色按只能查看处理
+impl From<&str> for Role {
+    fn from(s: &str) -> Self {
+        s.parse().unwrap_or_default()
+    }
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::View => "view",
+            Permission::Edit => "edit",
+            Permission::Delete => "delete",
+            Permission::Import => "import",
+            Permission::Export => "export",
+            Permission::ManageUsers => "manage_users",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "view" => Some(Permission::View),
+            "edit" => Some(Permission::Edit),
+            "delete" => Some(Permission::Delete),
+            "import" => Some(Permission::Import),
+            "export" => Some(Permission::Export),
+            "manage_users" => Some(Permission::ManageUsers),
+            _ => None,
+        }
+    }
+
+    /// 是否为只读的权限, 只读的 API token 只能拥有这些权限
+    fn is_read_only(&self) -> bool {
+        matches!(self, Permission::View | Permission::Export)
+    }
+}
+
+/// 当前登录的管理员, 由 [`crate::middleware::admin_auth::Auth`] 放入请求的 extensions 中
+#[derive(Debug, Clone)]
+pub struct CurrentAdmin {
+    pub id: i32,
+    pub username: String,
+    pub role: Role,
+    /// 允许操作的类目, 为空表示不限制
+    pub categories: Vec<String>,
+    /// 是否通过只读的 API token 认证
+    pub read_only: bool,
+}
+
+impl CurrentAdmin {
+    pub fn can_do(&self, permission: Permission) -> bool {
+        if self.read_only && !permission.is_read_only() {
+            return false;
+        }
+        self.role.has(permission)
+    }
+
+    /// 供模板使用, 例如 `current.can("delete")`
+    pub fn can(&self, permission: &str) -> bool {
+        Permission::from_str(permission)
+            .map(|p| self.can_do(p))
+            .unwrap_or(false)
+    }
+
+    /// 要求拥有某个权限
+    pub fn require(&self, permission: Permission) -> Result<()> {
+        if self.can_do(permission) {
+            Ok(())
+        } else {
+            Err(AppError::forbidden(&format!(
+                "当前账号({})没有 {} 权限",
+                self.role.label(),
+                permission.as_str()
+            )))
+        }
+    }
+
+    /// 是否可以操作某个类目
+    pub fn can_access_category(&self, category: &str) -> bool {
+        self.categories.is_empty() || self.categories.iter().any(|c| c == category)
+    }
+
+    /// 要求拥有某个权限并且可以操作指定的类目
+    pub fn require_category(&self, permission: Permission, category: &str) -> Result<()> {
+        self.require(permission)?;
+        if self.can_access_category(category) {
+            Ok(())
+        } else {
+            Err(AppError::forbidden(&format!("没有操作类目'{}'的权限", category)))
+        }
+    }
+
+    /// 类目限制是否生效
+    pub fn is_category_scoped(&self) -> bool {
+        !self.categories.is_empty()
+    }
+}
+
+impl From<AdminSession> for CurrentAdmin {
+    fn from(sess: AdminSession) -> Self {
+        Self {
+            id: sess.id,
+            username: sess.username,
+            // 旧的 session 中没有角色, 系统账号默认为管理员
+            role: if sess.role.is_empty() && sess.is_sys {
+                Role::Administrator
+            } else {
+                Role::from(sess.role.as_str())
+            },
+            categories: sess.categories,
+            read_only: false,
+        }
+    }
+}
+
+impl CurrentAdmin {
+    /// 由 API token 的身份构建, `categories` 为该管理员限定的类目
+    pub fn from_token(identity: TokenIdentity, categories: Vec<String>) -> Self {
+        Self {
+            id: identity.admin_id,
+            username: identity.username,
+            role: Role::from(identity.role.as_str()),
+            categories,
+            read_only: TokenScope::from(identity.scope.as_str()) == TokenScope::Read,
+        }
+    }
+}
+
+/// 解析以逗号(或中文逗号、换行)分隔的类目列表
+pub fn parse_categories(s: &str) -> Vec<String> {
+    let mut categories: Vec<String> = s
+        .split(|c| c == ',' || c == '，' || c == '\n')
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect();
+    categories.sort();
+    categories.dedup();
+    categories
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admin(role: Role, categories: Vec<&str>, read_only: bool) -> CurrentAdmin {
+        CurrentAdmin {
+            id: 1,
+            username: "test".to_string(),
+            role,
+            categories: categories.into_iter().map(|c| c.to_string()).collect(),
+            read_only,
+        }
+    }
+
+    #[test]
+    fn role_permissions_should_work() {
+        assert!(Role::Viewer.has(Permission::View));
+        assert!(!Role::Viewer.has(Permission::Edit));
+        assert!(Role::Operator.has(Permission::Import));
+        assert!(!Role::Operator.has(Permission::Delete));
+        assert!(Role::Pharmacist.has(Permission::Delete));
+        assert!(!Role::Pharmacist.has(Permission::ManageUsers));
+        assert!(Role::Administrator.has(Permission::ManageUsers));
+        assert!(Role::Auditor.has(Permission::Export));
+        assert!(!Role::Auditor.has(Permission::Edit));
+    }
+
+    #[test]
+    fn role_should_round_trip() {
+        for role in Role::all() {
+            assert_eq!(Role::from(role.as_str()), role);
+        }
+        assert_eq!(Role::from("unknown"), Role::Viewer);
+        assert_eq!(Role::from(""), Role::Viewer);
+        assert_eq!(Role::default(), Role::Viewer);
+        assert!("Operator".parse::<Role>().is_err());
+        assert_eq!("pharmacist".parse::<Role>().unwrap(), Role::Pharmacist);
+    }
+
+    #[test]
+    fn read_only_token_should_drop_write_permissions() {
+        let current = admin(Role::Administrator, vec![], true);
+        assert!(current.can_do(Permission::View));
+        assert!(current.can_do(Permission::Export));
+        assert!(!current.can_do(Permission::Edit));
+        assert!(current.require(Permission::Delete).is_err());
+    }
+
+    #[test]
+    fn category_scope_should_work() {
+        let current = admin(Role::Pharmacist, vec!["急救箱"], false);
+        assert!(current.require_category(Permission::Edit, "急救箱").is_ok());
+        assert!(current.require_category(Permission::Edit, "手术室").is_err());
+
+        let current = admin(Role::Pharmacist, vec![], false);
+        assert!(current.require_category(Permission::Edit, "手术室").is_ok());
+    }
+
+    #[test]
+    fn template_can_should_parse_permission() {
+        let current = admin(Role::Operator, vec![], false);
+        assert!(current.can("edit"));
+        assert!(!current.can("delete"));
+        assert!(!current.can("no_such_permission"));
+    }
+
+    #[test]
+    fn parse_categories_should_split_and_dedup() {
+        assert_eq!(
+            parse_categories("急救箱, 手术室，急救箱\n"),
+            vec!["急救箱".to_string(), "手术室".to_string()]
+        );
+        assert!(parse_categories(" ").is_empty());
+    }
+}
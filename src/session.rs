@@ -1,4 +1,7 @@
 use crate::config::SessionConfig;
+use crate::model::{ActiveSession, AdminSession};
+use crate::cache::Cache;
+use crate::Result;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use tower_cookies::Cookies;
@@ -8,6 +11,169 @@ pub fn gen_redis_key(cfg: &SessionConfig, id: &str) -> String {
     format!("{}{}", &cfg.prefix, id)
 }
 
+/// 记录某个管理员所有 session id 的集合, 用于一次性注销该管理员的所有登录
+pub fn gen_admin_index_key(cfg: &SessionConfig, admin_id: i32) -> String {
+    format!("{}admin:{}", &cfg.prefix, admin_id)
+}
+
+/// 把新登录的 session 记录到管理员的索引中
+pub async fn track(
+    cache: &dyn Cache,
+    cfg: &SessionConfig,
+    admin_id: i32,
+    id: &str,
+) -> Result<()> {
+    let index_key = gen_admin_index_key(cfg, admin_id);
+    cache.sadd(&index_key, id, cfg.expired).await
+}
+
+/// 退出登录时从管理员的索引中移除
+pub async fn untrack(
+    cache: &dyn Cache,
+    cfg: &SessionConfig,
+    admin_id: i32,
+    id: &str,
+) -> Result<()> {
+    let index_key = gen_admin_index_key(cfg, admin_id);
+    cache.srem(&index_key, id).await
+}
+
+/// 注销管理员所有的登录, 修改密码、禁用或删除账号时使用
+pub async fn revoke_admin(
+    cache: &dyn Cache,
+    cfg: &SessionConfig,
+    admin_id: i32,
+) -> Result<()> {
+    let index_key = gen_admin_index_key(cfg, admin_id);
+    for id in cache.smembers(&index_key).await? {
+        cache.del(&gen_redis_key(cfg, &id)).await?;
+    }
+    cache.del(&index_key).await?;
+    Ok(())
+}
+
+/// 距离上次刷新超过这个秒数才更新最后访问时间, 避免每个请求都写缓存
+const TOUCH_INTERVAL: i64 = 60;
+
+/// session 在缓存中的生存时间: 空闲超时和剩余的最长有效期中较小的一个
+///
+/// `idle_timeout` 为 0 时不启用空闲超时, 一直有效到最长有效期
+fn ttl_for(cfg: &SessionConfig, dateline: i64, now: i64) -> usize {
+    let remaining = (dateline - now).max(1) as usize;
+    if cfg.idle_timeout == 0 {
+        remaining
+    } else {
+        remaining.min(cfg.idle_timeout)
+    }
+}
+
+async fn save(
+    cache: &dyn Cache,
+    cfg: &SessionConfig,
+    id: &str,
+    sess: &AdminSession,
+    now: i64,
+) -> Result<()> {
+    let data = serde_json::to_string(sess)?;
+    let ttl = ttl_for(cfg, sess.dateline as i64, now);
+    cache.set_ex(&gen_redis_key(cfg, id), &data, ttl).await
+}
+
+/// 保存新登录的 session 并记录到管理员的索引中, 返回 session id
+pub async fn create(
+    cache: &dyn Cache,
+    cfg: &SessionConfig,
+    sess: &AdminSession,
+) -> Result<String> {
+    let id = id();
+    save(cache, cfg, &id, sess, sess.created_at).await?;
+    track(cache, cfg, sess.id, &id).await?;
+    Ok(id)
+}
+
+/// 读取 session, 超过最长有效期的直接删除; 有访问时延长空闲超时
+pub async fn load(
+    cache: &dyn Cache,
+    cfg: &SessionConfig,
+    id: &str,
+) -> Result<Option<AdminSession>> {
+    let data = match cache.get_str(&gen_redis_key(cfg, id)).await? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    let mut sess: AdminSession = serde_json::from_str(&data)?;
+    let now = chrono::Local::now().timestamp();
+    if sess.dateline as i64 <= now {
+        cache.del(&gen_redis_key(cfg, id)).await?;
+        untrack(cache, cfg, sess.id, id).await?;
+        return Ok(None);
+    }
+    if now - sess.last_seen >= TOUCH_INTERVAL {
+        sess.last_seen = now;
+        save(cache, cfg, id, &sess, now).await?;
+    }
+    Ok(Some(sess))
+}
+
+/// 管理员当前所有的登录, 按最后访问时间倒序; 已经过期的顺便从索引中移除
+pub async fn list_admin(
+    cache: &dyn Cache,
+    cfg: &SessionConfig,
+    admin_id: i32,
+    current_id: &str,
+) -> Result<Vec<ActiveSession>> {
+    let index_key = gen_admin_index_key(cfg, admin_id);
+    let mut list = vec![];
+    for id in cache.smembers(&index_key).await? {
+        let data = cache.get_str(&gen_redis_key(cfg, &id)).await?;
+        match data.and_then(|data| serde_json::from_str::<AdminSession>(&data).ok()) {
+            Some(sess) => list.push(ActiveSession {
+                is_current: id == current_id,
+                id,
+                data: sess,
+            }),
+            None => cache.srem(&index_key, &id).await?,
+        }
+    }
+    list.sort_by(|a, b| b.data.last_seen.cmp(&a.data.last_seen));
+    Ok(list)
+}
+
+/// 注销管理员的某一个登录, 不属于该管理员的 session 返回 false
+pub async fn revoke(
+    cache: &dyn Cache,
+    cfg: &SessionConfig,
+    admin_id: i32,
+    id: &str,
+) -> Result<bool> {
+    let index_key = gen_admin_index_key(cfg, admin_id);
+    if !cache.smembers(&index_key).await?.iter().any(|s| s == id) {
+        return Ok(false);
+    }
+    cache.del(&gen_redis_key(cfg, id)).await?;
+    cache.srem(&index_key, id).await?;
+    Ok(true)
+}
+
+/// 生成 `Set-Cookie` 的值, 禁止跨站请求携带
+///
+/// * `http_only` - 是否禁止脚本读取, session cookie 必须禁止
+pub fn cookie(cfg: &SessionConfig, name: &str, value: &str, http_only: bool) -> String {
+    let mut cookie = format!("{}={}; Path=/; SameSite=Lax", name, value);
+    if http_only {
+        cookie.push_str("; HttpOnly");
+    }
+    if cfg.secure {
+        cookie.push_str("; Secure");
+    }
+    cookie
+}
+
+/// 删除 cookie 的 `Set-Cookie` 值
+pub fn removal_cookie(cfg: &SessionConfig, name: &str) -> String {
+    format!("{}; Max-Age=0", cookie(cfg, name, "", true))
+}
+
 pub struct GeneratedKey {
     pub id: String,
     pub cookie_key: String,
@@ -65,7 +231,47 @@ pub struct CookieKV {
     pub value: String,
 }
 
+#[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn cfg(idle_timeout: usize) -> SessionConfig {
+        SessionConfig {
+            prefix: "strangers:session".to_string(),
+            id_name: "strangers_session".to_string(),
+            expired: 86400,
+            idle_timeout,
+            secure: false,
+        }
+    }
+
+    #[test]
+    fn test_ttl_for() {
+        // 空闲超时小于剩余有效期
+        assert_eq!(ttl_for(&cfg(7200), 100_000, 10_000), 7200);
+        // 快到最长有效期时, 不能超过剩余的时间
+        assert_eq!(ttl_for(&cfg(7200), 10_100, 10_000), 100);
+        // 不启用空闲超时
+        assert_eq!(ttl_for(&cfg(0), 100_000, 10_000), 90_000);
+        // 已经过期的至少给 1 秒, redis 不接受 0
+        assert_eq!(ttl_for(&cfg(7200), 9_000, 10_000), 1);
+    }
+
+    #[test]
+    fn test_cookie() {
+        let mut cfg = cfg(0);
+        assert_eq!(
+            cookie(&cfg, "sid", "abc", true),
+            "sid=abc; Path=/; SameSite=Lax; HttpOnly"
+        );
+        cfg.secure = true;
+        assert_eq!(
+            cookie(&cfg, "sid_csrf", "abc", false),
+            "sid_csrf=abc; Path=/; SameSite=Lax; Secure"
+        );
+        assert!(removal_cookie(&cfg, "sid").ends_with("; Max-Age=0"));
+    }
+
     #[test]
     fn test_regex_rule() {
         let prefix = "strangers_session";
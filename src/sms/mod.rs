@@ -1,2 +1 @@
-pub mod send;
 pub mod sms;
@@ -1,78 +1,131 @@
+use crate::config::NotifyConfig;
 use crate::db::medicinal;
+use crate::db::query::param;
+use crate::db::query::Filter;
 use crate::handler::helper::get_client;
 use crate::model::{AppState, MedicinalList};
-use crate::sms::send::NotifySms;
+use crate::notify::digest::Digest;
+use crate::notify::{outbox, Event, Notifier};
 use crate::Result;
-use chrono::Timelike;
+use deadpool_postgres::Client;
 use std::sync::Arc;
 use tokio::time;
-use tokio::time::{Duration, Instant};
-use tokio_postgres::Client;
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, warn};
 
 pub const EXPIRED_DAYS: i64 = 30;
-const INTERVAL_SECONDS: u64 = 1200;
-const INTERVAL_SCHEDULE_SECONDS: u64 = 120;
 
-// 利用tokio后台启动一个定期任务
-// 定期扫描medicinal表之中没有被删除,并且已经过期或者即将过期的药品,并且发送短信通知
-// 定期扫描,每天发送短信,每天早上9:30做一次
-pub async fn do_work(state: Arc<AppState>, notify_state: Arc<NotifySms>) -> Result<()> {
-    let client = get_client(&state, "定时任务:短信发送模块").await?;
-    // 查询已经过期的药品,马上发送短信
-    let condition = format!(
-        "is_del=false AND validity <= '{}' AND notify_at <= '{}'",
-        chrono::Local::now().format("%Y-%m-%d").to_string(),
-        chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-    );
-    debug!("condition: {}", condition);
-    let expired_medicinal = medicinal::all(&client, &condition, &[]).await?;
-    let mut ids = Vec::new();
-    for item in &expired_medicinal {
-        warn!("药品已经过期,发送短信通知: {}", item);
-        send(item, notify_state.clone(), "已经过期药品").await;
-        ids.push(item.id.to_string());
-    }
+/// 数量(`count` 开头的数字)低于阈值, 没有数字的不算
+const LOW_STOCK: &str =
+    r"COALESCE(substring(count from '^\s*(\d{1,18})')::bigint, 9223372036854775807) < ?";
 
-    if ids.len() > 0 {
-        // 更新notify_at时间
-        match update(&client, ids).await {
-            Ok(_) => {}
-            Err(e) => {
-                error!("更新notify_at时间失败: {}", e);
-            }
+/// 到了通知时间的药品
+pub struct Due {
+    /// 已经过期的药品
+    pub expired: Vec<MedicinalList>,
+    /// 即将过期的药品
+    pub expiring: Vec<MedicinalList>,
+    /// 多少天内过期算即将过期
+    pub expiring_days: i64,
+    /// 库存不足的药品, 已经过期或者即将过期的不重复提醒
+    pub low_stock: Vec<MedicinalList>,
+    /// 数量低于这个值算库存不足, 没有配置时不检查库存
+    pub low_stock_threshold: Option<i32>,
+}
+
+impl Due {
+    /// 需要发送的通知, 汇总方式时除了 `NOTIFY.CRITICAL` 中的事件, 其它的合并为一条汇总
+    pub fn into_events(self, cfg: &NotifyConfig) -> Vec<Event> {
+        let days = self.expiring_days;
+        let mut events: Vec<Event> = self.expired.into_iter().map(Event::Expired).collect();
+        events.extend(
+            self.expiring
+                .into_iter()
+                .map(|item| Event::Expiring { item, days }),
+        );
+        if let Some(threshold) = self.low_stock_threshold {
+            events.extend(
+                self.low_stock
+                    .into_iter()
+                    .map(|item| Event::LowStock { item, threshold }),
+            );
         }
+        if cfg.mode != "digest" {
+            return events;
+        }
+        let (mut critical, rest): (Vec<Event>, Vec<Event>) = events
+            .into_iter()
+            .partition(|e| cfg.critical.iter().any(|kind| kind == e.kind()));
+        if !rest.is_empty() {
+            critical.push(Event::Digest(Digest::new(
+                &rest,
+                days,
+                cfg.base_url.as_deref(),
+            )));
+        }
+        critical
     }
+}
+
+/// 查询到了通知时间的药品: 没有被删除, 已经过期、即将过期或者库存不足, 并且 `notify_at` 已经到了
+pub async fn due(state: &AppState, client: &Client) -> Result<Due> {
+    let now = chrono::Local::now();
+    let today = state.plan.today();
+    let filter = Filter::eq("is_del", false)
+        .and(Filter::le("validity", today))
+        .and(Filter::le("notify_at", now));
+    let expired = medicinal::all(client, filter).await?;
 
-    // 查询即将过期的药品
-    let expired_days = **&state
+    // 查询即将过期的药品, 今天过期的已经算在过期的药品中
+    let expiring_days = state
         .sms_cfg
         .as_ref()
-        .unwrap()
-        .expired_days
-        .as_ref()
-        .unwrap_or(&EXPIRED_DAYS);
-    let condition = format!(
-        "is_del=false AND notify_at <= '{}' AND validity BETWEEN '{}' AND '{}'",
-        chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-        chrono::Local::now().format("%Y-%m-%d").to_string(),
-        chrono::Local::now()
-            .checked_add_signed(chrono::Duration::days(expired_days))
-            .unwrap()
-            .format("%Y-%m-%d")
-            .to_string(),
-    );
-    let expired_medicinal = medicinal::all(&client, &condition, &[]).await?;
+        .and_then(|c| c.expired_days)
+        .unwrap_or(EXPIRED_DAYS);
+    let filter = Filter::eq("is_del", false)
+        .and(Filter::le("notify_at", now))
+        .and(Filter::gt("validity", today))
+        .and(Filter::le(
+            "validity",
+            today + chrono::Duration::days(expiring_days),
+        ));
+    let expiring = medicinal::all(client, filter).await?;
+
+    let low_stock_threshold = state.notify_cfg.low_stock;
+    let mut low_stock = vec![];
+    if let Some(threshold) = low_stock_threshold {
+        let filter = Filter::eq("is_del", false)
+            .and(Filter::le("notify_at", now))
+            .and(Filter::gt(
+                "validity",
+                today + chrono::Duration::days(expiring_days),
+            ))
+            .and(Filter::sql(LOW_STOCK, vec![param(threshold as i64)]));
+        low_stock = medicinal::all(client, filter).await?;
+    }
+    Ok(Due {
+        expired,
+        expiring,
+        expiring_days,
+        low_stock,
+        low_stock_threshold,
+    })
+}
+
+// 利用tokio后台启动一个定期任务
+// 定期扫描medicinal表之中没有被删除,并且已经过期、即将过期或者库存不足的药品,并且发送到所有的通知渠道
+// 定期扫描,每天发送短信,每天早上9:30做一次
+/// 检查需要提醒的药品写入发件箱, 然后发送发件箱中到期的消息, 返回发送成功的条数
+pub async fn do_work(state: Arc<AppState>, notifier: Arc<Notifier>) -> Result<usize> {
+    let mut client = get_client(&state, "定时任务:通知模块").await?;
+    let due = due(&state, &client).await?;
     let mut ids = Vec::new();
-    for item in &expired_medicinal {
-        warn!("药品即将({}天)过期,发送短信通知: {}", expired_days, item);
-        send(
-            item,
-            notify_state.clone(),
-            &format!("即将过期({}天)药品", expired_days),
-        )
-        .await;
-        ids.push(item.id.to_string());
+    for event in due.into_events(&state.notify_cfg) {
+        warn!("{},发送通知: {}", event.title(), event.summary());
+        // 写入发件箱之后由发件箱负责发送和重试, 写入失败时不更新通知时间, 下次再试
+        match outbox::enqueue(&mut client, &notifier, &event).await {
+            Ok(_) => ids.extend(event.ids()),
+            Err(err) => error!("通知写入发件箱失败: {}", err),
+        }
     }
 
     if ids.len() > 0 {
@@ -85,91 +138,76 @@ pub async fn do_work(state: Arc<AppState>, notify_state: Arc<NotifySms>) -> Resu
         }
     }
 
-    Ok(())
+    outbox::dispatch(&state, &notifier).await
 }
 
-async fn update(client: &Client, ids: Vec<String>) -> Result<bool> {
+async fn update(client: &Client, ids: Vec<i32>) -> Result<u64> {
     // 更新通知时间
     // 24小时后的时间
     let after24hours = chrono::Local::now()
         .checked_add_signed(chrono::Duration::hours(24))
         .unwrap_or(chrono::Local::now());
-    // 打印时区
-    let condition = format!("id in ({})", ids.join(","));
-    let update_data = format!("notify_at='{}'", after24hours.to_rfc3339());
-    debug!("condition: {}, update_data: {}", condition, update_data);
-    medicinal::update_notify_at(&client, &condition, &update_data).await
+    debug!("update notify_at: {:?}, {}", ids, after24hours);
+    medicinal::update_notify_at(&client, &ids, after24hours).await
 }
 
-pub async fn sms_schedule(state: Arc<AppState>, notify_state: Arc<NotifySms>) {
-    // 从现在开始, 每一个小时做一次任务
-    // https://rust-book.junmajinlong.com/ch100/03_use_tokio_time.html
-    if *&state.sms_cfg.as_ref().is_none()
-        || !*&state
-            .sms_cfg
-            .as_ref()
-            .unwrap()
-            .send_sms_toggle
-            .unwrap_or(false)
-    {
-        warn!("短信配置未配置,不启动定时任务");
+/// 按 `SCHEDULE` 的计划定时检查, 把需要提醒的药品发送到所有渠道
+pub async fn sms_schedule(state: Arc<AppState>, notifier: Arc<Notifier>) {
+    if notifier.is_empty() {
+        warn!("没有配置通知渠道,不启动定时任务");
         return;
     }
-    let start = Instant::now();
-    let interval = Duration::from_secs(
-        *&state
-            .sms_cfg
-            .as_ref()
-            .unwrap()
-            .check_interval
-            .unwrap_or(INTERVAL_SECONDS),
-    );
-    debug!("sms_schedule interval: {}s", &interval.as_secs());
-    // let mut intv = time::interval_at(start, interval);
-    let mut intv = time::interval_at(start, Duration::from_secs(INTERVAL_SCHEDULE_SECONDS));
+    info!("定时检查通知: {}", state.plan);
     loop {
-        // 如果时间在[8-18]点这个区间，则不进行报警，以免影响休息
-        if chrono::Local::now().hour() >= 18 || chrono::Local::now().hour() < 8 {
-            debug!(
-                "now:{} is holiday time, so do sleep",
-                chrono::Local::now().hour()
-            );
-        } else {
-            let _ = do_work(state.clone(), notify_state.clone()).await;
+        let next = state.plan.next();
+        if let Ok(mut next_run) = state.next_run.write() {
+            *next_run = next;
+        }
+        let next = match next {
+            Some(next) => next,
+            None => {
+                warn!("按 SCHEDULE 的配置找不到下一次检查的时间, 停止定时任务");
+                return;
+            }
+        };
+        debug!("下一次检查通知: {}", next);
+        let wait = next.with_timezone(&chrono::Utc) - chrono::Utc::now();
+        time::sleep(wait.to_std().unwrap_or_default()).await;
+        if let Err(err) = do_work(state.clone(), notifier.clone()).await {
+            error!("定时检查通知失败: {}", err);
         }
-        intv.tick().await;
     }
 }
 
-async fn send(item: &MedicinalList, notify_state: Arc<NotifySms>, msg: &str) {
-    // 构建发送短信的内容
-    let p = aliyun_sdk::SmsParam {
-        name: format!(
-            "{}:{}-{}-{}",
-            msg, &item.name, item.batch_number, item.category
-        ),
-        code: format!("有效期:{}", item.validity.format("%Y-%m-%d")),
-    };
-
-    let sms = aliyun_sdk::SmsRequest {
-        phones: notify_state.phones.clone(),
-        sign_name: notify_state.sign_name.clone(),
-        template_code: notify_state.template_code.clone(),
-        out_id: Some("123".to_string()),
-        param: p,
-    };
-
-    notify_state
-        .tx
-        .clone()
-        .send(sms)
-        .map_err(|e| {
-            error!("发送短信失败: {}", e);
-        })
-        .ok();
-}
-
+#[cfg(test)]
 mod tests {
+    #[test]
+    fn test_into_events() {
+        use super::Due;
+        use crate::config::NotifyConfig;
+        use crate::notify::tests::item;
+
+        let due = || Due {
+            expired: vec![item()],
+            expiring: vec![item(), item()],
+            expiring_days: 30,
+            low_stock: vec![item()],
+            low_stock_threshold: None,
+        };
+        let mut cfg = NotifyConfig::default();
+        let events = due().into_events(&cfg);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].summary(), "已过期 1, 30天内过期 2");
+
+        cfg.critical = vec!["expired".to_string()];
+        let events = due().into_events(&cfg);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind(), "expired");
+        assert_eq!(events[1].kind(), "digest");
+
+        cfg.mode = "item".to_string();
+        assert_eq!(due().into_events(&cfg).len(), 3);
+    }
 
     #[test]
     fn test_time_format_with_zone() {
@@ -0,0 +1,125 @@
+//! API token, 供脚本等非浏览器的客户端通过 `Authorization: Bearer <token>` 访问
+//!
+//! token 只在创建的时候明文展示一次, 数据库中只保存它的 sha256 摘要
+
+use crate::db::api_token;
+use crate::handler::helper::get_client;
+use crate::model::{AppState, TokenIdentity};
+use crate::Result;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// token 的前缀, 方便在日志或者代码仓库中识别泄露的 token
+const TOKEN_PREFIX: &str = "stg_";
+/// token 随机部分的长度
+const TOKEN_LEN: usize = 40;
+
+/// token 的权限范围
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    /// 只读, 只允许 GET/HEAD 请求
+    Read,
+    /// 可读写
+    Write,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Write => "write",
+        }
+    }
+
+    /// 判断该权限范围是否允许指定的 http 方法
+    pub fn allows(&self, method: &axum::http::Method) -> bool {
+        match self {
+            TokenScope::Read => {
+                method == axum::http::Method::GET || method == axum::http::Method::HEAD
+            }
+            TokenScope::Write => true,
+        }
+    }
+}
+
+impl From<&str> for TokenScope {
+    fn from(s: &str) -> Self {
+        match s {
+            "write" => TokenScope::Write,
+            _ => TokenScope::Read,
+        }
+    }
+}
+
+/// 生成新的明文 token
+pub fn generate() -> String {
+    let s: String = thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(TOKEN_LEN)
+        .map(char::from)
+        .collect();
+    format!("{}{}", TOKEN_PREFIX, s)
+}
+
+/// 计算 token 的摘要, 用于存储和查找
+pub fn hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// 从 `Authorization` 头中取出 bearer token
+pub fn from_authorization(value: &str) -> Option<&str> {
+    let value = value.trim();
+    let (scheme, token) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+    let token = token.trim();
+    if token.is_empty() {
+        None
+    } else {
+        Some(token)
+    }
+}
+
+/// 校验明文 token, 成功则记录最后使用时间并返回 token 对应的身份
+pub async fn authenticate(state: &AppState, token: &str) -> Result<TokenIdentity> {
+    let client = get_client(state, "token_authenticate").await?;
+    let identity = api_token::find_valid(&client, &hash(token)).await?;
+    api_token::touch(&client, identity.id).await?;
+    Ok(identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Method;
+
+    #[test]
+    fn generated_token_should_have_prefix_and_stable_hash() {
+        let token = generate();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + TOKEN_LEN);
+        assert_eq!(hash(&token), hash(&token));
+        assert_eq!(hash(&token).len(), 64);
+        assert_ne!(hash(&token), hash(&generate()));
+    }
+
+    #[test]
+    fn bearer_should_be_parsed() {
+        assert_eq!(from_authorization("Bearer stg_abc"), Some("stg_abc"));
+        assert_eq!(from_authorization("bearer  stg_abc "), Some("stg_abc"));
+        assert_eq!(from_authorization("Basic dXNlcjpwd2Q="), None);
+        assert_eq!(from_authorization("Bearer "), None);
+    }
+
+    #[test]
+    fn read_scope_should_only_allow_safe_methods() {
+        assert!(TokenScope::Read.allows(&Method::GET));
+        assert!(!TokenScope::Read.allows(&Method::POST));
+        assert!(!TokenScope::Read.allows(&Method::DELETE));
+        assert!(TokenScope::Write.allows(&Method::PUT));
+    }
+}